@@ -29,6 +29,10 @@ namespace Blindodon.Services;
 public class MastodonBridge : IDisposable
 {
     private const string PipeName = "blindodon_ipc";
+    private static readonly string SecretFilePath = Path.Combine(
+        Environment.GetFolderPath(Environment.SpecialFolder.LocalApplicationData),
+        "Blindodon",
+        "ipc_session.secret");
     private NamedPipeClientStream? _pipe;
     private StreamReader? _reader;
     private StreamWriter? _writer;
@@ -77,6 +81,16 @@ public class MastodonBridge : IDisposable
 
             Log.Information("Connected to Rust core");
 
+            // The core only accepts connections that present its session secret first
+            var secret = (await File.ReadAllTextAsync(SecretFilePath)).Trim();
+            var authResult = await SendRequestAsync("system.authenticate", new { secret });
+            if (authResult == null)
+            {
+                Log.Error("Rust core rejected the session secret");
+                Disconnect();
+                return false;
+            }
+
             // Send a ping to verify connection
             var pingResult = await SendRequestAsync("ping", null);
             Log.Debug("Ping response: {Response}", pingResult);
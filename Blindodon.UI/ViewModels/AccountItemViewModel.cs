@@ -45,6 +45,24 @@ public partial class AccountItemViewModel : ObservableObject
     [ObservableProperty]
     private DateTime _lastUsedAt;
 
+    [ObservableProperty]
+    private string _software = "mastodon";
+
+    [ObservableProperty]
+    private bool _supportsScheduledPosts;
+
+    [ObservableProperty]
+    private bool _supportsQuotes;
+
+    [ObservableProperty]
+    private bool _supportsReactions;
+
+    [ObservableProperty]
+    private bool _supportsMarkdown;
+
+    [ObservableProperty]
+    private bool _needsReauth;
+
     /// <summary>
     /// Gets the display name if set, otherwise falls back to username.
     /// </summary>
@@ -96,13 +114,15 @@ public partial class AccountItemViewModel : ObservableObject
     /// Gets the full accessibility label for screen readers.
     /// </summary>
     public string AccessibilityLabel =>
-        $"{EffectiveDisplayName} at {InstanceDomain}" + (IsDefault ? ", default account" : "");
+        $"{EffectiveDisplayName} at {InstanceDomain}" + (IsDefault ? ", default account" : "")
+        + (NeedsReauth ? ", needs to log in again" : "");
 
     /// <summary>
     /// Creates an AccountItemViewModel from a JSON object received from the backend.
     /// </summary>
     public static AccountItemViewModel FromJson(JObject json)
     {
+        var capabilities = json["capabilities"] as JObject;
         return new AccountItemViewModel
         {
             Id = json["id"]?.Value<string>() ?? "",
@@ -111,7 +131,13 @@ public partial class AccountItemViewModel : ObservableObject
             DisplayName = json["display_name"]?.Value<string>() ?? "",
             AvatarUrl = json["avatar_url"]?.Value<string>() ?? "",
             IsDefault = json["is_default"]?.Value<bool>() ?? false,
-            LastUsedAt = json["last_used_at"]?.Value<DateTime>() ?? DateTime.MinValue
+            LastUsedAt = json["last_used_at"]?.Value<DateTime>() ?? DateTime.MinValue,
+            Software = json["software"]?.Value<string>() ?? "mastodon",
+            SupportsScheduledPosts = capabilities?["scheduled_posts"]?.Value<bool>() ?? true,
+            SupportsQuotes = capabilities?["quotes"]?.Value<bool>() ?? false,
+            SupportsReactions = capabilities?["reactions"]?.Value<bool>() ?? false,
+            SupportsMarkdown = capabilities?["markdown"]?.Value<bool>() ?? false,
+            NeedsReauth = json["needs_reauth"]?.Value<bool>() ?? false
         };
     }
 }
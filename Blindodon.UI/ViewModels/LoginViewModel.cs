@@ -15,6 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 using System.Diagnostics;
+using System.Windows;
 using CommunityToolkit.Mvvm.ComponentModel;
 using CommunityToolkit.Mvvm.Input;
 using Newtonsoft.Json.Linq;
@@ -33,7 +34,8 @@ public enum LoginState
     EnterInstance,
 
     /// <summary>
-    /// OAuth started, waiting for user to enter the authorization code.
+    /// OAuth started, waiting for the browser to return or for the user to
+    /// enter the authorization code.
     /// </summary>
     WaitingForCode,
 
@@ -63,6 +65,11 @@ public partial class LoginViewModel : ObservableObject
     [ObservableProperty]
     private bool _isLoading;
 
+    /// <summary>
+    /// State returned by auth.start, sent back with the authorization code.
+    /// </summary>
+    private string _authState = "";
+
     /// <summary>
     /// Event raised when login is successful.
     /// </summary>
@@ -83,6 +90,19 @@ public partial class LoginViewModel : ObservableObject
     /// </summary>
     public bool CanSubmitCode => !string.IsNullOrWhiteSpace(AuthorizationCode) && !IsLoading;
 
+    public LoginViewModel()
+    {
+        App.Bridge.EventReceived += Bridge_EventReceived;
+    }
+
+    /// <summary>
+    /// Stops listening for core events once the login window is closed.
+    /// </summary>
+    public void Detach()
+    {
+        App.Bridge.EventReceived -= Bridge_EventReceived;
+    }
+
     partial void OnInstanceUrlChanged(string value)
     {
         OnPropertyChanged(nameof(CanStartAuth));
@@ -127,7 +147,8 @@ public partial class LoginViewModel : ObservableObject
         {
             var result = await App.Bridge.SendRequestAsync("auth.start", new
             {
-                instance_url = InstanceUrl
+                instance_url = InstanceUrl,
+                loopback = true
             });
 
             if (result != null)
@@ -135,8 +156,10 @@ public partial class LoginViewModel : ObservableObject
                 var authUrl = result["auth_url"]?.Value<string>();
                 if (!string.IsNullOrEmpty(authUrl))
                 {
+                    _authState = result["state"]?.Value<string>() ?? "";
+
                     StatusMessage = "Opening browser for authorization...";
-                    App.Accessibility.Announce("Opening your browser. After authorizing, copy the code and paste it here.");
+                    App.Accessibility.Announce("Opening your browser. After authorizing, Blindodon will continue automatically.");
 
                     // Open the auth URL in the default browser
                     Process.Start(new ProcessStartInfo
@@ -147,7 +170,7 @@ public partial class LoginViewModel : ObservableObject
 
                     // Move to the code entry state
                     CurrentState = LoginState.WaitingForCode;
-                    StatusMessage = "Paste the authorization code from your browser";
+                    StatusMessage = "Waiting for authorization in your browser";
                 }
                 else
                 {
@@ -195,44 +218,17 @@ public partial class LoginViewModel : ObservableObject
             var result = await App.Bridge.SendRequestAsync("auth.callback", new
             {
                 instance_url = InstanceUrl,
-                code = AuthorizationCode.Trim()
+                code = AuthorizationCode.Trim(),
+                state = _authState
             });
 
             if (result != null && result["success"]?.Value<bool>() == true)
             {
-                StatusMessage = "Login successful!";
-                App.Audio.Play(Services.AudioManager.SoundEvent.Connected);
-                App.Accessibility.Announce("Login successful!");
-
-                // Create account view model from the response
-                var accountJson = result["account"]?.ToObject<Newtonsoft.Json.Linq.JObject>();
-                if (accountJson != null)
-                {
-                    var account = AccountItemViewModel.FromJson(accountJson);
-                    Log.Information("Login successful for {Username} at {Instance}",
-                        account.Username, account.InstanceDomain);
-                    LoginComplete?.Invoke(this, account);
-                }
-                else
-                {
-                    // Create a minimal account if we don't get full details
-                    var account = new AccountItemViewModel
-                    {
-                        InstanceUrl = InstanceUrl,
-                        Username = "user"
-                    };
-                    LoginComplete?.Invoke(this, account);
-                }
+                CompleteLogin(result["account"]?.ToObject<JObject>());
             }
             else
             {
-                var error = result?["error"]?.Value<string>() ?? "Authentication failed";
-                StatusMessage = error;
-                App.Audio.Play(Services.AudioManager.SoundEvent.Error);
-                App.Accessibility.Announce(error);
-
-                // Go back to code entry state to let user try again
-                CurrentState = LoginState.WaitingForCode;
+                FailLogin(result?["error"]?.Value<string>() ?? "Authentication failed");
             }
         }
         catch (Exception ex)
@@ -248,6 +244,71 @@ public partial class LoginViewModel : ObservableObject
         }
     }
 
+    /// <summary>
+    /// Finishes a login started with a loopback redirect, which the core
+    /// completes on its own once the browser returns.
+    /// </summary>
+    private void Bridge_EventReceived(object? sender, Services.IpcEvent e)
+    {
+        if (e.EventType != "event.auth_completed" || e.Data == null)
+            return;
+
+        Application.Current.Dispatcher.Invoke(() =>
+        {
+            if (CurrentState != LoginState.WaitingForCode || e.Data["state"]?.Value<string>() != _authState)
+                return;
+
+            if (e.Data["success"]?.Value<bool>() == true)
+            {
+                CompleteLogin(e.Data["account"]?.ToObject<JObject>());
+            }
+            else
+            {
+                FailLogin(e.Data["error"]?.Value<string>() ?? "Authentication failed");
+            }
+        });
+    }
+
+    /// <summary>
+    /// Reports a successful login with the account returned by the core.
+    /// </summary>
+    private void CompleteLogin(JObject? accountJson)
+    {
+        StatusMessage = "Login successful!";
+        App.Audio.Play(Services.AudioManager.SoundEvent.Connected);
+        App.Accessibility.Announce("Login successful!");
+
+        if (accountJson != null)
+        {
+            var account = AccountItemViewModel.FromJson(accountJson);
+            Log.Information("Login successful for {Username} at {Instance}",
+                account.Username, account.InstanceDomain);
+            LoginComplete?.Invoke(this, account);
+        }
+        else
+        {
+            // Create a minimal account if we don't get full details
+            var account = new AccountItemViewModel
+            {
+                InstanceUrl = InstanceUrl,
+                Username = "user"
+            };
+            LoginComplete?.Invoke(this, account);
+        }
+    }
+
+    /// <summary>
+    /// Reports a failed login and goes back to code entry so the user can retry.
+    /// </summary>
+    private void FailLogin(string error)
+    {
+        StatusMessage = error;
+        App.Audio.Play(Services.AudioManager.SoundEvent.Error);
+        App.Accessibility.Announce(error);
+
+        CurrentState = LoginState.WaitingForCode;
+    }
+
     /// <summary>
     /// Cancels the login flow and closes the window.
     /// </summary>
@@ -296,6 +296,25 @@ public partial class MainViewModel : ObservableObject
         Log.Information("Reply window closed for post {PostId}", post.Id);
     }
 
+    [RelayCommand]
+    private async Task FillGap(PostViewModel? post)
+    {
+        var vm = CurrentTimelineViewModel;
+        if (post == null || vm == null || !post.HasGapBelow) return;
+
+        try
+        {
+            var loaded = await vm.FillGapAsync(post);
+            var stillMissing = post.HasGapBelow ? ", more are still missing" : "";
+            App.Accessibility.Announce(loaded == 1 ? $"Loaded 1 missing post{stillMissing}" : $"Loaded {loaded} missing posts{stillMissing}");
+        }
+        catch (Exception ex)
+        {
+            Log.Error(ex, "Failed to load missing posts");
+            App.Audio.Play(Services.AudioManager.SoundEvent.Error);
+        }
+    }
+
     [RelayCommand]
     private async Task Boost(PostViewModel? post)
     {
@@ -381,6 +400,14 @@ public partial class MainViewModel : ObservableObject
                 case "event.post_deleted":
                     HandlePostDeleted(e.Data);
                     break;
+
+                case "event.post_action":
+                    HandlePostAction(e.Data);
+                    break;
+
+                case "event.auth_expired":
+                    HandleAuthExpired(e.Data);
+                    break;
             }
         });
     }
@@ -433,6 +460,14 @@ public partial class MainViewModel : ObservableObject
         App.Accessibility.AnnounceNotification(notificationType, from);
     }
 
+    private void HandleAuthExpired(JObject? data)
+    {
+        var accountId = data?["account_id"]?.Value<string>() ?? "An account";
+
+        StatusMessage = $"{accountId} needs to log in again";
+        App.Accessibility.Announce($"The server ended the session of {accountId}. Log in again from the account list.");
+    }
+
     private void HandlePostDeleted(JObject? data)
     {
         if (data == null) return;
@@ -455,6 +490,24 @@ public partial class MainViewModel : ObservableObject
         }
     }
 
+    private void HandlePostAction(JObject? data)
+    {
+        if (data == null) return;
+
+        // Another client (or this one) boosted or favourited a post
+        var postData = data["post"]?.ToObject<JObject>();
+        if (postData == null) return;
+
+        var updated = PostViewModel.FromJson(postData);
+        foreach (var post in Posts.Where(p => p.Id == updated.Id))
+        {
+            post.Reblogged = updated.Reblogged;
+            post.Favourited = updated.Favourited;
+            post.ReblogsCount = updated.ReblogsCount;
+            post.FavouritesCount = updated.FavouritesCount;
+        }
+    }
+
     private void Bridge_ConnectionStateChanged(object? sender, bool connected)
     {
         Application.Current.Dispatcher.Invoke(() =>
@@ -87,6 +87,12 @@ public partial class PostViewModel : ObservableObject
     [ObservableProperty]
     private List<MediaAttachmentViewModel> _mediaAttachments = new();
 
+    /// <summary>
+    /// ID of the post on the other side of a gap right below this one
+    /// </summary>
+    [ObservableProperty]
+    private string? _gapOlderId;
+
     /// <summary>
     /// Gets whether this post has a content warning
     /// </summary>
@@ -107,6 +113,16 @@ public partial class PostViewModel : ObservableObject
     /// </summary>
     public PostViewModel EffectivePost => Reblog ?? this;
 
+    /// <summary>
+    /// Gets whether posts may be missing right below this one
+    /// </summary>
+    public bool HasGapBelow => GapOlderId != null;
+
+    partial void OnGapOlderIdChanged(string? value)
+    {
+        OnPropertyChanged(nameof(HasGapBelow));
+    }
+
     /// <summary>
     /// Create a PostViewModel from JSON
     /// </summary>
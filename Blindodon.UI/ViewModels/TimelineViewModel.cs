@@ -86,6 +86,7 @@ public partial class TimelineViewModel : ObservableObject
                     }
 
                     UpdatePaginationInfo();
+                    ApplyGaps(result["gaps"]);
 
                     if (Posts.Count > 0)
                     {
@@ -138,6 +139,7 @@ public partial class TimelineViewModel : ObservableObject
                     }
 
                     UpdatePaginationInfo();
+                    ApplyGaps(result["gaps"]);
                 }
 
                 HasMore = result["has_more"]?.Value<bool>() ?? false;
@@ -183,6 +185,7 @@ public partial class TimelineViewModel : ObservableObject
                     }
 
                     UpdatePaginationInfo();
+                    ApplyGaps(result["gaps"]);
                 }
             }
         }
@@ -196,6 +199,52 @@ public partial class TimelineViewModel : ObservableObject
         }
     }
 
+    /// <summary>
+    /// Load the posts missing right below a post, oldest first
+    /// </summary>
+    /// <returns>The number of posts loaded</returns>
+    public async Task<int> FillGapAsync(PostViewModel post, int limit = 20)
+    {
+        if (IsLoading || post.GapOlderId == null) return 0;
+
+        IsLoading = true;
+
+        try
+        {
+            var result = await App.Bridge.SendRequestAsync("timeline.fill_gap", new
+            {
+                timeline_type = Type,
+                newer_id = post.Id,
+                older_id = post.GapOlderId,
+                limit = limit
+            });
+
+            if (result == null) return 0;
+
+            // Whatever is still missing comes back as a smaller gap
+            post.GapOlderId = null;
+
+            var posts = result["posts"]?.ToObject<List<JObject>>() ?? new List<JObject>();
+            var index = Posts.IndexOf(post) + 1;
+            var loaded = 0;
+            foreach (var json in posts)
+            {
+                var loadedPost = PostViewModel.FromJson(json);
+                if (Posts.Any(p => p.Id == loadedPost.Id)) continue;
+
+                Posts.Insert(index++, loadedPost);
+                loaded++;
+            }
+
+            ApplyGaps(result["gaps"]);
+            return loaded;
+        }
+        finally
+        {
+            IsLoading = false;
+        }
+    }
+
     /// <summary>
     /// Insert a new post at the beginning (from streaming)
     /// </summary>
@@ -219,6 +268,21 @@ public partial class TimelineViewModel : ObservableObject
         }
     }
 
+    private void ApplyGaps(JToken? gaps)
+    {
+        if (gaps is not JArray gapArray) return;
+
+        foreach (var gap in gapArray)
+        {
+            var newerId = gap["newer_id"]?.Value<string>();
+            var post = Posts.FirstOrDefault(p => p.Id == newerId);
+            if (post != null)
+            {
+                post.GapOlderId = gap["older_id"]?.Value<string>();
+            }
+        }
+    }
+
     private void UpdatePaginationInfo()
     {
         if (Posts.Count > 0)
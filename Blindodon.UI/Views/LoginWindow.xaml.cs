@@ -121,6 +121,7 @@ public partial class LoginWindow : Window
         _viewModel.LoginComplete -= ViewModel_LoginComplete;
         _viewModel.RequestClose -= ViewModel_RequestClose;
         _viewModel.PropertyChanged -= ViewModel_PropertyChanged;
+        _viewModel.Detach();
         base.OnClosed(e);
     }
 }
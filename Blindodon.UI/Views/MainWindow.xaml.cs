@@ -115,6 +115,11 @@ public partial class MainWindow : Window
                 e.Handled = true;
                 break;
 
+            case Key.G:
+                _viewModel.FillGapCommand.Execute(_viewModel.SelectedPost);
+                e.Handled = true;
+                break;
+
             case Key.Enter:
                 // View full post/thread
                 _viewModel.ViewThreadCommand.Execute(_viewModel.SelectedPost);
@@ -141,6 +146,11 @@ public partial class MainWindow : Window
 
             announcement += $". {post.ReblogsCount} boosts, {post.FavouritesCount} favorites.";
 
+            if (post.HasGapBelow)
+            {
+                announcement += " Posts may be missing below; press G to load them.";
+            }
+
             App.Accessibility.Announce(announcement);
         }
     }
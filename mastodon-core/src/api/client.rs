@@ -24,68 +24,82 @@ use megalodon::{
     megalodon::GetLocalTimelineInputOptions,
     megalodon::GetPublicTimelineInputOptions,
     megalodon::PostStatusInputOptions,
-    response::Response,
     Megalodon,
-    SNS,
 };
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
+use tracing::{info, warn};
 
 use crate::models::{
     AuthResponse, InstanceInfo, MediaAttachment, MediaUploadRequest, NewPost, Notification,
-    NotificationRequest, NotificationResponse, Post, TimelineRequest, TimelineResponse,
-    TimelineType, User, Visibility,
+    NotificationRequest, OAuthApp, NotificationResponse, Post, ServerSoftware, TimelineRequest,
+    TimelineResponse, TimelineType, User, Visibility,
 };
 
 use super::converter;
+use super::oauth::{self, PendingLogin};
 
 /// Application name for OAuth
 const APP_NAME: &str = "Blindodon";
 /// Scopes required for the application
 const SCOPES: &[&str] = &["read", "write", "follow", "push"];
-/// Redirect URI for OAuth callback
-const REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+/// Redirect URI for the out-of-band OAuth flow, where the user copies the code
+pub const OOB_REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
 
-/// Stored OAuth application details
-struct OAuthAppData {
-    client_id: String,
-    client_secret: String,
-    instance_url: String,
+/// Token endpoint response of a PKCE exchange; only the token itself is needed
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    access_token: String,
 }
 
-/// Thread-safe storage for pending OAuth apps
-static PENDING_OAUTH: RwLock<Option<OAuthAppData>> = RwLock::const_new(None);
-
 /// Mastodon API client
 pub struct MastodonClient {
     client: Arc<Box<dyn Megalodon + Send + Sync>>,
     instance_url: String,
     access_token: String,
+    software: ServerSoftware,
 }
 
 impl MastodonClient {
-    /// Start the OAuth authentication flow
-    pub async fn start_auth(instance_url: &str) -> Result<AuthResponse> {
-        info!("Starting OAuth flow for {}", instance_url);
+    /// Detect the software an instance runs from its nodeinfo
+    ///
+    /// Falls back to Mastodon, whose API most servers implement, if the
+    /// instance cannot be identified.
+    pub async fn detect_software(instance_url: &str) -> ServerSoftware {
+        let instance_url = normalize_url(instance_url);
+
+        match megalodon::detector(&instance_url).await {
+            Ok(sns) => {
+                let software = converter::convert_sns(&sns);
+                info!("{} runs {}", instance_url, software.as_str());
+                software
+            }
+            Err(e) => {
+                warn!("Could not detect the software of {}, assuming Mastodon: {}", instance_url, e);
+                ServerSoftware::Mastodon
+            }
+        }
+    }
+
+    /// Register the application on an instance running the given software
+    pub async fn register_app(instance_url: &str, redirect_uri: &str, software: ServerSoftware) -> Result<OAuthApp> {
+        info!("Registering application on {}", instance_url);
 
         // Normalize the instance URL
         let instance_url = normalize_url(instance_url);
 
         // Create a client to register the app
         let client = generator(
-            SNS::Mastodon,
+            converter::sns_for(software),
             instance_url.clone(),
             None,
             None,
         )?;
 
-        // Register the application
         let app_data = client
             .register_app(
                 APP_NAME.to_string(),
                 &megalodon::megalodon::AppInputOptions {
-                    redirect_uris: Some(REDIRECT_URI.to_string()),
+                    redirect_uris: Some(redirect_uri.to_string()),
                     scopes: Some(SCOPES.iter().map(|s| s.to_string()).collect()),
                     website: Some("https://github.com/blindodon/blindodon".to_string()),
                 },
@@ -93,93 +107,116 @@ impl MastodonClient {
             .await
             .context("Failed to register application")?;
 
-        let client_id = app_data.client_id.clone();
-        let client_secret = app_data.client_secret.clone();
+        Ok(OAuthApp {
+            client_id: app_data.client_id,
+            client_secret: app_data.client_secret,
+            redirect_uri: redirect_uri.to_string(),
+            instance_url,
+            software,
+        })
+    }
 
-        // Store the app data for the callback
-        *PENDING_OAUTH.write().await = Some(OAuthAppData {
-            client_id: client_id.clone(),
-            client_secret: client_secret.clone(),
-            instance_url: instance_url.clone(),
-        });
+    /// Start the OAuth authentication flow with a registered app
+    ///
+    /// `account_id` names the stored account being logged in again, if any.
+    pub fn start_auth(app: &OAuthApp, account_id: Option<&str>) -> Result<AuthResponse> {
+        info!("Starting OAuth flow for {}", app.instance_url);
+
+        let state = oauth::random_token()?;
+        // Only software known to support PKCE gets a challenge; the others
+        // exchange the code through megalodon, which sends no verifier
+        let code_verifier = app.software.supports_pkce().then(oauth::random_token).transpose()?;
 
         // Generate the authorization URL
-        let auth_url = format!(
-            "{}/oauth/authorize?client_id={}&redirect_uri={}&response_type=code&scope={}",
-            instance_url,
-            client_id,
-            urlencoding::encode(REDIRECT_URI),
-            SCOPES.join("+")
+        let mut auth_url = format!(
+            "{}/oauth/authorize?client_id={}&redirect_uri={}&response_type=code&scope={}&state={}",
+            app.instance_url,
+            urlencoding::encode(&app.client_id),
+            urlencoding::encode(&app.redirect_uri),
+            SCOPES.join("+"),
+            state
+        );
+        if let Some(code_verifier) = &code_verifier {
+            auth_url.push_str(&format!(
+                "&code_challenge={}&code_challenge_method=S256",
+                oauth::code_challenge(code_verifier)
+            ));
+        }
+
+        // Store the app data for the callback
+        oauth::insert(
+            state.clone(),
+            PendingLogin::new(app.clone(), code_verifier, account_id.map(str::to_string)),
         );
 
         info!("Authorization URL generated");
 
-        Ok(AuthResponse {
-            auth_url,
-            state: uuid::Uuid::new_v4().to_string(),
-        })
+        Ok(AuthResponse { auth_url, state })
     }
 
     /// Complete the OAuth authentication flow
-    pub async fn complete_auth(instance_url: &str, code: &str) -> Result<Self> {
+    ///
+    /// `state` must be the one returned by `start_auth` for this instance.
+    /// The pending login is kept if the exchange fails, so a mistyped code
+    /// can be retried until the login expires. Returns the client together
+    /// with the login it completed.
+    pub async fn complete_auth(instance_url: &str, code: &str, state: &str) -> Result<(Self, PendingLogin)> {
         info!("Completing OAuth flow");
 
         let instance_url = normalize_url(instance_url);
 
         // Get the stored app data
-        let app_data = PENDING_OAUTH.read().await;
-        let app_data = app_data
-            .as_ref()
-            .context("No pending OAuth flow")?;
+        let login = oauth::get(state).context("Unknown or expired login; please start again")?;
 
-        if app_data.instance_url != instance_url {
+        if login.app.instance_url != instance_url {
             anyhow::bail!("Instance URL mismatch");
         }
 
-        // Create a client to exchange the code
-        let client = generator(
-            SNS::Mastodon,
-            instance_url.clone(),
-            None,
-            None,
-        )?;
-
-        // Exchange the code for a token
-        let token_data = client
-            .fetch_access_token(
-                app_data.client_id.clone(),
-                app_data.client_secret.clone(),
-                code.to_string(),
-                REDIRECT_URI.to_string(),
-            )
-            .await
-            .context("Failed to fetch access token")?;
-
-        let access_token = token_data.access_token.clone();
+        // Exchange the code for a token, proving we started this login
+        // where the server supports PKCE
+        let access_token = match &login.code_verifier {
+            Some(code_verifier) => exchange_code(&login.app, code, code_verifier).await,
+            None => fetch_access_token(&login.app, code).await,
+        }
+        .context("Failed to fetch access token")?;
+        oauth::remove(state);
 
         info!("Access token obtained successfully");
 
         // Create the authenticated client
         let auth_client = generator(
-            SNS::Mastodon,
+            converter::sns_for(login.app.software),
             instance_url.clone(),
             Some(access_token.clone()),
             None,
         )?;
 
-        Ok(Self {
+        let client = Self {
             client: Arc::new(auth_client),
             instance_url,
             access_token,
-        })
+            software: login.app.software,
+        };
+        Ok((client, login))
+    }
+
+    /// Revoke this client's access token on the instance
+    pub async fn revoke_token(&self, app: &OAuthApp) -> Result<()> {
+        self.client
+            .revoke_access_token(app.client_id.clone(), app.client_secret.clone(), self.access_token.clone())
+            .await
+            .context("Failed to revoke access token")?;
+
+        info!("Access token revoked on {}", self.instance_url);
+        Ok(())
     }
 
     /// Create a client from an existing access token
-    pub fn from_token(instance_url: &str, access_token: &str) -> Result<Self> {
+    pub fn from_token(instance_url: &str, access_token: &str, software: ServerSoftware) -> Result<Self> {
         let instance_url = normalize_url(instance_url);
 
         let client = generator(
-            SNS::Mastodon,
+            converter::sns_for(software),
             instance_url.clone(),
             Some(access_token.to_string()),
             None,
@@ -189,6 +226,24 @@ impl MastodonClient {
             client: Arc::new(client),
             instance_url,
             access_token: access_token.to_string(),
+            software,
+        })
+    }
+
+    /// Whether an error means the server rejected the access token
+    ///
+    /// Servers answer 401 once a token is revoked or expired, and 403 when
+    /// the account behind it is disabled or suspended. A 403 can also
+    /// refuse a single action, so callers should confirm it with
+    /// `get_current_user` before giving up on the token.
+    pub fn is_token_rejected(error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| {
+            let status = match cause.downcast_ref::<megalodon::error::Error>() {
+                Some(megalodon::error::Error::OwnError(e)) => e.status,
+                Some(megalodon::error::Error::RequestError(e)) => e.status().map(|s| s.as_u16()),
+                _ => cause.downcast_ref::<reqwest::Error>().and_then(|e| e.status()).map(|s| s.as_u16()),
+            };
+            matches!(status, Some(401 | 403))
         })
     }
 
@@ -202,6 +257,11 @@ impl MastodonClient {
         &self.instance_url
     }
 
+    /// Get the software the instance runs
+    pub fn software(&self) -> ServerSoftware {
+        self.software
+    }
+
     /// Get the current authenticated user
     pub async fn get_current_user(&self) -> Result<User> {
         let response = self.client
@@ -214,9 +274,9 @@ impl MastodonClient {
 
     /// Get a timeline
     pub async fn get_timeline(&self, request: &TimelineRequest) -> Result<TimelineResponse> {
-        let limit = request.limit.unwrap_or(20);
+        let limit = request.limit();
 
-        let posts = match &request.timeline_type {
+        let response = match &request.timeline_type {
             TimelineType::Home => {
                 let options = GetHomeTimelineInputOptions {
                     max_id: request.max_id.clone(),
@@ -225,8 +285,7 @@ impl MastodonClient {
                     limit: Some(limit),
                     ..Default::default()
                 };
-                let response = self.client.get_home_timeline(Some(&options)).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_home_timeline(Some(&options)).await?)
             }
             TimelineType::Local => {
                 let options = GetLocalTimelineInputOptions {
@@ -236,8 +295,7 @@ impl MastodonClient {
                     limit: Some(limit),
                     ..Default::default()
                 };
-                let response = self.client.get_local_timeline(Some(&options)).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_local_timeline(Some(&options)).await?)
             }
             TimelineType::Federated => {
                 let options = GetPublicTimelineInputOptions {
@@ -247,13 +305,12 @@ impl MastodonClient {
                     limit: Some(limit),
                     ..Default::default()
                 };
-                let response = self.client.get_public_timeline(Some(&options)).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_public_timeline(Some(&options)).await?)
             }
             TimelineType::Notifications => {
                 // For notifications, we return an empty list for now
                 // This should be handled separately
-                vec![]
+                None
             }
             TimelineType::Hashtag { tag } => {
                 let options = megalodon::megalodon::GetTagTimelineInputOptions {
@@ -263,24 +320,20 @@ impl MastodonClient {
                     limit: Some(limit),
                     ..Default::default()
                 };
-                let response = self.client.get_tag_timeline(tag.clone(), Some(&options)).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_tag_timeline(tag.clone(), Some(&options)).await?)
             }
             TimelineType::User { user_id } => {
                 let options = megalodon::megalodon::GetAccountStatusesInputOptions {
                     limit: Some(limit),
                     ..Default::default()
                 };
-                let response = self.client.get_account_statuses(user_id.clone(), Some(&options)).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_account_statuses(user_id.clone(), Some(&options)).await?)
             }
             TimelineType::Bookmarks => {
-                let response = self.client.get_bookmarks(None).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_bookmarks(None).await?)
             }
             TimelineType::Favourites => {
-                let response = self.client.get_favourites(None).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_favourites(None).await?)
             }
             TimelineType::List { list_id } => {
                 let options = megalodon::megalodon::GetListTimelineInputOptions {
@@ -289,28 +342,36 @@ impl MastodonClient {
                     min_id: request.min_id.clone(),
                     limit: Some(limit),
                 };
-                let response = self.client.get_list_timeline(list_id.clone(), Some(&options)).await?;
-                response.json.into_iter().map(|s| converter::convert_status(&s)).collect()
+                Some(self.client.get_list_timeline(list_id.clone(), Some(&options)).await?)
             }
             TimelineType::Direct => {
                 // Direct messages timeline - skip for now as API changed significantly
-                vec![]
+                None
             }
             _ => {
                 warn!("Unsupported timeline type: {:?}", request.timeline_type);
-                vec![]
+                None
+            }
+        };
+
+        let (posts, has_more): (Vec<Post>, bool) = match response {
+            Some(response) => {
+                let has_more = has_next_page(&response.header, response.json.len(), limit);
+                let posts = response.json.iter().map(converter::convert_status).collect();
+                (posts, has_more)
             }
+            None => (vec![], false),
         };
 
         let max_id = posts.first().map(|p| p.id.clone());
         let min_id = posts.last().map(|p| p.id.clone());
-        let has_more = posts.len() == limit as usize;
 
         Ok(TimelineResponse {
             posts,
             max_id,
             min_id,
             has_more,
+            gaps: vec![],
         })
     }
 
@@ -527,8 +588,54 @@ impl MastodonClient {
     }
 }
 
+/// Exchange an authorization code for a token, with the PKCE code verifier
+///
+/// megalodon cannot send a code verifier, so this talks to the standard
+/// token endpoint directly. Only used for software that supports PKCE.
+async fn exchange_code(app: &OAuthApp, code: &str, code_verifier: &str) -> Result<String> {
+    let scope = SCOPES.join(" ");
+    let response = reqwest::Client::new()
+        .post(format!("{}/oauth/token", app.instance_url))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("client_id", app.client_id.as_str()),
+            ("client_secret", app.client_secret.as_str()),
+            ("redirect_uri", app.redirect_uri.as_str()),
+            ("code_verifier", code_verifier),
+            ("scope", scope.as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let token: TokenResponse = response.json().await?;
+    Ok(token.access_token)
+}
+
+/// Exchange an authorization code for a token the way the app's software expects
+async fn fetch_access_token(app: &OAuthApp, code: &str) -> Result<String> {
+    let client = generator(
+        converter::sns_for(app.software),
+        app.instance_url.clone(),
+        None,
+        None,
+    )?;
+
+    let token_data = client
+        .fetch_access_token(
+            app.client_id.clone(),
+            app.client_secret.clone(),
+            code.to_string(),
+            app.redirect_uri.clone(),
+        )
+        .await?;
+
+    Ok(token_data.access_token)
+}
+
 /// Normalize an instance URL
-fn normalize_url(url: &str) -> String {
+pub fn normalize_url(url: &str) -> String {
     let url = url.trim();
     let url = if url.starts_with("http://") || url.starts_with("https://") {
         url.to_string()
@@ -539,3 +646,15 @@ fn normalize_url(url: &str) -> String {
     // Remove trailing slash
     url.trim_end_matches('/').to_string()
 }
+
+/// Whether a page of statuses links to an older page
+///
+/// Mastodon and Pleroma announce the next page in the `Link` header, even
+/// when filtering cut the page short. Without the header a page is assumed
+/// to have a successor whenever it is full.
+fn has_next_page(header: &reqwest::header::HeaderMap, len: usize, limit: u32) -> bool {
+    match header.get(reqwest::header::LINK).and_then(|link| link.to_str().ok()) {
+        Some(link) => link.contains("rel=\"next\""),
+        None => len >= limit as usize,
+    }
+}
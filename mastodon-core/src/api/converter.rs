@@ -16,12 +16,12 @@
 
 //! Type converters from megalodon types to Blindodon types
 
-use megalodon::entities;
+use megalodon::{entities, SNS};
 
 use crate::models::{
     Application, CustomEmoji, MediaAttachment, MediaDimensions, MediaFocus, MediaMeta,
     MediaType, Mention, Notification, NotificationType, Poll, PollOption, Post, ProfileField,
-    Tag, User, Visibility,
+    ServerSoftware, Tag, User, Visibility,
 };
 
 /// Convert a megalodon Status to a Blindodon Post
@@ -262,6 +262,30 @@ fn convert_notification_type(notification_type: &entities::notification::Notific
     }
 }
 
+/// Convert a detected megalodon SNS to the server software it stands for
+pub fn convert_sns(sns: &SNS) -> ServerSoftware {
+    match sns {
+        SNS::Mastodon => ServerSoftware::Mastodon,
+        SNS::Pleroma => ServerSoftware::Pleroma,
+        SNS::Friendica => ServerSoftware::Friendica,
+        SNS::Firefish => ServerSoftware::Firefish,
+        SNS::Gotosocial => ServerSoftware::Gotosocial,
+        SNS::Pixelfed => ServerSoftware::Pixelfed,
+    }
+}
+
+/// Get the megalodon SNS that talks to the given server software
+pub fn sns_for(software: ServerSoftware) -> SNS {
+    match software {
+        ServerSoftware::Mastodon => SNS::Mastodon,
+        ServerSoftware::Pleroma => SNS::Pleroma,
+        ServerSoftware::Friendica => SNS::Friendica,
+        ServerSoftware::Firefish => SNS::Firefish,
+        ServerSoftware::Gotosocial => SNS::Gotosocial,
+        ServerSoftware::Pixelfed => SNS::Pixelfed,
+    }
+}
+
 /// Strip HTML tags from content for plain text
 fn strip_html(html: &str) -> String {
     let mut result = String::new();
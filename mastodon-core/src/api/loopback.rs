@@ -0,0 +1,250 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! One-shot loopback listener for OAuth redirects
+//!
+//! Instead of the out-of-band flow, where the user copies the code from the
+//! browser, a login can register `http://127.0.0.1:<port>/callback` as its
+//! redirect URI. The browser then hands the code straight to this listener,
+//! which answers with a short page telling the user they can close the tab.
+//!
+//! Any local process or web page can reach the port, so only a redirect
+//! carrying the login's `state` ends the wait; others get an error page.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tracing::{debug, warn};
+
+use super::oauth::LOGIN_TTL;
+
+/// Path the browser is redirected to
+const CALLBACK_PATH: &str = "/callback";
+
+/// Largest request head accepted from the browser
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time a connection gets to send its request head
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after a failed accept before trying again
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Query parameters of a redirect whose `state` matched the login
+#[derive(Debug, Default)]
+pub struct CallbackParams {
+    pub code: Option<String>,
+    /// Set when the user denied access or the server reported an error
+    pub error: Option<String>,
+}
+
+/// Listener on a random localhost port waiting for one redirect
+pub struct LoopbackListener {
+    listener: TcpListener,
+    port: u16,
+}
+
+impl LoopbackListener {
+    /// Bind to a free port on 127.0.0.1
+    pub async fn bind() -> Result<Self> {
+        Self::bind_port(0).await
+    }
+
+    /// Bind to the port of a redirect URI registered earlier
+    ///
+    /// Returns `None` if the URI is not one of ours or the port is taken.
+    pub async fn rebind(redirect_uri: &str) -> Option<Self> {
+        let port = redirect_uri
+            .strip_prefix("http://127.0.0.1:")?
+            .strip_suffix(CALLBACK_PATH)?
+            .parse()
+            .ok()
+            .filter(|&port| port != 0)?;
+
+        Self::bind_port(port).await.ok()
+    }
+
+    async fn bind_port(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .await
+            .context("Failed to bind loopback listener for OAuth")?;
+        let port = listener.local_addr()?.port();
+        debug!("Waiting for OAuth redirect on port {}", port);
+
+        Ok(Self { listener, port })
+    }
+
+    /// Redirect URI to register for this login
+    pub fn redirect_uri(&self) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, CALLBACK_PATH)
+    }
+
+    /// Wait for the browser to arrive at the callback path with `state`
+    ///
+    /// Every connection is read on its own task, so one that stays silent
+    /// cannot hold up the browser. Other requests, such as for a favicon,
+    /// are answered with 404, and redirects with another state with 400.
+    /// The connection is returned so the page can report how the login
+    /// went. Gives up once the login would have expired.
+    pub async fn accept(self, state: &str) -> Result<(CallbackParams, TcpStream)> {
+        tokio::time::timeout(LOGIN_TTL, self.accept_callback(state))
+            .await
+            .context("Timed out waiting for the browser to return from the login")?
+    }
+
+    async fn accept_callback(&self, state: &str) -> Result<(CallbackParams, TcpStream)> {
+        // Dropping the set on return aborts connections still being read
+        let mut requests = JoinSet::new();
+
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => {
+                    match accepted {
+                        Ok((stream, _)) => {
+                            requests.spawn(read_callback(stream, state.to_string()));
+                        }
+                        Err(e) => {
+                            // Errors such as running out of descriptors pass
+                            // by themselves; do not spin while they last
+                            warn!("Failed to accept OAuth redirect connection: {}", e);
+                            tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        }
+                    }
+                }
+                Some(finished) = requests.join_next() => {
+                    if let Ok(Some(callback)) = finished {
+                        return Ok(callback);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Read one connection, returning it if it is the redirect for this login
+async fn read_callback(mut stream: TcpStream, state: String) -> Option<(CallbackParams, TcpStream)> {
+    let target = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_target(&mut stream)).await {
+        Ok(Ok(target)) => target,
+        Ok(Err(e)) => {
+            warn!("Ignoring malformed OAuth redirect request: {}", e);
+            return None;
+        }
+        Err(_) => {
+            warn!("Ignoring OAuth redirect connection that sent no request within {:?}", REQUEST_TIMEOUT);
+            return None;
+        }
+    };
+
+    let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+    if path != CALLBACK_PATH {
+        let _ = write_response(&mut stream, "404 Not Found", "Not found", "Nothing to see here.").await;
+        return None;
+    }
+
+    let mut params = parse_query(query);
+    if params.remove("state").as_deref() != Some(state.as_str()) {
+        warn!("Ignoring OAuth redirect for another login");
+        respond(stream, false, "This page does not belong to the login in progress.").await;
+        return None;
+    }
+
+    Some((
+        CallbackParams {
+            code: params.remove("code"),
+            error: params.remove("error_description").or_else(|| params.remove("error")),
+        },
+        stream,
+    ))
+}
+
+/// Answer the browser with a page saying how the login went
+pub async fn respond(mut stream: TcpStream, success: bool, message: &str) {
+    let (status, title) = if success {
+        ("200 OK", "Login complete")
+    } else {
+        ("400 Bad Request", "Login failed")
+    };
+
+    if let Err(e) = write_response(&mut stream, status, title, message).await {
+        warn!("Failed to answer OAuth redirect: {}", e);
+    }
+}
+
+/// Read the request head and return its target, such as `/callback?code=...`
+async fn read_request_target(stream: &mut TcpStream) -> Result<String> {
+    let mut head = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_SIZE {
+            anyhow::bail!("Request too large");
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            anyhow::bail!("Connection closed before the request was complete");
+        }
+        head.extend_from_slice(&chunk[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) => Ok(target.to_string()),
+        _ => anyhow::bail!("Expected a GET request"),
+    }
+}
+
+/// Decode `application/x-www-form-urlencoded` query parameters
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .filter_map(|(key, value)| {
+            let value = urlencoding::decode(&value.replace('+', " ")).ok()?.into_owned();
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// Write a complete HTML response and close the connection
+async fn write_response(stream: &mut TcpStream, status: &str, title: &str, message: &str) -> std::io::Result<()> {
+    let body = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Blindodon: {title}</title>\n</head>\n<body>\n<main>\n<h1>{title}</h1>\n<p role=\"status\">{message}</p>\n</main>\n</body>\n</html>\n",
+        title = escape_html(title),
+        message = escape_html(message),
+    );
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Escape text for use in HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
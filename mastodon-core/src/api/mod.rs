@@ -18,6 +18,9 @@
 
 mod client;
 mod converter;
+mod oauth;
+pub mod loopback;
 
-pub use client::MastodonClient;
-pub use converter::convert_status;
+pub use client::{normalize_url, MastodonClient, OOB_REDIRECT_URI};
+pub use oauth::PendingLogin;
+pub use converter::{convert_notification, convert_status, sns_for};
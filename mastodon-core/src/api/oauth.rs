@@ -0,0 +1,158 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Pending OAuth logins
+//!
+//! Every `auth.start` registers a pending login keyed by a random `state`,
+//! so several logins can be in progress at once. The state travels through
+//! the authorize URL and must come back with the code. Logins to software
+//! known to support PKCE also carry a code verifier whose S256 challenge
+//! goes into the authorize URL; the server checks it when the code is
+//! exchanged, so a code intercepted on its way back is useless on its own.
+
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::models::OAuthApp;
+
+/// How long a login may take from `auth.start` to `auth.callback`
+pub(super) const LOGIN_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Number of random bytes in a state or code verifier
+const TOKEN_LEN: usize = 32;
+
+/// A login waiting for its authorization code
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    /// App the login was started with
+    pub app: OAuthApp,
+    /// PKCE code verifier, sent when exchanging the code (`None` if the
+    /// server software does not support PKCE)
+    pub code_verifier: Option<String>,
+    /// Stored account this login renews, if any
+    pub account_id: Option<String>,
+    started_at: Instant,
+}
+
+impl PendingLogin {
+    pub fn new(app: OAuthApp, code_verifier: Option<String>, account_id: Option<String>) -> Self {
+        Self {
+            app,
+            code_verifier,
+            account_id,
+            started_at: Instant::now(),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.started_at.elapsed() > LOGIN_TTL
+    }
+}
+
+/// Pending logins keyed by state
+static PENDING_LOGINS: LazyLock<Mutex<HashMap<String, PendingLogin>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Generate a random URL-safe token for a state or code verifier
+pub fn random_token() -> Result<String> {
+    let mut bytes = [0u8; TOKEN_LEN];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate random token"))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// S256 PKCE challenge for a code verifier
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, code_verifier.as_bytes()))
+}
+
+/// Remember a login until its code comes back
+pub fn insert(state: String, login: PendingLogin) {
+    let mut logins = PENDING_LOGINS.lock().unwrap();
+    logins.retain(|_, login| !login.is_expired());
+    logins.insert(state, login);
+}
+
+/// Look up the login a state belongs to, unless it has expired
+pub fn get(state: &str) -> Option<PendingLogin> {
+    let mut logins = PENDING_LOGINS.lock().unwrap();
+    logins.retain(|_, login| !login.is_expired());
+    logins.get(state).cloned()
+}
+
+/// Forget a login once it has completed
+pub fn remove(state: &str) {
+    PENDING_LOGINS.lock().unwrap().remove(state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login() -> PendingLogin {
+        PendingLogin::new(
+            OAuthApp {
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+                redirect_uri: "urn:ietf:wg:oauth:2.0:oob".to_string(),
+                instance_url: "https://example.social".to_string(),
+                software: Default::default(),
+            },
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn code_challenge_matches_rfc7636() {
+        // RFC 7636, appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn random_tokens_are_url_safe() {
+        let token = random_token().unwrap();
+        assert_eq!(URL_SAFE_NO_PAD.decode(&token).unwrap().len(), TOKEN_LEN);
+        assert_ne!(token, random_token().unwrap());
+    }
+
+    #[test]
+    fn pending_logins_are_found_until_removed() {
+        let state = random_token().unwrap();
+        insert(state.clone(), login());
+        assert!(get(&state).is_some());
+        remove(&state);
+        assert!(get(&state).is_none());
+    }
+
+    #[test]
+    fn pending_logins_expire() {
+        let state = random_token().unwrap();
+        let mut expired = login();
+        expired.started_at = Instant::now() - LOGIN_TTL - Duration::from_secs(1);
+        insert(state.clone(), expired);
+        assert!(get(&state).is_none());
+    }
+}
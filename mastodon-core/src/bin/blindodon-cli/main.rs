@@ -0,0 +1,370 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Blindodon CLI - terminal client for a running Blindodon core
+//!
+//! Connects to the core's IPC endpoint like the UI does and offers a
+//! subcommand per common task. Output is plain text with one fact per line,
+//! which reads well with terminal screen readers; `--json` prints the raw
+//! results instead for scripting. `call` reaches every other method.
+
+mod output;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use mastodon_core::ipc::client::{ClientConfig, IpcClient};
+use mastodon_core::models::{
+    methods, AccountsResponse, BackupResponse, NewPost, NotificationResponse, Post, SwitchAccountResponse, TimelineResponse,
+    TimelineType, VaultStatus, Visibility,
+};
+
+#[derive(Parser)]
+#[command(name = "blindodon-cli", version, about = "Terminal client for a running Blindodon core")]
+struct Cli {
+    /// Print results as JSON instead of plain text
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Socket path (Unix) or pipe name (Windows) of the core
+    #[arg(long, global = true, value_name = "PATH")]
+    socket: Option<String>,
+
+    /// File holding the core's session secret
+    #[arg(long, global = true, value_name = "PATH")]
+    secret_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read a timeline
+    Timeline {
+        /// Which timeline to read
+        #[arg(value_enum, default_value_t = TimelineArg::Home)]
+        timeline: TimelineArg,
+        /// Number of posts to fetch
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+        /// Only fetch posts older than this post ID
+        #[arg(long, value_name = "ID")]
+        max_id: Option<String>,
+        /// Read the posts stored locally instead of asking the server
+        #[arg(long)]
+        cached: bool,
+    },
+    /// Load the posts missing between two posts of a timeline
+    FillGap {
+        /// Post just above the gap
+        newer_id: String,
+        /// Post just below the gap
+        older_id: String,
+        /// Which timeline the gap is in
+        #[arg(long, value_enum, default_value_t = TimelineArg::Home)]
+        timeline: TimelineArg,
+        /// Number of posts to load
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Publish a post; pass - as the text to read it from standard input
+    Post {
+        /// Text of the post
+        text: String,
+        /// Content warning shown before the text
+        #[arg(long, value_name = "TEXT")]
+        cw: Option<String>,
+        /// Who can see the post
+        #[arg(long, value_enum, default_value_t = VisibilityArg::Public)]
+        visibility: VisibilityArg,
+        /// Reply to this post ID
+        #[arg(long, value_name = "ID")]
+        reply_to: Option<String>,
+    },
+    /// Read notifications
+    Notifications {
+        /// Number of notifications to fetch
+        #[arg(long, default_value_t = 20)]
+        limit: u32,
+    },
+    /// Boost a post
+    Boost { id: String },
+    /// Undo a boost
+    Unboost { id: String },
+    /// Favourite a post
+    Favourite { id: String },
+    /// Undo a favourite
+    Unfavourite { id: String },
+    /// List stored accounts, or switch to one by number or ID
+    SwitchAccount {
+        /// Number from the list or account ID to switch to
+        account: Option<String>,
+    },
+    /// Unlock stored tokens, reading the passphrase from standard input
+    Unlock,
+    /// Export accounts and settings to an encrypted backup, reading the
+    /// passphrase from standard input
+    Export { path: PathBuf },
+    /// Import accounts and settings from an encrypted backup, reading the
+    /// passphrase from standard input
+    Import { path: PathBuf },
+    /// Call any method with JSON parameters and print the JSON result
+    Call {
+        /// Method name, such as instance.get
+        method: String,
+        /// Parameters as a JSON object
+        #[arg(default_value = "{}")]
+        params: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TimelineArg {
+    Home,
+    Local,
+    Federated,
+    Direct,
+    Bookmarks,
+    Favourites,
+    Trending,
+}
+
+impl From<TimelineArg> for TimelineType {
+    fn from(timeline: TimelineArg) -> Self {
+        match timeline {
+            TimelineArg::Home => TimelineType::Home,
+            TimelineArg::Local => TimelineType::Local,
+            TimelineArg::Federated => TimelineType::Federated,
+            TimelineArg::Direct => TimelineType::Direct,
+            TimelineArg::Bookmarks => TimelineType::Bookmarks,
+            TimelineArg::Favourites => TimelineType::Favourites,
+            TimelineArg::Trending => TimelineType::Trending,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum VisibilityArg {
+    Public,
+    Unlisted,
+    Private,
+    Direct,
+}
+
+impl From<VisibilityArg> for Visibility {
+    fn from(visibility: VisibilityArg) -> Self {
+        match visibility {
+            VisibilityArg::Public => Visibility::Public,
+            VisibilityArg::Unlisted => Visibility::Unlisted,
+            VisibilityArg::Private => Visibility::Private,
+            VisibilityArg::Direct => Visibility::Direct,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let mut config = ClientConfig::from_env();
+    if let Some(socket) = cli.socket {
+        config.endpoint = socket;
+    }
+    if let Some(secret_file) = cli.secret_file {
+        config.secret_file = Some(secret_file);
+    }
+
+    let mut client = IpcClient::connect(&config).await?;
+    let mut session = Session { client: &mut client, json: cli.json };
+
+    match cli.command {
+        Command::Timeline { timeline, limit, max_id, cached } => {
+            let params = json!({
+                "timeline_type": TimelineType::from(timeline),
+                "limit": limit,
+                "max_id": max_id,
+                "cached": cached,
+            });
+            session
+                .show(methods::TIMELINE_GET, params, |page: TimelineResponse| output::timeline(&page))
+                .await
+        }
+        Command::FillGap { newer_id, older_id, timeline, limit } => {
+            let params = json!({
+                "timeline_type": TimelineType::from(timeline),
+                "newer_id": newer_id,
+                "older_id": older_id,
+                "limit": limit,
+            });
+            session
+                .show(methods::TIMELINE_FILL_GAP, params, |page: TimelineResponse| output::timeline(&page))
+                .await
+        }
+        Command::Post { text, cw, visibility, reply_to } => {
+            let content = if text == "-" {
+                let mut content = String::new();
+                std::io::stdin().read_to_string(&mut content).context("Failed to read the post from standard input")?;
+                content
+            } else {
+                text
+            };
+            let new_post = NewPost {
+                content,
+                sensitive: cw.is_some(),
+                spoiler_text: cw,
+                visibility: visibility.into(),
+                language: None,
+                in_reply_to_id: reply_to,
+                media_ids: Vec::new(),
+                poll: None,
+                scheduled_at: None,
+                blindodon_pm: false,
+            };
+            session
+                .show(methods::POST_CREATE, serde_json::to_value(new_post)?, |post: Post| {
+                    format!("Posted.\n{}", output::post(&post))
+                })
+                .await
+        }
+        Command::Notifications { limit } => {
+            session
+                .show(methods::NOTIFICATIONS_GET, json!({ "limit": limit }), |page: NotificationResponse| {
+                    output::notifications(&page)
+                })
+                .await
+        }
+        Command::Boost { id } => session.post_action(methods::POST_BOOST, id, "Boosted").await,
+        Command::Unboost { id } => session.post_action(methods::POST_UNBOOST, id, "Boost removed from").await,
+        Command::Favourite { id } => session.post_action(methods::POST_FAVOURITE, id, "Favourited").await,
+        Command::Unfavourite { id } => session.post_action(methods::POST_UNFAVOURITE, id, "Favourite removed from").await,
+        Command::SwitchAccount { account: None } => {
+            session
+                .show(methods::AUTH_GET_ACCOUNTS, json!({}), |accounts: AccountsResponse| output::accounts(&accounts))
+                .await
+        }
+        Command::SwitchAccount { account: Some(account) } => {
+            let account_id = session.resolve_account(&account).await?;
+            session
+                .show(methods::AUTH_SWITCH_ACCOUNT, json!({ "account_id": account_id }), |switched: SwitchAccountResponse| {
+                    format!("Switched to {}", output::account_name(&switched.account))
+                })
+                .await
+        }
+        Command::Unlock => {
+            let passphrase = read_passphrase()?;
+            session
+                .show(methods::VAULT_UNLOCK, json!({ "passphrase": passphrase }), |_: VaultStatus| {
+                    "Stored tokens unlocked".to_string()
+                })
+                .await
+        }
+        Command::Export { path } => {
+            // The core resolves paths against its own working directory
+            let path = std::path::absolute(&path).context("Invalid backup path")?;
+            let passphrase = read_passphrase()?;
+            session
+                .show(methods::BACKUP_EXPORT, json!({ "path": path, "passphrase": passphrase }), |r: BackupResponse| {
+                    format!("Exported {} account(s) and {} setting(s) to {}", r.accounts, r.settings, r.path)
+                })
+                .await
+        }
+        Command::Import { path } => {
+            let path = std::path::absolute(&path).context("Invalid backup path")?;
+            let passphrase = read_passphrase()?;
+            session
+                .show(methods::BACKUP_IMPORT, json!({ "path": path, "passphrase": passphrase }), |r: BackupResponse| {
+                    format!("Imported {} account(s) and {} setting(s) from {}", r.accounts, r.settings, r.path)
+                })
+                .await
+        }
+        Command::Call { method, params } => {
+            let params: Value = serde_json::from_str(&params).context("Parameters must be valid JSON")?;
+            let result = session.client.call(&method, params).await?;
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            Ok(())
+        }
+    }
+}
+
+/// Read a passphrase from the first line of standard input
+fn read_passphrase() -> Result<String> {
+    let mut passphrase = String::new();
+    std::io::stdin().read_line(&mut passphrase).context("Failed to read the passphrase from standard input")?;
+    Ok(passphrase.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// A connected client and how to print results
+struct Session<'a> {
+    client: &'a mut IpcClient,
+    json: bool,
+}
+
+impl Session<'_> {
+    /// Call a method and print its result as JSON or with `format`
+    async fn show<T, F>(&mut self, method: &str, params: Value, format: F) -> Result<()>
+    where
+        T: DeserializeOwned,
+        F: FnOnce(T) -> String,
+    {
+        let result = self.client.call(method, params).await?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        } else {
+            let result = serde_json::from_value(result).with_context(|| format!("Unexpected result from {}", method))?;
+            println!("{}", format(result));
+        }
+        Ok(())
+    }
+
+    /// Boost or favourite a post, or undo it
+    async fn post_action(&mut self, method: &str, id: String, done: &str) -> Result<()> {
+        self.show(method, json!({ "post_id": id }), |post: Post| {
+            format!("{} post by {}.", done, output::author(&post))
+        })
+        .await
+    }
+
+    /// Turn a number from the account list into an account ID
+    async fn resolve_account(&mut self, account: &str) -> Result<String> {
+        let Ok(number) = account.parse::<usize>() else {
+            return Ok(account.to_string());
+        };
+
+        let accounts: AccountsResponse = self.client.request(methods::AUTH_GET_ACCOUNTS, &json!({})).await?;
+        number
+            .checked_sub(1)
+            .and_then(|index| accounts.accounts.get(index))
+            .map(|account| account.id.clone())
+            .with_context(|| format!("There is no account number {}", number))
+    }
+}
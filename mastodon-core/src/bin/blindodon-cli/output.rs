@@ -0,0 +1,218 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Plain text rendering of results
+//!
+//! Everything is written to be read aloud: one item per paragraph, one fact
+//! per line, numbers spelled with their unit, and no decorative symbols.
+
+use chrono::{DateTime, Utc};
+
+use mastodon_core::models::{
+    AccountsResponse, MediaType, Notification, NotificationResponse, NotificationType, Post, StoredAccount,
+    TimelineGap, TimelineResponse, User,
+};
+
+/// A page of posts
+pub fn timeline(page: &TimelineResponse) -> String {
+    if page.posts.is_empty() {
+        return "No posts.".to_string();
+    }
+
+    // A gap goes right after its newer post, or first if that post is not on the page
+    let gap_notice = |gap: &TimelineGap| {
+        format!("Posts may be missing here. Load them with: fill-gap {} {}", gap.newer_id, gap.older_id)
+    };
+    let mut paragraphs: Vec<String> = page
+        .gaps
+        .iter()
+        .filter(|gap| !page.posts.iter().any(|post| post.id == gap.newer_id))
+        .map(gap_notice)
+        .collect();
+
+    for (index, post) in page.posts.iter().enumerate() {
+        paragraphs.push(format!("{}. {}", index + 1, self::post(post)));
+        paragraphs.extend(page.gaps.iter().filter(|gap| gap.newer_id == post.id).map(gap_notice));
+    }
+    paragraphs.join("\n\n")
+}
+
+/// A single post, starting with who wrote it
+pub fn post(post: &Post) -> String {
+    if let Some(boosted) = &post.reblog {
+        return format!("{} boosted {}", user_name(&post.account), self::post(boosted));
+    }
+
+    let mut lines = vec![format!("{}, {}", author(post), relative_time(post.created_at))];
+
+    if !post.spoiler_text.is_empty() {
+        lines.push(format!("Content warning: {}", post.spoiler_text));
+    }
+    lines.push(post.plain_content.clone().unwrap_or_else(|| post.content.clone()));
+
+    for attachment in &post.media_attachments {
+        let kind = match attachment.media_type {
+            MediaType::Image => "Image",
+            MediaType::Video => "Video",
+            MediaType::Gifv => "Animated image",
+            MediaType::Audio => "Audio",
+            MediaType::Unknown => "Attachment",
+        };
+        match attachment.description.as_deref().filter(|d| !d.is_empty()) {
+            Some(description) => lines.push(format!("{}: {}", kind, description)),
+            None => lines.push(format!("{} without description", kind)),
+        }
+    }
+
+    if let Some(poll) = &post.poll {
+        let options: Vec<&str> = poll.options.iter().map(|option| option.title.as_str()).collect();
+        lines.push(format!("Poll: {}", options.join(", ")));
+    }
+
+    let mut stats = format!(
+        "{}, {}, {}.",
+        count(post.replies_count, "reply", "replies"),
+        count(post.reblogs_count, "boost", "boosts"),
+        count(post.favourites_count, "favourite", "favourites"),
+    );
+    if post.reblogged == Some(true) {
+        stats.push_str(" You boosted this.");
+    }
+    if post.favourited == Some(true) {
+        stats.push_str(" You favourited this.");
+    }
+    lines.push(stats);
+    lines.push(format!("ID {}", post.id));
+
+    lines.join("\n")
+}
+
+/// A page of notifications
+pub fn notifications(page: &NotificationResponse) -> String {
+    if page.notifications.is_empty() {
+        return "No notifications.".to_string();
+    }
+
+    let notifications: Vec<String> = page
+        .notifications
+        .iter()
+        .enumerate()
+        .map(|(index, notification)| format!("{}. {}", index + 1, self::notification(notification)))
+        .collect();
+    notifications.join("\n\n")
+}
+
+/// A single notification and the post it is about
+fn notification(notification: &Notification) -> String {
+    let what = match notification.notification_type {
+        NotificationType::Mention => "mentioned you",
+        NotificationType::Reblog => "boosted your post",
+        NotificationType::Favourite => "favourited your post",
+        NotificationType::Follow => "followed you",
+        NotificationType::FollowRequest => "requested to follow you",
+        NotificationType::Poll => "ran a poll that has ended",
+        NotificationType::Update => "edited a post",
+        NotificationType::AdminSignUp => "signed up",
+        NotificationType::AdminReport => "filed a report",
+        NotificationType::SeveredRelationships => "was cut off by moderation",
+        NotificationType::Unknown => "sent a notification",
+    };
+
+    let mut lines = vec![format!(
+        "{} {}, {}",
+        user_name(&notification.account),
+        what,
+        relative_time(notification.created_at)
+    )];
+    if let Some(status) = &notification.status {
+        lines.push(status.plain_content.clone().unwrap_or_else(|| status.content.clone()));
+        lines.push(format!("Post ID {}", status.id));
+    }
+
+    lines.join("\n")
+}
+
+/// Stored accounts, numbered for `switch-account`
+pub fn accounts(accounts: &AccountsResponse) -> String {
+    if accounts.accounts.is_empty() {
+        return "No accounts. Log in from the Blindodon app first.".to_string();
+    }
+
+    let lines: Vec<String> = accounts
+        .accounts
+        .iter()
+        .enumerate()
+        .map(|(index, account)| {
+            let active = accounts.current_account_id.as_deref() == Some(account.id.as_str());
+            format!(
+                "{}. {}{}",
+                index + 1,
+                account_name(account),
+                if active { ", active" } else { "" }
+            )
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Display name and handle of a stored account
+pub fn account_name(account: &StoredAccount) -> String {
+    let handle = match account.instance_url.split("://").nth(1) {
+        Some(domain) => format!("{}@{}", account.username, domain.trim_end_matches('/')),
+        None => account.acct.clone(),
+    };
+    if account.display_name.is_empty() {
+        handle
+    } else {
+        format!("{} ({})", account.display_name, handle)
+    }
+}
+
+/// Who wrote a post
+pub fn author(post: &Post) -> String {
+    user_name(&post.account)
+}
+
+/// Display name and handle of a user
+fn user_name(user: &User) -> String {
+    if user.display_name.is_empty() {
+        format!("@{}", user.acct)
+    } else {
+        format!("{} (@{})", user.display_name, user.acct)
+    }
+}
+
+/// "1 boost", "2 boosts"
+fn count(n: u64, singular: &str, plural: &str) -> String {
+    format!("{} {}", n, if n == 1 { singular } else { plural })
+}
+
+/// How long ago something happened, in words
+fn relative_time(at: DateTime<Utc>) -> String {
+    let elapsed = Utc::now() - at;
+
+    if elapsed.num_minutes() < 1 {
+        "just now".to_string()
+    } else if elapsed.num_hours() < 1 {
+        format!("{} ago", count(elapsed.num_minutes() as u64, "minute", "minutes"))
+    } else if elapsed.num_days() < 1 {
+        format!("{} ago", count(elapsed.num_hours() as u64, "hour", "hours"))
+    } else if elapsed.num_days() < 7 {
+        format!("{} ago", count(elapsed.num_days() as u64, "day", "days"))
+    } else {
+        at.format("%-d %B %Y").to_string()
+    }
+}
@@ -0,0 +1,367 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Encrypted backups of stored accounts and settings
+//!
+//! A backup carries every stored account with its secrets, the app
+//! registrations their tokens were issued to, global and per-account
+//! settings, and timeline positions, so a profile can move to another
+//! machine without logging in to each account again. The core has no
+//! separate store for drafts or filters; clients keep them as settings,
+//! which travel with the backup.
+//!
+//! The file is a small JSON envelope holding the PBKDF2 salt and a payload
+//! sealed with the key derived from the backup passphrase. The backup key
+//! is independent of the vault, so a backup imports under any vault, and
+//! secrets are sealed again by the vault they are imported into.
+//!
+//! Importing merges into the existing data: accounts, registrations and
+//! settings in the backup replace those with the same key.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::info;
+
+use super::vault::derive_key;
+use super::CacheManager;
+use crate::crypto::vault::{self, DEFAULT_ITERATIONS};
+use crate::models::{BackupResponse, OAuthApp, StoredAccount};
+
+/// Format name in the envelope
+const FORMAT: &str = "blindodon-backup";
+
+/// Current backup format version
+const VERSION: u32 = 1;
+
+/// Highest PBKDF2 iteration count accepted from a backup file, so a
+/// crafted file cannot tie up the core deriving its key
+const MAX_ITERATIONS: u32 = DEFAULT_ITERATIONS * 4;
+
+/// Context the payload is sealed for
+const PAYLOAD_CONTEXT: &str = "backup.payload";
+
+/// Backup failures callers need to tell apart
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error("Wrong backup passphrase")]
+    WrongPassphrase,
+    #[error("Not a Blindodon backup")]
+    Invalid,
+    #[error("Backup format version {0} is not supported")]
+    UnsupportedVersion(u32),
+}
+
+/// Outer layer of a backup file
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    format: String,
+    version: u32,
+    salt: String,
+    iterations: u32,
+    payload: String,
+}
+
+/// Everything a backup carries
+#[derive(Serialize, Deserialize)]
+struct Contents {
+    exported_at: DateTime<Utc>,
+    accounts: Vec<BackupAccount>,
+    oauth_apps: Vec<OAuthApp>,
+    settings: HashMap<String, String>,
+    account_settings: Vec<AccountSetting>,
+    timeline_positions: Vec<TimelinePosition>,
+}
+
+/// A stored account together with the secrets it normally leaves out
+#[derive(Serialize, Deserialize)]
+struct BackupAccount {
+    #[serde(flatten)]
+    account: StoredAccount,
+    access_token: String,
+    refresh_token: Option<String>,
+    blindodon_pm_private_key: Option<String>,
+}
+
+/// Row of the `account_settings` table
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+struct AccountSetting {
+    account_id: String,
+    key: String,
+    value: String,
+}
+
+/// Row of the `timeline_positions` table
+#[derive(Serialize, Deserialize, sqlx::FromRow)]
+struct TimelinePosition {
+    timeline_id: String,
+    last_read_id: Option<String>,
+    scroll_position: Option<i64>,
+}
+
+impl CacheManager {
+    /// Write a backup sealed with `passphrase` to `path`
+    pub async fn export_backup(&self, path: &Path, passphrase: &str) -> Result<BackupResponse> {
+        if self.is_vault_locked() {
+            return Err(self.vault_locked_error().into());
+        }
+
+        let accounts = self
+            .get_accounts()
+            .await?
+            .into_iter()
+            .map(|mut account| BackupAccount {
+                access_token: std::mem::take(&mut account.access_token),
+                refresh_token: account.refresh_token.take(),
+                blindodon_pm_private_key: account.blindodon_pm_private_key.take(),
+                account,
+            })
+            .collect::<Vec<_>>();
+
+        let mut oauth_apps = Vec::new();
+        let mut instances: Vec<&str> = accounts.iter().map(|a| a.account.instance_url.as_str()).collect();
+        instances.sort_unstable();
+        instances.dedup();
+        for instance_url in instances {
+            oauth_apps.extend(self.get_oauth_apps(instance_url).await?);
+        }
+
+        let account_settings: Vec<AccountSetting> =
+            sqlx::query_as("SELECT account_id, key, value FROM account_settings")
+                .fetch_all(&self.pool)
+                .await?;
+        let timeline_positions: Vec<TimelinePosition> =
+            sqlx::query_as("SELECT timeline_id, last_read_id, scroll_position FROM timeline_positions")
+                .fetch_all(&self.pool)
+                .await?;
+
+        let contents = Contents {
+            exported_at: Utc::now(),
+            accounts,
+            oauth_apps,
+            settings: self.get_all_settings(None).await?,
+            account_settings,
+            timeline_positions,
+        };
+        let response = BackupResponse {
+            path: path.display().to_string(),
+            accounts: contents.accounts.len(),
+            settings: contents.settings.len() + contents.account_settings.len(),
+        };
+
+        let salt = vault::generate_salt()?;
+        let key = derive_key(passphrase.to_string(), salt.to_vec(), DEFAULT_ITERATIONS).await?;
+        let envelope = Envelope {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            salt: STANDARD.encode(salt),
+            iterations: DEFAULT_ITERATIONS,
+            payload: key.seal(&serde_json::to_string(&contents)?, PAYLOAD_CONTEXT)?,
+        };
+        write_private_file(path, &serde_json::to_string_pretty(&envelope)?)?;
+
+        info!("Exported {} account(s) to {}", response.accounts, path.display());
+        Ok(response)
+    }
+
+    /// Merge a backup sealed with `passphrase` from `path`
+    ///
+    /// Everything is written in one transaction, so a failed import leaves
+    /// the cache as it was. The current default account stays the default;
+    /// the backup's one only becomes the default if there is none yet.
+    /// Returns the ids of the imported accounts along with the summary.
+    pub async fn import_backup(&self, path: &Path, passphrase: &str) -> Result<(BackupResponse, Vec<String>)> {
+        if self.is_vault_locked() {
+            return Err(self.vault_locked_error().into());
+        }
+
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read backup {}", path.display()))?;
+        let envelope: Envelope = serde_json::from_str(&data).map_err(|_| BackupError::Invalid)?;
+        if envelope.format != FORMAT {
+            return Err(BackupError::Invalid.into());
+        }
+        if envelope.version != VERSION {
+            return Err(BackupError::UnsupportedVersion(envelope.version).into());
+        }
+
+        let salt = STANDARD.decode(&envelope.salt).map_err(|_| BackupError::Invalid)?;
+        if envelope.iterations == 0 || envelope.iterations > MAX_ITERATIONS {
+            return Err(BackupError::Invalid.into());
+        }
+        let key = derive_key(passphrase.to_string(), salt, envelope.iterations).await?;
+        let payload = key
+            .open(&envelope.payload, PAYLOAD_CONTEXT)
+            .map_err(|_| BackupError::WrongPassphrase)?;
+        let contents: Contents = serde_json::from_str(&payload).map_err(|_| BackupError::Invalid)?;
+
+        let had_default = self.get_default_account().await?.is_some();
+        let mut tx = self.pool.begin().await?;
+        let mut backup_default = None;
+        for backup in &contents.accounts {
+            let mut account = backup.account.clone();
+            account.access_token = backup.access_token.clone();
+            account.refresh_token = backup.refresh_token.clone();
+            account.blindodon_pm_private_key = backup.blindodon_pm_private_key.clone();
+            if account.is_default {
+                backup_default = Some(account.id.clone());
+            }
+            account.is_default = false;
+            self.save_account_on(&mut tx, &account).await?;
+        }
+        if let Some(account_id) = backup_default.filter(|_| !had_default) {
+            self.set_default_account_on(&mut tx, &account_id).await?;
+        }
+
+        for app in &contents.oauth_apps {
+            self.save_oauth_app_on(&mut tx, app).await?;
+        }
+        for (key, value) in &contents.settings {
+            self.set_setting_on(&mut tx, key, value, None).await?;
+        }
+        for setting in &contents.account_settings {
+            self.set_setting_on(&mut tx, &setting.key, &setting.value, Some(&setting.account_id)).await?;
+        }
+        for position in &contents.timeline_positions {
+            sqlx::query(
+                r#"
+                INSERT INTO timeline_positions (timeline_id, last_read_id, scroll_position, updated_at)
+                VALUES (?, ?, ?, CURRENT_TIMESTAMP)
+                ON CONFLICT(timeline_id) DO UPDATE SET
+                    last_read_id = excluded.last_read_id,
+                    scroll_position = excluded.scroll_position,
+                    updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(&position.timeline_id)
+            .bind(&position.last_read_id)
+            .bind(position.scroll_position)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        info!(
+            "Imported {} account(s) from a backup made {}",
+            contents.accounts.len(),
+            contents.exported_at
+        );
+        let response = BackupResponse {
+            path: path.display().to_string(),
+            accounts: contents.accounts.len(),
+            settings: contents.settings.len() + contents.account_settings.len(),
+        };
+        Ok((response, contents.accounts.into_iter().map(|backup| backup.account.id).collect()))
+    }
+}
+
+/// Write a file readable only by the current user, replacing any existing one
+fn write_private_file(path: &Path, contents: &str) -> Result<()> {
+    use std::io::Write;
+
+    vault::remove_file(path)?;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create backup {}", path.display()))?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: &str, access_token: &str) -> StoredAccount {
+        let mut account: StoredAccount = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "instance_url": "https://example.social",
+            "username": "alice",
+            "acct": "alice@example.social",
+            "display_name": "Alice",
+            "token_expires_at": null,
+            "added_at": "2025-01-01T00:00:00Z",
+            "last_used_at": "2025-01-01T00:00:00Z",
+            "is_default": true,
+            "avatar_url": null,
+            "blindodon_pm_public_key": null,
+        }))
+        .unwrap();
+        account.access_token = access_token.to_string();
+        account
+    }
+
+    #[tokio::test]
+    async fn export_import_round_trip() {
+        let (source, source_dir) = CacheManager::temporary().await;
+        source.save_account(&account("1", "token-1")).await.unwrap();
+        source.set_setting("theme", "dark", None).await.unwrap();
+        source.set_setting("sound", "off", Some("1")).await.unwrap();
+        let path = source_dir.join("backup.json");
+        let exported = source.export_backup(&path, "backup passphrase").await.unwrap();
+        assert_eq!(exported.accounts, 1);
+        assert_eq!(exported.settings, 2);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("token-1"));
+
+        let (target, target_dir) = CacheManager::temporary().await;
+        target.save_account(&account("1", "stale")).await.unwrap();
+        let (imported, account_ids) = target.import_backup(&path, "backup passphrase").await.unwrap();
+        assert_eq!(imported.accounts, 1);
+        assert_eq!(account_ids, ["1"]);
+
+        let restored = target.get_account("1").await.unwrap().unwrap();
+        assert_eq!(restored.access_token, "token-1");
+        assert_eq!(target.get_setting("theme", None).await.unwrap().as_deref(), Some("dark"));
+        assert_eq!(target.get_setting("sound", Some("1")).await.unwrap().as_deref(), Some("off"));
+
+        std::fs::remove_dir_all(source_dir).unwrap();
+        std::fs::remove_dir_all(target_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn import_rejects_wrong_passphrase_and_other_files() {
+        let (cache, dir) = CacheManager::temporary().await;
+        cache.save_account(&account("1", "token-1")).await.unwrap();
+        let path = dir.join("backup.json");
+        cache.export_backup(&path, "backup passphrase").await.unwrap();
+
+        let e = cache.import_backup(&path, "wrong").await.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(BackupError::WrongPassphrase)));
+
+        let mut envelope: Envelope = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        envelope.iterations = MAX_ITERATIONS + 1;
+        std::fs::write(&path, serde_json::to_string(&envelope).unwrap()).unwrap();
+        let e = cache.import_backup(&path, "backup passphrase").await.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(BackupError::Invalid)));
+
+        std::fs::write(&path, "{}").unwrap();
+        let e = cache.import_backup(&path, "backup passphrase").await.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(BackupError::Invalid)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -17,25 +17,55 @@
 //! Cache module for local data storage
 //!
 //! Uses SQLite for persistent caching of posts, users, and other data.
+//! Timelines are kept per account, with gaps marking where continuity is
+//! unknown (see `timeline`).
+//! Secrets in the `accounts` table are sealed by the vault (see `vault`),
+//! and can be carried to another machine in an encrypted backup (see
+//! `backup`).
+
+mod backup;
+mod timeline;
+mod vault;
 
 use anyhow::Result;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnection, SqlitePool, SqlitePoolOptions};
 use std::collections::HashMap;
-use std::path::PathBuf;
-use tracing::{debug, info};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::crypto::vault::VaultKey;
+use crate::models::{OAuthApp, ServerSoftware, StoredAccount};
+
+pub use backup::BackupError;
+pub use vault::VaultError;
+
+/// Columns of an account row: `data, access_token, refresh_token, blindodon_pm_private_key, needs_reauth`
+type AccountRow = (String, String, Option<String>, Option<String>, bool);
 
-use crate::models::StoredAccount;
+/// Columns of an app registration row: `client_id, client_secret, redirect_uri, instance_url, software`
+type OAuthAppRow = (String, String, String, String, String);
 
 /// Cache manager for local data storage
 pub struct CacheManager {
     pool: SqlitePool,
+    /// Key sealing stored secrets; `None` while the vault is locked
+    vault_key: RwLock<Option<VaultKey>>,
+    /// Where the vault key is kept when no passphrase is set
+    key_path: PathBuf,
+    /// Set when the key file is missing or does not match the database
+    vault_key_lost: AtomicBool,
 }
 
 impl CacheManager {
     /// Create a new cache manager
     pub async fn new() -> Result<Self> {
-        let db_path = get_db_path();
+        Self::with_path(&get_db_path()).await
+    }
 
+    /// Open the cache database at `db_path`, with the vault key beside it
+    pub async fn with_path(db_path: &Path) -> Result<Self> {
         // Ensure the directory exists
         if let Some(parent) = db_path.parent() {
             std::fs::create_dir_all(parent)?;
@@ -50,8 +80,14 @@ impl CacheManager {
             .connect(&db_url)
             .await?;
 
-        let manager = Self { pool };
+        let manager = Self {
+            pool,
+            vault_key: RwLock::new(None),
+            key_path: db_path.with_file_name("vault.key"),
+            vault_key_lost: AtomicBool::new(false),
+        };
         manager.init_schema().await?;
+        manager.init_vault().await?;
 
         Ok(manager)
     }
@@ -62,17 +98,29 @@ impl CacheManager {
 
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS posts (
-                id TEXT PRIMARY KEY,
+            -- Never written to; replaced by timeline_posts
+            DROP TABLE IF EXISTS posts;
+
+            CREATE TABLE IF NOT EXISTS timeline_posts (
                 account_id TEXT NOT NULL,
-                content TEXT NOT NULL,
+                timeline TEXT NOT NULL,
+                sort_key TEXT NOT NULL,
+                post_id TEXT NOT NULL,
                 created_at TEXT NOT NULL,
                 data TEXT NOT NULL,
-                cached_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+                cached_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (account_id, timeline, sort_key)
             );
 
-            CREATE INDEX IF NOT EXISTS idx_posts_account ON posts(account_id);
-            CREATE INDEX IF NOT EXISTS idx_posts_created ON posts(created_at);
+            CREATE TABLE IF NOT EXISTS timeline_gaps (
+                account_id TEXT NOT NULL,
+                timeline TEXT NOT NULL,
+                newer_key TEXT NOT NULL,
+                newer_id TEXT NOT NULL,
+                older_key TEXT NOT NULL,
+                older_id TEXT NOT NULL,
+                PRIMARY KEY (account_id, timeline, newer_key)
+            );
 
             CREATE TABLE IF NOT EXISTS users (
                 id TEXT PRIMARY KEY,
@@ -97,6 +145,15 @@ impl CacheManager {
                 is_default INTEGER NOT NULL DEFAULT 0
             );
 
+            CREATE TABLE IF NOT EXISTS oauth_apps (
+                instance_url TEXT NOT NULL,
+                redirect_uri TEXT NOT NULL,
+                client_id TEXT NOT NULL,
+                client_secret TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (instance_url, redirect_uri)
+            );
+
             CREATE TABLE IF NOT EXISTS timeline_positions (
                 timeline_id TEXT PRIMARY KEY,
                 last_read_id TEXT,
@@ -109,33 +166,87 @@ impl CacheManager {
                 value TEXT NOT NULL,
                 updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
             );
+
+            CREATE TABLE IF NOT EXISTS account_settings (
+                account_id TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (account_id, key)
+            );
             "#,
         )
         .execute(&self.pool)
         .await?;
 
+        self.add_column_if_missing("accounts", "blindodon_pm_private_key", "TEXT").await?;
+        self.add_column_if_missing("oauth_apps", "software", "TEXT NOT NULL DEFAULT 'mastodon'").await?;
+        self.add_column_if_missing("accounts", "needs_reauth", "INTEGER NOT NULL DEFAULT 0").await?;
+
         info!("Cache schema initialized");
 
         Ok(())
     }
 
+    /// Add a column to a table created by an older version
+    async fn add_column_if_missing(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let columns: Vec<(String,)> = sqlx::query_as(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .fetch_all(&self.pool)
+            .await?;
+
+        if !columns.iter().any(|(name,)| name == column) {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
+            info!("Added column {}.{}", table, column);
+        }
+
+        Ok(())
+    }
+
     /// Get the database pool
     pub fn pool(&self) -> &SqlitePool {
         &self.pool
     }
 
+    /// Close the database
+    ///
+    /// Waits for queries that are still running to finish, then closes
+    /// every connection in the pool.
+    pub async fn close(&self) {
+        self.pool.close().await;
+        info!("Cache database closed");
+    }
+
     /// Clean up old cached data
+    ///
+    /// Drops cached timeline posts older than `max_age_days`, along with
+    /// the gaps that end up below the oldest post that remains.
     pub async fn cleanup(&self, max_age_days: u32) -> Result<u64> {
         let result = sqlx::query(
             r#"
-            DELETE FROM posts
-            WHERE cached_at < datetime('now', '-' || ? || ' days')
+            DELETE FROM timeline_posts
+            WHERE datetime(created_at) < datetime('now', '-' || ? || ' days')
             "#,
         )
         .bind(max_age_days)
         .execute(&self.pool)
         .await?;
 
+        sqlx::query(
+            r#"
+            DELETE FROM timeline_gaps
+            WHERE NOT EXISTS (
+                SELECT 1 FROM timeline_posts p
+                WHERE p.account_id = timeline_gaps.account_id
+                  AND p.timeline = timeline_gaps.timeline
+                  AND p.sort_key <= timeline_gaps.older_key
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         let deleted = result.rows_affected();
         if deleted > 0 {
             info!("Cleaned up {} old cached posts", deleted);
@@ -147,31 +258,53 @@ impl CacheManager {
     // ===== ACCOUNT CRUD METHODS =====
 
     /// Save or update an account in the database
+    ///
+    /// Fails with `VaultError::Locked` while the vault is locked.
     pub async fn save_account(&self, account: &StoredAccount) -> Result<()> {
+        self.save_account_on(&mut *self.pool.acquire().await?, account).await
+    }
+
+    /// `save_account` on a given connection, such as a transaction
+    pub(super) async fn save_account_on(&self, conn: &mut SqliteConnection, account: &StoredAccount) -> Result<()> {
         let data = serde_json::to_string(account)?;
+        let access_token = self.seal(&account.id, "access_token", &account.access_token)?;
+        let refresh_token = account
+            .refresh_token
+            .as_deref()
+            .map(|token| self.seal(&account.id, "refresh_token", token))
+            .transpose()?;
+        let private_key = account
+            .blindodon_pm_private_key
+            .as_deref()
+            .map(|key| self.seal(&account.id, "blindodon_pm_private_key", key))
+            .transpose()?;
 
         sqlx::query(
             r#"
-            INSERT INTO accounts (id, instance_url, username, access_token, refresh_token, data, created_at, last_used_at, is_default)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO accounts (id, instance_url, username, access_token, refresh_token, blindodon_pm_private_key, data, created_at, last_used_at, is_default, needs_reauth)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             ON CONFLICT(id) DO UPDATE SET
                 access_token = excluded.access_token,
                 refresh_token = excluded.refresh_token,
+                blindodon_pm_private_key = excluded.blindodon_pm_private_key,
                 data = excluded.data,
                 last_used_at = excluded.last_used_at,
-                is_default = excluded.is_default
+                is_default = excluded.is_default,
+                needs_reauth = excluded.needs_reauth
             "#,
         )
         .bind(&account.id)
         .bind(&account.instance_url)
         .bind(&account.username)
-        .bind(&account.access_token)
-        .bind(&account.refresh_token)
+        .bind(&access_token)
+        .bind(&refresh_token)
+        .bind(&private_key)
         .bind(&data)
         .bind(account.added_at.to_rfc3339())
         .bind(account.last_used_at.to_rfc3339())
         .bind(account.is_default)
-        .execute(&self.pool)
+        .bind(account.needs_reauth)
+        .execute(&mut *conn)
         .await?;
 
         info!("Saved account {} ({})", account.acct, account.id);
@@ -180,22 +313,20 @@ impl CacheManager {
 
     /// Get all saved accounts
     pub async fn get_accounts(&self) -> Result<Vec<StoredAccount>> {
-        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
-            "SELECT data, access_token, refresh_token FROM accounts ORDER BY last_used_at DESC",
+        let rows: Vec<AccountRow> = sqlx::query_as(
+            "SELECT data, access_token, refresh_token, blindodon_pm_private_key, needs_reauth FROM accounts ORDER BY last_used_at DESC",
         )
         .fetch_all(&self.pool)
         .await?;
 
-        let accounts: Vec<StoredAccount> = rows
-            .into_iter()
-            .filter_map(|(data, access_token, refresh_token)| {
-                let mut account: StoredAccount = serde_json::from_str(&data).ok()?;
-                // Restore sensitive fields that were skipped during serialization
-                account.access_token = access_token;
-                account.refresh_token = refresh_token;
-                Some(account)
-            })
-            .collect();
+        let mut accounts = Vec::with_capacity(rows.len());
+        for row in rows {
+            match self.account_from_row(row) {
+                Ok(account) => accounts.push(account),
+                Err(e) if e.is::<VaultError>() => return Err(e),
+                Err(e) => warn!("Skipping unreadable account: {}", e),
+            }
+        }
 
         Ok(accounts)
     }
@@ -203,8 +334,8 @@ impl CacheManager {
     /// Get the default (or most recently used) account
     pub async fn get_default_account(&self) -> Result<Option<StoredAccount>> {
         // First try to get account marked as default
-        let row: Option<(String, String, Option<String>)> = sqlx::query_as(
-            "SELECT data, access_token, refresh_token FROM accounts WHERE is_default = 1 LIMIT 1",
+        let row: Option<AccountRow> = sqlx::query_as(
+            "SELECT data, access_token, refresh_token, blindodon_pm_private_key, needs_reauth FROM accounts WHERE is_default = 1 LIMIT 1",
         )
         .fetch_optional(&self.pool)
         .await?;
@@ -214,60 +345,98 @@ impl CacheManager {
             Some(r) => Some(r),
             None => {
                 sqlx::query_as(
-                    "SELECT data, access_token, refresh_token FROM accounts ORDER BY last_used_at DESC LIMIT 1",
+                    "SELECT data, access_token, refresh_token, blindodon_pm_private_key, needs_reauth FROM accounts ORDER BY last_used_at DESC LIMIT 1",
                 )
                 .fetch_optional(&self.pool)
                 .await?
             }
         };
 
-        match row {
-            Some((data, access_token, refresh_token)) => {
-                let mut account: StoredAccount = serde_json::from_str(&data)?;
-                account.access_token = access_token;
-                account.refresh_token = refresh_token;
-                Ok(Some(account))
-            }
-            None => Ok(None),
-        }
+        row.map(|row| self.account_from_row(row)).transpose()
     }
 
     /// Get account by ID
     pub async fn get_account(&self, account_id: &str) -> Result<Option<StoredAccount>> {
-        let row: Option<(String, String, Option<String>)> = sqlx::query_as(
-            "SELECT data, access_token, refresh_token FROM accounts WHERE id = ?",
+        let row: Option<AccountRow> = sqlx::query_as(
+            "SELECT data, access_token, refresh_token, blindodon_pm_private_key, needs_reauth FROM accounts WHERE id = ?",
         )
         .bind(account_id)
         .fetch_optional(&self.pool)
         .await?;
 
-        match row {
-            Some((data, access_token, refresh_token)) => {
-                let mut account: StoredAccount = serde_json::from_str(&data)?;
-                account.access_token = access_token;
-                account.refresh_token = refresh_token;
-                Ok(Some(account))
-            }
-            None => Ok(None),
-        }
+        row.map(|row| self.account_from_row(row)).transpose()
+    }
+
+    /// Build an account from its row, opening the sealed secrets
+    fn account_from_row(
+        &self,
+        (data, access_token, refresh_token, private_key, needs_reauth): AccountRow,
+    ) -> Result<StoredAccount> {
+        let mut account: StoredAccount = serde_json::from_str(&data)?;
+        // Restore sensitive fields that were skipped during serialization
+        account.access_token = self.open(&account.id, "access_token", &access_token)?;
+        account.refresh_token = refresh_token
+            .map(|token| self.open(&account.id, "refresh_token", &token))
+            .transpose()?;
+        account.blindodon_pm_private_key = private_key
+            .map(|key| self.open(&account.id, "blindodon_pm_private_key", &key))
+            .transpose()?;
+        // Capabilities follow the software, whatever was saved
+        account.capabilities = account.software.capabilities();
+        account.needs_reauth = needs_reauth;
+        Ok(account)
     }
 
-    /// Delete an account
+    /// Delete an account along with its settings
     pub async fn delete_account(&self, account_id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
         sqlx::query("DELETE FROM accounts WHERE id = ?")
             .bind(account_id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        for table in ["account_settings", "timeline_posts", "timeline_gaps"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE account_id = ?", table))
+                .bind(account_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
 
         info!("Deleted account {}", account_id);
         Ok(())
     }
 
+    /// Whether an account with this ID is stored
+    pub async fn has_account(&self, account_id: &str) -> Result<bool> {
+        let row: Option<(i64,)> = sqlx::query_as("SELECT 1 FROM accounts WHERE id = ?")
+            .bind(account_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Flag or clear an account whose token the server rejected
+    pub async fn set_needs_reauth(&self, account_id: &str, needs_reauth: bool) -> Result<()> {
+        sqlx::query("UPDATE accounts SET needs_reauth = ? WHERE id = ?")
+            .bind(needs_reauth)
+            .bind(account_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     /// Set account as default and update last_used_at
     pub async fn set_default_account(&self, account_id: &str) -> Result<()> {
+        self.set_default_account_on(&mut *self.pool.acquire().await?, account_id).await
+    }
+
+    /// `set_default_account` on a given connection, such as a transaction
+    pub(super) async fn set_default_account_on(&self, conn: &mut SqliteConnection, account_id: &str) -> Result<()> {
         // Clear all default flags
         sqlx::query("UPDATE accounts SET is_default = 0")
-            .execute(&self.pool)
+            .execute(&mut *conn)
             .await?;
 
         // Set the new default
@@ -275,17 +444,90 @@ impl CacheManager {
             "UPDATE accounts SET is_default = 1, last_used_at = CURRENT_TIMESTAMP WHERE id = ?",
         )
         .bind(account_id)
-        .execute(&self.pool)
+        .execute(&mut *conn)
         .await?;
 
         debug!("Set default account to {}", account_id);
         Ok(())
     }
 
+    // ===== OAUTH APP METHODS =====
+
+    /// Save an app registration, replacing any for the same instance and redirect URI
+    pub async fn save_oauth_app(&self, app: &OAuthApp) -> Result<()> {
+        self.save_oauth_app_on(&mut *self.pool.acquire().await?, app).await
+    }
+
+    /// `save_oauth_app` on a given connection, such as a transaction
+    pub(super) async fn save_oauth_app_on(&self, conn: &mut SqliteConnection, app: &OAuthApp) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO oauth_apps (instance_url, redirect_uri, client_id, client_secret, software)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(instance_url, redirect_uri) DO UPDATE SET
+                client_id = excluded.client_id,
+                client_secret = excluded.client_secret,
+                software = excluded.software,
+                created_at = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(&app.instance_url)
+        .bind(&app.redirect_uri)
+        .bind(&app.client_id)
+        .bind(&app.client_secret)
+        .bind(app.software.as_str())
+        .execute(&mut *conn)
+        .await?;
+
+        debug!("Saved app registration for {} ({})", app.instance_url, app.redirect_uri);
+        Ok(())
+    }
+
+    /// Get every app registered on an instance
+    pub async fn get_oauth_apps(&self, instance_url: &str) -> Result<Vec<OAuthApp>> {
+        let rows: Vec<OAuthAppRow> = sqlx::query_as(
+            "SELECT client_id, client_secret, redirect_uri, instance_url, software FROM oauth_apps WHERE instance_url = ? ORDER BY created_at DESC",
+        )
+        .bind(instance_url)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(oauth_app_from_row).collect())
+    }
+
+    /// Get the app registered on an instance with the given client ID
+    pub async fn get_oauth_app_by_client_id(&self, instance_url: &str, client_id: &str) -> Result<Option<OAuthApp>> {
+        let row: Option<OAuthAppRow> = sqlx::query_as(
+            "SELECT client_id, client_secret, redirect_uri, instance_url, software FROM oauth_apps WHERE instance_url = ? AND client_id = ?",
+        )
+        .bind(instance_url)
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(oauth_app_from_row))
+    }
+
     // ===== SETTINGS CRUD METHODS =====
+    //
+    // Settings are global unless an account ID is given. An account's own
+    // value for a key overrides the global one; keys it has not set fall
+    // back to the global value.
+
+    /// Get a setting value, as seen by an account if one is given
+    pub async fn get_setting(&self, key: &str, account_id: Option<&str>) -> Result<Option<String>> {
+        if let Some(account_id) = account_id {
+            let row: Option<(String,)> =
+                sqlx::query_as("SELECT value FROM account_settings WHERE account_id = ? AND key = ?")
+                    .bind(account_id)
+                    .bind(key)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            if let Some((value,)) = row {
+                return Ok(Some(value));
+            }
+        }
 
-    /// Get a setting value
-    pub async fn get_setting(&self, key: &str) -> Result<Option<String>> {
         let row: Option<(String,)> =
             sqlx::query_as("SELECT value FROM settings WHERE key = ?")
                 .bind(key)
@@ -295,44 +537,110 @@ impl CacheManager {
         Ok(row.map(|(v,)| v))
     }
 
-    /// Set a setting value
-    pub async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
-        sqlx::query(
-            r#"
-            INSERT INTO settings (key, value, updated_at)
-            VALUES (?, ?, CURRENT_TIMESTAMP)
-            ON CONFLICT(key) DO UPDATE SET
-                value = excluded.value,
-                updated_at = CURRENT_TIMESTAMP
-            "#,
-        )
-        .bind(key)
-        .bind(value)
-        .execute(&self.pool)
-        .await?;
+    /// Set a setting value, for an account only if one is given
+    pub async fn set_setting(&self, key: &str, value: &str, account_id: Option<&str>) -> Result<()> {
+        self.set_setting_on(&mut *self.pool.acquire().await?, key, value, account_id).await
+    }
+
+    /// `set_setting` on a given connection, such as a transaction
+    pub(super) async fn set_setting_on(&self, conn: &mut SqliteConnection, key: &str, value: &str, account_id: Option<&str>) -> Result<()> {
+        match account_id {
+            Some(account_id) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO account_settings (account_id, key, value, updated_at)
+                    VALUES (?, ?, ?, CURRENT_TIMESTAMP)
+                    ON CONFLICT(account_id, key) DO UPDATE SET
+                        value = excluded.value,
+                        updated_at = CURRENT_TIMESTAMP
+                    "#,
+                )
+                .bind(account_id)
+                .bind(key)
+                .bind(value)
+                .execute(&mut *conn)
+                .await?;
+
+                debug!("Set setting {} = {} for {}", key, value, account_id);
+            }
+            None => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO settings (key, value, updated_at)
+                    VALUES (?, ?, CURRENT_TIMESTAMP)
+                    ON CONFLICT(key) DO UPDATE SET
+                        value = excluded.value,
+                        updated_at = CURRENT_TIMESTAMP
+                    "#,
+                )
+                .bind(key)
+                .bind(value)
+                .execute(&mut *conn)
+                .await?;
+
+                debug!("Set setting {} = {}", key, value);
+            }
+        }
 
-        debug!("Set setting {} = {}", key, value);
         Ok(())
     }
 
-    /// Delete a setting
-    pub async fn delete_setting(&self, key: &str) -> Result<()> {
-        sqlx::query("DELETE FROM settings WHERE key = ?")
-            .bind(key)
-            .execute(&self.pool)
-            .await?;
+    /// Delete a setting, for an account only if one is given
+    ///
+    /// Deleting an account's value makes it fall back to the global one.
+    pub async fn delete_setting(&self, key: &str, account_id: Option<&str>) -> Result<()> {
+        match account_id {
+            Some(account_id) => {
+                sqlx::query("DELETE FROM account_settings WHERE account_id = ? AND key = ?")
+                    .bind(account_id)
+                    .bind(key)
+                    .execute(&self.pool)
+                    .await?;
+
+                debug!("Deleted setting {} for {}", key, account_id);
+            }
+            None => {
+                sqlx::query("DELETE FROM settings WHERE key = ?")
+                    .bind(key)
+                    .execute(&self.pool)
+                    .await?;
+
+                debug!("Deleted setting {}", key);
+            }
+        }
 
         Ok(())
     }
 
-    /// Get all settings as a map
-    pub async fn get_all_settings(&self) -> Result<HashMap<String, String>> {
+    /// Get all settings as a map, as seen by an account if one is given
+    pub async fn get_all_settings(&self, account_id: Option<&str>) -> Result<HashMap<String, String>> {
         let rows: Vec<(String, String)> =
             sqlx::query_as("SELECT key, value FROM settings")
                 .fetch_all(&self.pool)
                 .await?;
+        let mut settings: HashMap<String, String> = rows.into_iter().collect();
+
+        if let Some(account_id) = account_id {
+            let rows: Vec<(String, String)> =
+                sqlx::query_as("SELECT key, value FROM account_settings WHERE account_id = ?")
+                    .bind(account_id)
+                    .fetch_all(&self.pool)
+                    .await?;
+            settings.extend(rows);
+        }
+
+        Ok(settings)
+    }
+}
 
-        Ok(rows.into_iter().collect())
+/// Build an app registration from an `oauth_apps` row
+fn oauth_app_from_row((client_id, client_secret, redirect_uri, instance_url, software): OAuthAppRow) -> OAuthApp {
+    OAuthApp {
+        client_id,
+        client_secret,
+        redirect_uri,
+        instance_url,
+        software: ServerSoftware::from_name(&software),
     }
 }
 
@@ -343,3 +651,13 @@ fn get_db_path() -> PathBuf {
         .join("Blindodon")
         .join("cache.db")
 }
+
+#[cfg(test)]
+impl CacheManager {
+    /// Open a cache in a new temporary directory, returned for cleanup
+    pub(super) async fn temporary() -> (Self, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("blindodon-test-{}", uuid::Uuid::new_v4()));
+        let cache = Self::with_path(&dir.join("cache.db")).await.unwrap();
+        (cache, dir)
+    }
+}
@@ -0,0 +1,522 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Cached timelines with gap markers
+//!
+//! Every fetched page of a timeline is stored per account and timeline.
+//! A page is known to be continuous from one end to the other, but not
+//! always with the posts already cached next to it: a `since_id` page
+//! that comes back full holds only the newest posts, and whatever was
+//! posted between it and `since_id` has not been seen. Such stretches are
+//! recorded as gaps, each between the two cached posts on either side.
+//!
+//! Where two cached posts meet without a gap between them, nothing is
+//! missing. Storing a page closes or shrinks the gaps it overlaps and
+//! opens new ones at the ends whose continuity it cannot vouch for, so
+//! that posts are never silently skipped.
+//!
+//! Posts are ordered by ID. Snowflake IDs grow in length over the
+//! lifetime of a server, so IDs are zero-padded into a sort key that
+//! orders correctly as text; the fixed-width IDs of other servers keep
+//! their order when padded.
+
+use anyhow::Result;
+use sqlx::{Sqlite, Transaction};
+use tracing::debug;
+
+use super::CacheManager;
+use crate::models::{Post, TimelineGap, TimelineRequest, TimelineResponse};
+
+/// Width post IDs are padded to in sort keys
+const SORT_KEY_WIDTH: usize = 32;
+
+/// Columns of a gap row: `newer_key, newer_id, older_key, older_id`
+type GapRow = (String, String, String, String);
+
+/// Sort key of a post ID
+fn sort_key(id: &str) -> String {
+    format!("{:0>width$}", id, width = SORT_KEY_WIDTH)
+}
+
+/// A post ID at the edge of a stretch of timeline
+struct Bound {
+    key: String,
+    id: String,
+}
+
+impl Bound {
+    fn of(id: &str) -> Self {
+        Self { key: sort_key(id), id: id.to_string() }
+    }
+}
+
+/// Stretch of timeline a fetched page is known to cover completely
+///
+/// A missing bound is the head or the end of the timeline. An open edge
+/// is the page's own newest or oldest post, past which the server may
+/// have posts the page did not include.
+struct Coverage {
+    upper: Option<Bound>,
+    lower: Option<Bound>,
+    open_above: bool,
+    open_below: bool,
+}
+
+impl Coverage {
+    /// Work out what a page fetched with `request` covers
+    fn of(request: &TimelineRequest, posts: &[Post]) -> Self {
+        let full = !posts.is_empty() && posts.len() >= request.limit() as usize;
+        let newest = posts.iter().map(|p| &p.id).max_by_key(|id| sort_key(id)).map(|id| Bound::of(id));
+        let oldest = posts.iter().map(|p| &p.id).min_by_key(|id| sort_key(id)).map(|id| Bound::of(id));
+
+        // A `min_id` page starts right above `min_id`, and reaches `max_id`
+        // or the head unless it came back full
+        if let Some(min_id) = &request.min_id {
+            return if full {
+                Self { upper: newest, lower: Some(Bound::of(min_id)), open_above: true, open_below: false }
+            } else {
+                Self {
+                    upper: request.max_id.as_deref().map(Bound::of),
+                    lower: Some(Bound::of(min_id)),
+                    open_above: false,
+                    open_below: false,
+                }
+            };
+        }
+
+        // Any other page starts right below `max_id` or at the head, and
+        // only a `since_id` page that is not full is known to reach down to it
+        let (lower, open_below) = match &request.since_id {
+            Some(since_id) if !full => (Some(Bound::of(since_id)), false),
+            _ => (oldest, !posts.is_empty()),
+        };
+        Self {
+            upper: request.max_id.as_deref().map(Bound::of),
+            lower,
+            open_above: false,
+            open_below,
+        }
+    }
+}
+
+impl CacheManager {
+    /// Store a page of a timeline fetched with `request`
+    ///
+    /// Returns the gaps next to the page's posts once it is stored.
+    pub async fn store_timeline_page(
+        &self,
+        account_id: &str,
+        timeline: &str,
+        request: &TimelineRequest,
+        posts: &[Post],
+    ) -> Result<Vec<TimelineGap>> {
+        let coverage = Coverage::of(request, posts);
+        let upper_key = coverage.upper.as_ref().map(|bound| bound.key.as_str());
+        let lower_key = coverage.lower.as_ref().map(|bound| bound.key.as_str());
+
+        let mut tx = self.pool.begin().await?;
+
+        // Open edges that were cached before keep the continuity recorded for them
+        let upper_new = coverage.open_above && !is_cached(&mut tx, account_id, timeline, upper_key).await?;
+        let lower_new = coverage.open_below && !is_cached(&mut tx, account_id, timeline, lower_key).await?;
+
+        // Cached posts the server no longer returns have been deleted or filtered out
+        sqlx::query(
+            r#"
+            DELETE FROM timeline_posts
+            WHERE account_id = ? AND timeline = ?
+              AND (?3 IS NULL OR sort_key < ?3)
+              AND (?4 IS NULL OR sort_key > ?4)
+            "#,
+        )
+        .bind(account_id)
+        .bind(timeline)
+        .bind(upper_key)
+        .bind(lower_key)
+        .execute(&mut *tx)
+        .await?;
+
+        for post in posts {
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO timeline_posts (account_id, timeline, sort_key, post_id, created_at, data)
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(account_id)
+            .bind(timeline)
+            .bind(sort_key(&post.id))
+            .bind(&post.id)
+            .bind(post.created_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .bind(serde_json::to_string(post)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // Gaps overlapping the page shrink to the parts it does not cover
+        let overlapping: Vec<GapRow> = sqlx::query_as(
+            r#"
+            SELECT newer_key, newer_id, older_key, older_id FROM timeline_gaps
+            WHERE account_id = ? AND timeline = ?
+              AND (?3 IS NULL OR older_key < ?3)
+              AND (?4 IS NULL OR newer_key > ?4)
+            "#,
+        )
+        .bind(account_id)
+        .bind(timeline)
+        .bind(upper_key)
+        .bind(lower_key)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for (newer_key, newer_id, older_key, older_id) in overlapping {
+            sqlx::query("DELETE FROM timeline_gaps WHERE account_id = ? AND timeline = ? AND newer_key = ?")
+                .bind(account_id)
+                .bind(timeline)
+                .bind(&newer_key)
+                .execute(&mut *tx)
+                .await?;
+
+            if let Some(upper) = coverage.upper.as_ref().filter(|upper| upper.key < newer_key) {
+                let newer = Bound { key: newer_key, id: newer_id };
+                insert_gap(&mut tx, account_id, timeline, &newer, upper).await?;
+            }
+            if let Some(lower) = coverage.lower.as_ref().filter(|lower| older_key < lower.key) {
+                let older = Bound { key: older_key, id: older_id };
+                insert_gap(&mut tx, account_id, timeline, lower, &older).await?;
+            }
+        }
+
+        // New posts at an open edge may not reach the cached posts next to them
+        if let Some(upper) = coverage.upper.as_ref().filter(|_| upper_new) {
+            if let Some(newer) = neighbour(&mut tx, account_id, timeline, &upper.key, true).await? {
+                insert_gap(&mut tx, account_id, timeline, &newer, upper).await?;
+            }
+        }
+        if let Some(lower) = coverage.lower.as_ref().filter(|_| lower_new) {
+            if let Some(older) = neighbour(&mut tx, account_id, timeline, &lower.key, false).await? {
+                insert_gap(&mut tx, account_id, timeline, lower, &older).await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        debug!("Cached {} posts of {} for {}", posts.len(), timeline, account_id);
+        self.timeline_gaps(account_id, timeline, posts).await
+    }
+
+    /// Read a page of a timeline from the cache
+    ///
+    /// Honours the cursors of `request` the way the server would. Pages
+    /// read with `min_id` are returned newest first as well.
+    pub async fn cached_timeline(
+        &self,
+        account_id: &str,
+        timeline: &str,
+        request: &TimelineRequest,
+    ) -> Result<TimelineResponse> {
+        let limit = request.limit() as usize;
+        let ascending = request.min_id.is_some();
+        let after = request.min_id.as_deref().or(request.since_id.as_deref()).map(sort_key);
+
+        // One row more than needed tells whether the cache holds more
+        let rows: Vec<(String,)> = sqlx::query_as(&format!(
+            r#"
+            SELECT data FROM timeline_posts
+            WHERE account_id = ? AND timeline = ?
+              AND (?3 IS NULL OR sort_key < ?3)
+              AND (?4 IS NULL OR sort_key > ?4)
+            ORDER BY sort_key {}
+            LIMIT ?5
+            "#,
+            if ascending { "ASC" } else { "DESC" }
+        ))
+        .bind(account_id)
+        .bind(timeline)
+        .bind(request.max_id.as_deref().map(sort_key))
+        .bind(after)
+        .bind(limit as i64 + 1)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let has_more = rows.len() > limit;
+        let mut posts: Vec<Post> = rows
+            .into_iter()
+            .take(limit)
+            .filter_map(|(data,)| serde_json::from_str(&data).ok())
+            .collect();
+        if ascending {
+            posts.reverse();
+        }
+
+        let gaps = self.timeline_gaps(account_id, timeline, &posts).await?;
+        Ok(TimelineResponse {
+            max_id: posts.first().map(|p| p.id.clone()),
+            min_id: posts.last().map(|p| p.id.clone()),
+            posts,
+            has_more,
+            gaps,
+        })
+    }
+
+    /// Gaps next to any of `posts`, newest first
+    async fn timeline_gaps(&self, account_id: &str, timeline: &str, posts: &[Post]) -> Result<Vec<TimelineGap>> {
+        let keys: Vec<String> = posts.iter().map(|p| sort_key(&p.id)).collect();
+        let (Some(newest), Some(oldest)) = (keys.iter().max(), keys.iter().min()) else {
+            return Ok(vec![]);
+        };
+
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            r#"
+            SELECT newer_id, older_id FROM timeline_gaps
+            WHERE account_id = ? AND timeline = ? AND newer_key >= ? AND older_key <= ?
+            ORDER BY newer_key DESC
+            "#,
+        )
+        .bind(account_id)
+        .bind(timeline)
+        .bind(oldest)
+        .bind(newest)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(newer_id, older_id)| TimelineGap { newer_id, older_id })
+            .collect())
+    }
+}
+
+/// Whether a post is cached at `key`
+async fn is_cached(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_id: &str,
+    timeline: &str,
+    key: Option<&str>,
+) -> Result<bool> {
+    let row: Option<(i64,)> =
+        sqlx::query_as("SELECT 1 FROM timeline_posts WHERE account_id = ? AND timeline = ? AND sort_key = ?")
+            .bind(account_id)
+            .bind(timeline)
+            .bind(key)
+            .fetch_optional(&mut **tx)
+            .await?;
+    Ok(row.is_some())
+}
+
+/// The cached post right above (`newer`) or below a sort key
+async fn neighbour(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_id: &str,
+    timeline: &str,
+    key: &str,
+    newer: bool,
+) -> Result<Option<Bound>> {
+    let query = if newer {
+        "SELECT sort_key, post_id FROM timeline_posts WHERE account_id = ? AND timeline = ? AND sort_key > ? ORDER BY sort_key ASC LIMIT 1"
+    } else {
+        "SELECT sort_key, post_id FROM timeline_posts WHERE account_id = ? AND timeline = ? AND sort_key < ? ORDER BY sort_key DESC LIMIT 1"
+    };
+    let row: Option<(String, String)> = sqlx::query_as(query)
+        .bind(account_id)
+        .bind(timeline)
+        .bind(key)
+        .fetch_optional(&mut **tx)
+        .await?;
+    Ok(row.map(|(key, id)| Bound { key, id }))
+}
+
+/// Record a gap between two posts
+async fn insert_gap(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_id: &str,
+    timeline: &str,
+    newer: &Bound,
+    older: &Bound,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO timeline_gaps (account_id, timeline, newer_key, newer_id, older_key, older_id)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(account_id)
+    .bind(timeline)
+    .bind(&newer.key)
+    .bind(&newer.id)
+    .bind(&older.key)
+    .bind(&older.id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TimelineType;
+
+    const ACCOUNT: &str = "account";
+    const TIMELINE: &str = "home";
+
+    fn post(id: &str) -> Post {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "uri": format!("https://example.social/statuses/{}", id),
+            "account": {
+                "id": "1", "username": "alice", "acct": "alice", "display_name": "Alice",
+                "note": "", "url": "https://example.social/@alice", "avatar": "", "avatar_static": "",
+                "header": "", "header_static": "", "locked": false, "fields": [], "emojis": [],
+                "bot": false, "group": false, "created_at": "2025-01-01T00:00:00Z",
+                "statuses_count": 0, "followers_count": 0, "following_count": 0
+            },
+            "content": "", "spoiler_text": "", "visibility": "public", "sensitive": false,
+            "created_at": "2025-01-01T00:00:00Z", "media_attachments": [], "tags": [],
+            "mentions": [], "emojis": [], "reblogs_count": 0, "favourites_count": 0,
+            "replies_count": 0
+        }))
+        .unwrap()
+    }
+
+    fn posts(ids: &[&str]) -> Vec<Post> {
+        ids.iter().map(|id| post(id)).collect()
+    }
+
+    fn request(max_id: Option<&str>, since_id: Option<&str>, min_id: Option<&str>) -> TimelineRequest {
+        TimelineRequest {
+            timeline_type: TimelineType::Home,
+            limit: Some(3),
+            max_id: max_id.map(str::to_string),
+            since_id: since_id.map(str::to_string),
+            min_id: min_id.map(str::to_string),
+            cached: false,
+        }
+    }
+
+    fn gap(newer_id: &str, older_id: &str) -> (String, String) {
+        (newer_id.to_string(), older_id.to_string())
+    }
+
+    fn gaps(gaps: Vec<TimelineGap>) -> Vec<(String, String)> {
+        gaps.into_iter().map(|gap| (gap.newer_id, gap.older_id)).collect()
+    }
+
+    fn ids(posts: &[Post]) -> Vec<&str> {
+        posts.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[test]
+    fn sort_keys_order_ids_of_any_length() {
+        assert!(sort_key("9") < sort_key("10"));
+        assert!(sort_key("109876543210987654") < sort_key("110000000000000000"));
+    }
+
+    #[test]
+    fn head_page_is_open_below_only() {
+        let coverage = Coverage::of(&request(None, None, None), &posts(&["10", "9", "8"]));
+        assert!(coverage.upper.is_none());
+        assert_eq!(coverage.lower.unwrap().id, "8");
+        assert!(!coverage.open_above);
+        assert!(coverage.open_below);
+    }
+
+    #[test]
+    fn since_id_page_reaches_since_id_unless_full() {
+        let partial = Coverage::of(&request(None, Some("5"), None), &posts(&["7", "6"]));
+        assert_eq!(partial.lower.unwrap().id, "5");
+        assert!(!partial.open_below);
+
+        let full = Coverage::of(&request(None, Some("5"), None), &posts(&["9", "8", "7"]));
+        assert_eq!(full.lower.unwrap().id, "7");
+        assert!(full.open_below);
+    }
+
+    #[test]
+    fn min_id_page_is_open_above_when_full() {
+        let full = Coverage::of(&request(Some("20"), None, Some("10")), &posts(&["13", "12", "11"]));
+        assert_eq!(full.upper.unwrap().id, "13");
+        assert_eq!(full.lower.unwrap().id, "10");
+        assert!(full.open_above);
+        assert!(!full.open_below);
+
+        let partial = Coverage::of(&request(Some("20"), None, Some("10")), &posts(&["12", "11"]));
+        assert_eq!(partial.upper.unwrap().id, "20");
+        assert!(!partial.open_above);
+    }
+
+    #[tokio::test]
+    async fn gaps_open_and_close_as_pages_arrive() {
+        let (cache, dir) = CacheManager::temporary().await;
+
+        let stored = cache
+            .store_timeline_page(ACCOUNT, TIMELINE, &request(None, None, None), &posts(&["10", "9", "8"]))
+            .await
+            .unwrap();
+        assert!(stored.is_empty());
+
+        // A full page of newer posts may not reach the cached ones
+        let stored = cache
+            .store_timeline_page(ACCOUNT, TIMELINE, &request(None, Some("10"), None), &posts(&["20", "19", "18"]))
+            .await
+            .unwrap();
+        assert_eq!(gaps(stored), vec![gap("18", "10")]);
+
+        // Filling from the bottom shrinks the gap
+        let stored = cache
+            .store_timeline_page(ACCOUNT, TIMELINE, &request(Some("18"), None, Some("10")), &posts(&["13", "12", "11"]))
+            .await
+            .unwrap();
+        assert_eq!(gaps(stored), vec![gap("18", "13")]);
+
+        // A page that is not full closes it
+        let stored = cache
+            .store_timeline_page(ACCOUNT, TIMELINE, &request(Some("18"), None, Some("13")), &posts(&["15", "14"]))
+            .await
+            .unwrap();
+        assert!(stored.is_empty());
+
+        let mut page = request(None, None, None);
+        page.limit = Some(20);
+        let cached = cache.cached_timeline(ACCOUNT, TIMELINE, &page).await.unwrap();
+        assert_eq!(ids(&cached.posts), ["20", "19", "18", "15", "14", "13", "12", "11", "10", "9", "8"]);
+        assert!(cached.gaps.is_empty());
+        assert!(!cached.has_more);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refetched_page_drops_posts_the_server_no_longer_returns() {
+        let (cache, dir) = CacheManager::temporary().await;
+
+        cache
+            .store_timeline_page(ACCOUNT, TIMELINE, &request(None, None, None), &posts(&["10", "9", "8"]))
+            .await
+            .unwrap();
+        cache
+            .store_timeline_page(ACCOUNT, TIMELINE, &request(None, None, None), &posts(&["11", "10", "8"]))
+            .await
+            .unwrap();
+
+        let cached = cache.cached_timeline(ACCOUNT, TIMELINE, &request(None, None, None)).await.unwrap();
+        assert_eq!(ids(&cached.posts), ["11", "10", "8"]);
+
+        let newer = cache.cached_timeline(ACCOUNT, TIMELINE, &request(None, None, Some("8"))).await.unwrap();
+        assert_eq!(ids(&newer.posts), ["11", "10"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
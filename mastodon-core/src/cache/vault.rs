@@ -0,0 +1,502 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Vault state of the cache database
+//!
+//! The `vault` table records how the key is protected, along with a value
+//! sealed by it so a passphrase can be checked. Without a passphrase the
+//! key lives in `vault.key` next to the database and the vault unlocks on
+//! its own at startup; with one it stays locked until `unlock`.
+//!
+//! Tokens written by versions without a vault are sealed the first time
+//! the vault is unlocked. If the key file is lost, `reset` drops the
+//! tokens nothing can read any more and starts over with a new key.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sqlx::Sqlite;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use tracing::{error, info, warn};
+
+use super::CacheManager;
+use crate::crypto::vault::{self, VaultKey, DEFAULT_ITERATIONS};
+use crate::models::{VaultMode, VaultStatus};
+
+/// Plaintext sealed as the check value
+const CHECK_PLAINTEXT: &str = "blindodon-vault";
+
+/// Context the check value is sealed for
+const CHECK_CONTEXT: &str = "vault.check";
+
+/// Columns of the `accounts` table that hold sealed secrets
+const SECRET_COLUMNS: &[&str] = &["access_token", "refresh_token", "blindodon_pm_private_key"];
+
+/// Vault failures callers need to tell apart
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("The vault is locked; unlock it with vault.unlock")]
+    Locked,
+    #[error("Wrong passphrase")]
+    WrongPassphrase,
+    #[error("The vault is not protected by a passphrase")]
+    NoPassphrase,
+    #[error("The vault key file is missing or does not match the database; drop the stored tokens with vault.reset and log in again")]
+    KeyLost,
+    #[error("The vault is unlocked; only a locked vault can be reset")]
+    NotLocked,
+}
+
+/// Row of the `vault` table
+#[derive(sqlx::FromRow)]
+struct VaultMeta {
+    mode: String,
+    salt: Option<String>,
+    iterations: Option<i64>,
+    check_value: String,
+}
+
+impl VaultMeta {
+    fn mode(&self) -> VaultMode {
+        match self.mode.as_str() {
+            "passphrase" => VaultMode::Passphrase,
+            _ => VaultMode::KeyFile,
+        }
+    }
+}
+
+impl CacheManager {
+    /// Create the vault on first run, or unlock it if it has no passphrase
+    pub(super) async fn init_vault(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS vault (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                mode TEXT NOT NULL,
+                salt TEXT,
+                iterations INTEGER,
+                check_value TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        let Some(meta) = self.vault_meta().await? else {
+            info!("Creating vault key");
+            let key = VaultKey::generate()?;
+            key.write_file(&self.key_path)?;
+
+            let mut tx = self.pool.begin().await?;
+            write_meta(&mut tx, VaultMode::KeyFile, None, &key).await?;
+            tx.commit().await?;
+
+            self.set_key(Some(key));
+            return self.seal_plaintext_secrets().await;
+        };
+
+        match meta.mode() {
+            VaultMode::Passphrase => {
+                info!("Vault is locked until its passphrase is given");
+                Ok(())
+            }
+            VaultMode::KeyFile => {
+                let key = match matching_key_file(&self.key_path, &meta.check_value) {
+                    Ok(key) => key,
+                    // A passphrase change may have committed before its key file was moved into place
+                    Err(e) => match matching_key_file(&self.pending_key_path(), &meta.check_value) {
+                        Ok(key) => {
+                            self.install_pending_key();
+                            key
+                        }
+                        Err(_) => {
+                            error!("Vault stays locked, stored tokens are unreadable: {:#}", e);
+                            self.vault_key_lost.store(true, Ordering::Relaxed);
+                            return Ok(());
+                        }
+                    },
+                };
+
+                self.set_key(Some(key));
+                self.seal_plaintext_secrets().await
+            }
+        }
+    }
+
+    /// Whether stored secrets are out of reach
+    pub fn is_vault_locked(&self) -> bool {
+        self.vault_key.read().unwrap().is_none()
+    }
+
+    /// Error for a request that needs the vault while it is locked
+    pub fn vault_locked_error(&self) -> VaultError {
+        if self.vault_key_lost.load(Ordering::Relaxed) {
+            VaultError::KeyLost
+        } else {
+            VaultError::Locked
+        }
+    }
+
+    /// How the vault is protected and whether it is locked
+    pub async fn vault_status(&self) -> Result<VaultStatus> {
+        let meta = self.vault_meta().await?.context("Vault is not initialized")?;
+        Ok(VaultStatus {
+            mode: meta.mode(),
+            locked: self.is_vault_locked(),
+            key_lost: self.vault_key_lost.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Unlock a passphrase-protected vault
+    pub async fn unlock_vault(&self, passphrase: &str) -> Result<()> {
+        if self.vault_key_lost.load(Ordering::Relaxed) {
+            return Err(VaultError::KeyLost.into());
+        }
+        let meta = self.vault_meta().await?.context("Vault is not initialized")?;
+        if meta.mode() != VaultMode::Passphrase {
+            return Err(VaultError::NoPassphrase.into());
+        }
+
+        let salt = STANDARD.decode(meta.salt.as_deref().unwrap_or_default())?;
+        let iterations = u32::try_from(meta.iterations.unwrap_or_default())?;
+        let key = derive_key(passphrase.to_string(), salt, iterations).await?;
+        if key.open(&meta.check_value, CHECK_CONTEXT).is_err() {
+            warn!("Vault unlock failed: wrong passphrase");
+            return Err(VaultError::WrongPassphrase.into());
+        }
+
+        self.set_key(Some(key));
+        info!("Vault unlocked");
+        self.seal_plaintext_secrets().await
+    }
+
+    /// Forget the key of a passphrase-protected vault
+    pub async fn lock_vault(&self) -> Result<()> {
+        let meta = self.vault_meta().await?.context("Vault is not initialized")?;
+        if meta.mode() != VaultMode::Passphrase {
+            return Err(VaultError::NoPassphrase.into());
+        }
+
+        self.set_key(None);
+        info!("Vault locked");
+        Ok(())
+    }
+
+    /// Protect the vault with a new passphrase, or go back to a key file
+    ///
+    /// Every stored secret is sealed again with the new key in a single
+    /// transaction. A new key file is written and synced beside the old one
+    /// before that commits, then moved into place; if the move fails, the
+    /// next start picks the pending file up. An old key file is removed
+    /// after switching to a passphrase.
+    pub async fn set_vault_passphrase(&self, passphrase: Option<&str>) -> Result<()> {
+        if self.is_vault_locked() {
+            return Err(self.vault_locked_error().into());
+        }
+
+        let (mode, salt, new_key) = match passphrase {
+            Some(passphrase) => {
+                let salt = vault::generate_salt()?;
+                let key = derive_key(passphrase.to_string(), salt.to_vec(), DEFAULT_ITERATIONS).await?;
+                (VaultMode::Passphrase, Some(salt), key)
+            }
+            None => (VaultMode::KeyFile, None, VaultKey::generate()?),
+        };
+
+        if mode == VaultMode::KeyFile {
+            new_key.write_file(&self.pending_key_path())?;
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let rows: Vec<(String, String, Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT id, access_token, refresh_token, blindodon_pm_private_key FROM accounts",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for (id, access_token, refresh_token, private_key) in rows {
+            let values = [Some(access_token), refresh_token, private_key];
+            for (column, value) in SECRET_COLUMNS.iter().zip(values) {
+                let Some(value) = value else { continue };
+                let resealed = new_key.seal(&self.open(&id, column, &value)?, &secret_context(&id, column))?;
+                update_secret(&mut tx, &id, column, &resealed).await?;
+            }
+        }
+
+        let salt = salt.map(|salt| STANDARD.encode(salt));
+        write_meta(&mut tx, mode, salt.as_deref().map(|salt| (salt, DEFAULT_ITERATIONS)), &new_key).await?;
+        tx.commit().await?;
+        self.set_key(Some(new_key));
+
+        match mode {
+            VaultMode::KeyFile => self.install_pending_key(),
+            VaultMode::Passphrase => {
+                if let Err(e) = vault::remove_file(&self.key_path) {
+                    warn!("Old vault key file was left behind: {:#}", e);
+                }
+            }
+        }
+
+        info!("Vault is now protected by a {}", if mode == VaultMode::Passphrase { "passphrase" } else { "key file" });
+        Ok(())
+    }
+
+    /// Start over with a new key file, dropping the secrets the lost key sealed
+    ///
+    /// Only a locked vault can be reset, so this also covers a forgotten
+    /// passphrase. Accounts are kept but flagged for re-authentication;
+    /// their ids are returned.
+    pub async fn reset_vault(&self) -> Result<Vec<String>> {
+        if !self.is_vault_locked() {
+            return Err(VaultError::NotLocked.into());
+        }
+
+        let key = VaultKey::generate()?;
+        key.write_file(&self.pending_key_path())?;
+
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<(String,)> = sqlx::query_as("SELECT id FROM accounts")
+            .fetch_all(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE accounts SET access_token = '', refresh_token = NULL, blindodon_pm_private_key = NULL, needs_reauth = 1",
+        )
+        .execute(&mut *tx)
+        .await?;
+        write_meta(&mut tx, VaultMode::KeyFile, None, &key).await?;
+        tx.commit().await?;
+
+        self.set_key(Some(key));
+        self.vault_key_lost.store(false, Ordering::Relaxed);
+        self.install_pending_key();
+
+        warn!("Vault reset; {} account(s) must log in again", ids.len());
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    /// Seal a secret of an account row
+    pub(super) fn seal(&self, account_id: &str, column: &str, value: &str) -> Result<String> {
+        let key = self.vault_key.read().unwrap();
+        let key = key.as_ref().ok_or_else(|| self.vault_locked_error())?;
+        key.seal(value, &secret_context(account_id, column))
+    }
+
+    /// Open a secret of an account row
+    ///
+    /// Values from before the vault existed are passed through unchanged.
+    pub(super) fn open(&self, account_id: &str, column: &str, value: &str) -> Result<String> {
+        let key = self.vault_key.read().unwrap();
+        let key = key.as_ref().ok_or_else(|| self.vault_locked_error())?;
+        if !vault::is_sealed(value) {
+            return Ok(value.to_string());
+        }
+        key.open(value, &secret_context(account_id, column))
+    }
+
+    fn set_key(&self, key: Option<VaultKey>) {
+        *self.vault_key.write().unwrap() = key;
+    }
+
+    /// Where a new key file is written before the database switches to it
+    fn pending_key_path(&self) -> PathBuf {
+        self.key_path.with_extension("key.new")
+    }
+
+    /// Move a pending key file into place once the database uses its key
+    fn install_pending_key(&self) {
+        if let Err(e) = std::fs::rename(self.pending_key_path(), &self.key_path) {
+            warn!("Failed to move vault key file into {}, will retry at next start: {}", self.key_path.display(), e);
+        }
+    }
+
+    async fn vault_meta(&self) -> Result<Option<VaultMeta>> {
+        Ok(
+            sqlx::query_as("SELECT mode, salt, iterations, check_value FROM vault WHERE id = 1")
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    /// Seal secrets written before the vault existed
+    async fn seal_plaintext_secrets(&self) -> Result<()> {
+        let rows: Vec<(String, String, Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT id, access_token, refresh_token, blindodon_pm_private_key FROM accounts",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tx = self.pool.begin().await?;
+        let mut sealed = 0;
+        for (id, access_token, refresh_token, private_key) in rows {
+            let values = [Some(access_token), refresh_token, private_key];
+            for (column, value) in SECRET_COLUMNS.iter().zip(values) {
+                let Some(value) = value.filter(|value| !vault::is_sealed(value)) else { continue };
+                update_secret(&mut tx, &id, column, &self.seal(&id, column, &value)?).await?;
+                sealed += 1;
+            }
+        }
+        tx.commit().await?;
+
+        if sealed > 0 {
+            info!("Encrypted {} stored secret(s) that were kept in plaintext", sealed);
+        }
+        Ok(())
+    }
+}
+
+/// Read a key file, making sure it is the key the database was sealed with
+fn matching_key_file(path: &Path, check_value: &str) -> Result<VaultKey> {
+    let key = VaultKey::read_file(path)?;
+    key.open(check_value, CHECK_CONTEXT)
+        .with_context(|| format!("Vault key file {} does not match the database", path.display()))?;
+    Ok(key)
+}
+
+/// Associated data binding a sealed secret to its row and column
+fn secret_context(account_id: &str, column: &str) -> String {
+    format!("accounts.{}.{}", column, account_id)
+}
+
+/// Derive a passphrase key off the async runtime, since PBKDF2 is slow on purpose
+pub(super) async fn derive_key(passphrase: String, salt: Vec<u8>, iterations: u32) -> Result<VaultKey> {
+    tokio::task::spawn_blocking(move || VaultKey::from_passphrase(&passphrase, &salt, iterations)).await?
+}
+
+async fn update_secret(tx: &mut sqlx::Transaction<'_, Sqlite>, account_id: &str, column: &str, value: &str) -> Result<()> {
+    // `column` is always one of SECRET_COLUMNS
+    sqlx::query(&format!("UPDATE accounts SET {} = ? WHERE id = ?", column))
+        .bind(value)
+        .bind(account_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+async fn write_meta(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    mode: VaultMode,
+    passphrase: Option<(&str, u32)>,
+    key: &VaultKey,
+) -> Result<()> {
+    let mode = match mode {
+        VaultMode::KeyFile => "key_file",
+        VaultMode::Passphrase => "passphrase",
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO vault (id, mode, salt, iterations, check_value)
+        VALUES (1, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            mode = excluded.mode,
+            salt = excluded.salt,
+            iterations = excluded.iterations,
+            check_value = excluded.check_value
+        "#,
+    )
+    .bind(mode)
+    .bind(passphrase.map(|(salt, _)| salt))
+    .bind(passphrase.map(|(_, iterations)| iterations))
+    .bind(key.seal(CHECK_PLAINTEXT, CHECK_CONTEXT)?)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::StoredAccount;
+
+    fn account(id: &str) -> StoredAccount {
+        let mut account: StoredAccount = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "instance_url": "https://example.social",
+            "username": "alice",
+            "acct": "alice@example.social",
+            "display_name": "Alice",
+            "token_expires_at": null,
+            "added_at": "2025-01-01T00:00:00Z",
+            "last_used_at": "2025-01-01T00:00:00Z",
+            "is_default": true,
+            "avatar_url": null,
+            "blindodon_pm_public_key": null,
+        }))
+        .unwrap();
+        account.access_token = "token".to_string();
+        account
+    }
+
+    #[tokio::test]
+    async fn tokens_are_sealed_at_rest() {
+        let (cache, dir) = CacheManager::temporary().await;
+        cache.save_account(&account("1")).await.unwrap();
+
+        let (stored,): (String,) = sqlx::query_as("SELECT access_token FROM accounts WHERE id = '1'")
+            .fetch_one(&cache.pool)
+            .await
+            .unwrap();
+        assert!(vault::is_sealed(&stored));
+        assert_eq!(cache.get_account("1").await.unwrap().unwrap().access_token, "token");
+
+        let e = cache.unlock_vault("anything").await.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(VaultError::NoPassphrase)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn lost_key_file_can_be_reset() {
+        let (cache, dir) = CacheManager::temporary().await;
+        cache.save_account(&account("1")).await.unwrap();
+        drop(cache);
+        std::fs::remove_file(dir.join("vault.key")).unwrap();
+
+        let cache = CacheManager::with_path(&dir.join("cache.db")).await.unwrap();
+        let status = cache.vault_status().await.unwrap();
+        assert!(status.locked && status.key_lost);
+        assert!(matches!(cache.vault_locked_error(), VaultError::KeyLost));
+        let e = cache.get_account("1").await.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(VaultError::KeyLost)));
+
+        assert_eq!(cache.reset_vault().await.unwrap(), ["1"]);
+        let status = cache.vault_status().await.unwrap();
+        assert!(!status.locked && !status.key_lost);
+        let account = cache.get_account("1").await.unwrap().unwrap();
+        assert!(account.access_token.is_empty());
+        assert!(account.needs_reauth);
+
+        let e = cache.reset_vault().await.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(VaultError::NotLocked)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pending_key_file_is_picked_up() {
+        let (cache, dir) = CacheManager::temporary().await;
+        cache.save_account(&account("1")).await.unwrap();
+        drop(cache);
+        std::fs::rename(dir.join("vault.key"), dir.join("vault.key.new")).unwrap();
+
+        let cache = CacheManager::with_path(&dir.join("cache.db")).await.unwrap();
+        assert!(!cache.is_vault_locked());
+        assert!(dir.join("vault.key").exists());
+        assert_eq!(cache.get_account("1").await.unwrap().unwrap().access_token, "token");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -17,7 +17,10 @@
 //! Cryptography module for Blindodon PM (End-to-End Encrypted DMs)
 //!
 //! This module will implement Signal Protocol-like encryption for private messages.
-//! Implementation is planned for Phase 4.
+//! Implementation is planned for Phase 4. The `vault` submodule seals
+//! secrets stored in the cache database.
+
+pub mod vault;
 
 use anyhow::Result;
 
@@ -0,0 +1,224 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Encryption of secrets at rest
+//!
+//! Access tokens and private keys are sealed with AES-256-GCM before they
+//! are written to the cache database. The key either lives in a key file
+//! readable only by the current user, or is derived from a passphrase with
+//! PBKDF2, in which case it is only ever held in memory.
+//!
+//! A sealed value is `v1:` followed by the base64 of the nonce and the
+//! ciphertext. The associated data names the row and column the value
+//! belongs to, so sealed values cannot be swapped between them.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::num::NonZeroU32;
+use std::path::Path;
+use tracing::info;
+
+/// Prefix of sealed values
+const SEALED_PREFIX: &str = "v1:";
+
+/// Length of a vault key in bytes
+const KEY_LEN: usize = 32;
+
+/// Length of a passphrase salt in bytes
+const SALT_LEN: usize = 16;
+
+/// PBKDF2 iterations for newly set passphrases
+pub const DEFAULT_ITERATIONS: u32 = 600_000;
+
+/// Key that seals and opens secrets
+pub struct VaultKey([u8; KEY_LEN]);
+
+impl VaultKey {
+    /// Generate a new random key
+    pub fn generate() -> Result<Self> {
+        Ok(Self(random_bytes()?))
+    }
+
+    /// Derive a key from a passphrase
+    pub fn from_passphrase(passphrase: &str, salt: &[u8], iterations: u32) -> Result<Self> {
+        let iterations = NonZeroU32::new(iterations).context("Iteration count must not be zero")?;
+        let mut key = [0u8; KEY_LEN];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+        Ok(Self(key))
+    }
+
+    /// Read a key file written by `write_file`
+    pub fn read_file(path: &Path) -> Result<Self> {
+        let encoded = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read vault key file {}", path.display()))?;
+        let bytes = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|bytes| <[u8; KEY_LEN]>::try_from(bytes).ok())
+            .with_context(|| format!("Vault key file {} is corrupt", path.display()))?;
+        Ok(Self(bytes))
+    }
+
+    /// Write the key to a file readable only by the current user
+    pub fn write_file(&self, path: &Path) -> Result<()> {
+        use std::io::Write;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        remove_file(path)?;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Failed to create vault key file {}", path.display()))?;
+        file.write_all(STANDARD.encode(self.0).as_bytes())?;
+        file.sync_all()?;
+
+        info!("Vault key written to {}", path.display());
+        Ok(())
+    }
+
+    /// Seal a secret, binding it to `context`
+    pub fn seal(&self, plaintext: &str, context: &str) -> Result<String> {
+        let nonce_bytes: [u8; NONCE_LEN] = random_bytes()?;
+        let mut in_out = plaintext.as_bytes().to_vec();
+        self.aead_key()
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::from(context), &mut in_out)
+            .map_err(|_| anyhow::anyhow!("Failed to seal secret"))?;
+
+        let mut sealed = nonce_bytes.to_vec();
+        sealed.extend_from_slice(&in_out);
+        Ok(format!("{}{}", SEALED_PREFIX, STANDARD.encode(sealed)))
+    }
+
+    /// Open a secret sealed with `seal` for the same `context`
+    pub fn open(&self, sealed: &str, context: &str) -> Result<String> {
+        let mut bytes = sealed
+            .strip_prefix(SEALED_PREFIX)
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .filter(|bytes| bytes.len() >= NONCE_LEN)
+            .context("Value is not sealed")?;
+
+        let mut in_out = bytes.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&bytes).map_err(|_| anyhow::anyhow!("Invalid nonce"))?;
+        let plaintext = self
+            .aead_key()
+            .open_in_place(nonce, Aad::from(context), &mut in_out)
+            .map_err(|_| anyhow::anyhow!("Failed to open secret: wrong key or tampered value"))?;
+
+        Ok(String::from_utf8(plaintext.to_vec())?)
+    }
+
+    fn aead_key(&self) -> LessSafeKey {
+        // The key length always matches AES-256
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.0).expect("valid AES-256 key"))
+    }
+}
+
+/// Whether a stored value was sealed, as opposed to left from before encryption
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+/// Generate a random salt for a passphrase
+pub fn generate_salt() -> Result<[u8; SALT_LEN]> {
+    random_bytes()
+}
+
+/// Remove a key file, if there is one
+pub fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {}", path.display())),
+    }
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate random bytes"))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_open_round_trip() {
+        let key = VaultKey::generate().unwrap();
+        let sealed = key.seal("token", "accounts.access_token.1").unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(key.open(&sealed, "accounts.access_token.1").unwrap(), "token");
+    }
+
+    #[test]
+    fn sealing_twice_gives_different_values() {
+        let key = VaultKey::generate().unwrap();
+        assert_ne!(key.seal("token", "ctx").unwrap(), key.seal("token", "ctx").unwrap());
+    }
+
+    #[test]
+    fn open_fails_for_another_context_or_key() {
+        let key = VaultKey::generate().unwrap();
+        let sealed = key.seal("token", "accounts.access_token.1").unwrap();
+        assert!(key.open(&sealed, "accounts.access_token.2").is_err());
+        assert!(VaultKey::generate().unwrap().open(&sealed, "accounts.access_token.1").is_err());
+        assert!(key.open("token", "accounts.access_token.1").is_err());
+    }
+
+    #[test]
+    fn wrong_passphrase_cannot_open() {
+        let salt = generate_salt().unwrap();
+        let key = VaultKey::from_passphrase("correct horse", &salt, 1_000).unwrap();
+        let sealed = key.seal("token", "ctx").unwrap();
+
+        let same = VaultKey::from_passphrase("correct horse", &salt, 1_000).unwrap();
+        assert_eq!(same.open(&sealed, "ctx").unwrap(), "token");
+        let wrong = VaultKey::from_passphrase("battery staple", &salt, 1_000).unwrap();
+        assert!(wrong.open(&sealed, "ctx").is_err());
+        assert!(VaultKey::from_passphrase("correct horse", &salt, 0).is_err());
+    }
+
+    #[test]
+    fn key_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("blindodon-vault-{}.key", uuid::Uuid::new_v4()));
+        let key = VaultKey::generate().unwrap();
+        key.write_file(&path).unwrap();
+        let sealed = key.seal("token", "ctx").unwrap();
+
+        let read = VaultKey::read_file(&path).unwrap();
+        assert_eq!(read.open(&sealed, "ctx").unwrap(), "token");
+
+        remove_file(&path).unwrap();
+        assert!(VaultKey::read_file(&path).is_err());
+        remove_file(&path).unwrap();
+    }
+}
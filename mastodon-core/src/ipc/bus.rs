@@ -0,0 +1,66 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! State change broadcast shared by every connection
+//!
+//! Several clients may talk to the same core, such as the main window and a
+//! small notifier. When one of them changes shared state (switches account,
+//! favourites a post, changes a setting...), the handler publishes an event
+//! here and every authenticated connection forwards it to its client,
+//! including the one that made the change. Clients that fall too far
+//! behind miss events and should refresh their state.
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::{debug, error};
+
+use crate::models::IpcMessage;
+
+/// Number of events buffered for a connection that is slow to forward them
+const BUS_CAPACITY: usize = 256;
+
+/// Broadcasts state change events to every connection
+pub struct StateBus {
+    sender: broadcast::Sender<IpcMessage>,
+}
+
+impl StateBus {
+    /// Create a bus with no subscribers
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        Self { sender }
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<IpcMessage> {
+        self.sender.subscribe()
+    }
+
+    /// Send an event to every subscribed connection
+    pub fn publish<T: Serialize>(&self, event: &str, params: T) {
+        let params = match serde_json::to_value(params) {
+            Ok(params) => params,
+            Err(e) => {
+                error!("Failed to serialize {}: {}", event, e);
+                return;
+            }
+        };
+
+        // Sending only fails when no client is connected
+        let receivers = self.sender.send(IpcMessage::event(event, params)).unwrap_or(0);
+        debug!("Published {} to {} connection(s)", event, receivers);
+    }
+}
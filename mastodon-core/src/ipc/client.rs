@@ -0,0 +1,167 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Client side of the IPC protocol
+//!
+//! Used by `blindodon-cli` to drive a running core the same way the UI
+//! does: connect to the endpoint, authenticate with the session secret and
+//! send requests one at a time. Events arriving in between are skipped.
+
+use anyhow::{Context, Result};
+use futures::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+use crate::models::{methods, AuthenticateRequest, AuthenticateResponse, IpcMessage, MessageType};
+
+use super::endpoint;
+use super::server::DEFAULT_MAX_FRAME_SIZE;
+use super::session;
+use super::transport::{self, Frame, FrameReader, FrameWriter, Framing};
+
+/// Where to reach the core
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// Named pipe name (Windows) or socket path (Unix) of the core
+    pub endpoint: String,
+    /// File holding the session secret (`None` for the core's default)
+    pub secret_file: Option<PathBuf>,
+}
+
+impl ClientConfig {
+    /// Build a configuration matching a core started with the same environment
+    ///
+    /// Honours `BLINDODON_SOCKET` and `BLINDODON_SECRET_FILE` like the core.
+    pub fn from_env() -> Self {
+        let endpoint = std::env::var("BLINDODON_SOCKET")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(endpoint::default_endpoint);
+        let secret_file = std::env::var("BLINDODON_SECRET_FILE")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from);
+
+        Self { endpoint, secret_file }
+    }
+
+    /// The secret file to read
+    fn secret_path(&self) -> PathBuf {
+        self.secret_file
+            .clone()
+            .unwrap_or_else(|| session::default_secret_path(&self.endpoint))
+    }
+}
+
+/// An authenticated connection to a running core
+pub struct IpcClient {
+    frames_in: FrameReader,
+    frames_out: FrameWriter,
+}
+
+impl IpcClient {
+    /// Connect to the core and authenticate
+    pub async fn connect(config: &ClientConfig) -> Result<Self> {
+        let secret_path = config.secret_path();
+        let secret = std::fs::read_to_string(&secret_path)
+            .with_context(|| format!("Failed to read session secret from {} (is the core running?)", secret_path.display()))?;
+
+        let (frames_in, frames_out) = open(&config.endpoint)
+            .await
+            .with_context(|| format!("Failed to connect to the core at {}", config.endpoint))?;
+        let mut client = Self { frames_in, frames_out };
+
+        let response: AuthenticateResponse = client
+            .request(methods::SYSTEM_AUTHENTICATE, &AuthenticateRequest { secret: secret.trim().to_string() })
+            .await?;
+        if !response.authenticated {
+            anyhow::bail!("The core did not accept the session secret");
+        }
+
+        Ok(client)
+    }
+
+    /// Call a method with typed parameters and result
+    pub async fn request<P, R>(&mut self, method: &str, params: &P) -> Result<R>
+    where
+        P: Serialize,
+        R: DeserializeOwned,
+    {
+        let result = self.call(method, serde_json::to_value(params)?).await?;
+        serde_json::from_value(result).with_context(|| format!("Unexpected result from {}", method))
+    }
+
+    /// Call a method and wait for its result
+    ///
+    /// An error response from the core is returned as an error.
+    pub async fn call(&mut self, method: &str, params: Value) -> Result<Value> {
+        let request = IpcMessage::request(method, Some(params));
+        let id = request.id.clone();
+        self.frames_out.send(serde_json::to_string(&request)?).await?;
+
+        loop {
+            let frame = match self.frames_in.next().await {
+                Some(frame) => frame?,
+                None => anyhow::bail!("The core closed the connection"),
+            };
+            let Frame::Message(text) = frame else {
+                anyhow::bail!("The core sent a message larger than {} bytes", DEFAULT_MAX_FRAME_SIZE);
+            };
+
+            let msg: IpcMessage = serde_json::from_str(&text).context("The core sent an invalid message")?;
+            if msg.message_type != MessageType::Response || msg.id != id {
+                continue;
+            }
+
+            return match msg.error {
+                Some(error) => Err(anyhow::anyhow!("{} (error {})", error.message, error.code)),
+                None => Ok(msg.result.unwrap_or(Value::Null)),
+            };
+        }
+    }
+}
+
+/// Open a connection to the endpoint
+#[cfg(windows)]
+async fn open(endpoint: &str) -> Result<(FrameReader, FrameWriter)> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+
+    /// Returned while every pipe instance is busy
+    const ERROR_PIPE_BUSY: i32 = 231;
+
+    let pipe = loop {
+        match ClientOptions::new().open(endpoint) {
+            Ok(pipe) => break pipe,
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let (reader, writer) = tokio::io::split(pipe);
+    Ok(transport::stream_framed(reader, writer, Framing::Lines, DEFAULT_MAX_FRAME_SIZE))
+}
+
+/// Open a connection to the endpoint
+#[cfg(not(windows))]
+async fn open(endpoint: &str) -> Result<(FrameReader, FrameWriter)> {
+    let stream = tokio::net::UnixStream::connect(endpoint).await?;
+    let (reader, writer) = stream.into_split();
+    Ok(transport::stream_framed(reader, writer, Framing::Lines, DEFAULT_MAX_FRAME_SIZE))
+}
@@ -0,0 +1,491 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Per-connection message loop
+//!
+//! Every request read from a client is dispatched on its own task, so a slow
+//! call such as `media.upload` never holds up later requests on the same
+//! connection. Responses are written back as soon as they are ready and may
+//! arrive out of order; clients match them to requests by `IpcMessage.id`.
+//! Streaming events share the same outbound queue as responses. A running
+//! request can be aborted with `system.cancel`, after which it is answered
+//! with a REQUEST_CANCELLED error.
+//!
+//! Nothing reaches the `MessageHandler` until the client has authenticated
+//! with the session secret in its first message. That message also decides
+//! whether the connection speaks the native envelope or JSON-RPC 2.0, in
+//! which case batches are answered with a single array. The secret must
+//! come in a request of its own; a batch as the first message is refused.
+//!
+//! Once authenticated, a connection also forwards the state change events
+//! published by requests on any connection, so every client stays in sync.
+
+use anyhow::Result;
+use futures::future::{AbortHandle, Abortable};
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, watch, Mutex, Semaphore};
+use tracing::{debug, error, info, warn};
+
+use crate::models::{error_codes, events, methods, IpcError, IpcMessage, ShutdownEvent, TimelineType};
+use crate::streaming::StreamManager;
+
+use super::handler::MessageHandler;
+use super::protocol::{Dialect, Inbound};
+use super::recorder::ConnectionRecorder;
+use super::server::{wait_for_shutdown, ServerConfig};
+use super::session::{SessionAuth, Transport};
+use super::transport::{Frame, FrameReader, FrameWriter};
+
+/// Number of outgoing messages that may be queued for the writer
+const OUTBOUND_QUEUE_SIZE: usize = 64;
+
+/// A stream subscription: the account it streams for and the timeline
+pub type StreamKey = (Option<String>, TimelineType);
+
+/// State belonging to a single client connection
+pub struct ConnectionContext {
+    /// Sink for events pushed to this client
+    events: mpsc::Sender<IpcMessage>,
+    /// Timeline streams this client is subscribed to (`None` once closed)
+    streams: Mutex<Option<HashMap<StreamKey, Arc<StreamManager>>>>,
+    /// Abort handles of running requests with their generation, keyed by
+    /// request ID
+    in_flight: std::sync::Mutex<HashMap<String, (u64, AbortHandle)>>,
+    /// Generation given to the next registered request
+    next_generation: AtomicU64,
+}
+
+impl ConnectionContext {
+    /// Create a new context writing events to the given sink
+    pub fn new(events: mpsc::Sender<IpcMessage>) -> Self {
+        Self {
+            events,
+            streams: Mutex::new(Some(HashMap::new())),
+            in_flight: std::sync::Mutex::new(HashMap::new()),
+            next_generation: AtomicU64::new(0),
+        }
+    }
+
+    /// Get a sender for pushing events to this client
+    pub fn event_sender(&self) -> mpsc::Sender<IpcMessage> {
+        self.events.clone()
+    }
+
+    /// Register a stream for an account's timeline
+    ///
+    /// Returns `false` if the client is already subscribed to that timeline
+    /// or the connection is closing.
+    pub async fn add_stream(&self, key: StreamKey, stream: Arc<StreamManager>) -> bool {
+        let mut streams = self.streams.lock().await;
+        match streams.as_mut() {
+            Some(streams) if !streams.contains_key(&key) => {
+                streams.insert(key, stream);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forget a stream that ended on its own
+    pub async fn remove_stream(&self, key: &StreamKey, stream: &Arc<StreamManager>) {
+        if let Some(streams) = self.streams.lock().await.as_mut() {
+            if streams.get(key).is_some_and(|s| Arc::ptr_eq(s, stream)) {
+                streams.remove(key);
+            }
+        }
+    }
+
+    /// Stop and forget the stream for an account's timeline
+    ///
+    /// Returns `false` if the client was not subscribed to that timeline.
+    pub async fn stop_stream(&self, key: &StreamKey) -> bool {
+        let stream = self
+            .streams
+            .lock()
+            .await
+            .as_mut()
+            .and_then(|streams| streams.remove(key));
+
+        match stream {
+            Some(stream) => {
+                stream.stop_all();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Track a running request so it can be cancelled
+    ///
+    /// Returns the generation identifying this request among those reusing
+    /// its ID, or `None` if a request with the same ID is already running.
+    fn register_request(&self, id: &str, handle: AbortHandle) -> Option<u64> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight.contains_key(id) {
+            return None;
+        }
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        in_flight.insert(id.to_string(), (generation, handle));
+        Some(generation)
+    }
+
+    /// Stop tracking a request that has finished
+    ///
+    /// A cancelled request is no longer tracked by the time it finishes, and
+    /// its ID may already belong to a new request, so the entry is only
+    /// removed if it is still of the same generation.
+    fn unregister_request(&self, id: &str, generation: u64) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight.get(id).is_some_and(|(current, _)| *current == generation) {
+            in_flight.remove(id);
+        }
+    }
+
+    /// Abort a running request
+    ///
+    /// Returns `false` if no request with this ID is running.
+    pub fn cancel_request(&self, id: &str) -> bool {
+        match self.in_flight.lock().unwrap().remove(id) {
+            Some((_, handle)) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop every stream and running request, and refuse new streams
+    pub async fn close(&self) {
+        if let Some(streams) = self.streams.lock().await.take() {
+            for stream in streams.into_values() {
+                stream.stop_all();
+            }
+        }
+
+        for (_, (_, handle)) in self.in_flight.lock().unwrap().drain() {
+            handle.abort();
+        }
+    }
+}
+
+/// Serve a single client connection until it disconnects
+pub async fn serve_connection(
+    mut frames_in: FrameReader,
+    frames_out: FrameWriter,
+    handler: Arc<MessageHandler>,
+    auth: Option<(Arc<SessionAuth>, Transport)>,
+    config: &ServerConfig,
+    shutdown: watch::Receiver<Option<String>>,
+    recorder: Option<ConnectionRecorder>,
+) -> Result<()> {
+    // The first frame decides the dialect and must authenticate the client
+    let first = loop {
+        let frame = tokio::select! {
+            frame = frames_in.next() => frame,
+            _ = wait_for_shutdown(shutdown.clone()) => return Ok(()),
+        };
+        match frame.transpose()? {
+            None => {
+                info!("Client disconnected before authenticating");
+                return Ok(());
+            }
+            Some(Frame::Oversized) => {
+                warn!("Closing connection: first message exceeds {} bytes", config.max_frame_size);
+                return Ok(());
+            }
+            Some(Frame::Message(frame)) if frame.trim().is_empty() => continue,
+            Some(Frame::Message(frame)) => break frame.trim().to_string(),
+        }
+    };
+    let dialect = Dialect::detect(&first);
+    debug!("Client speaks {:?}", dialect);
+
+    let (outbound_tx, outbound_rx) = mpsc::channel::<IpcMessage>(OUTBOUND_QUEUE_SIZE);
+    let (batch_tx, batch_rx) = mpsc::channel::<Vec<IpcMessage>>(OUTBOUND_QUEUE_SIZE);
+    let writer_task = tokio::spawn(write_messages(frames_out, dialect, outbound_rx, batch_rx, recorder.clone()));
+
+    // The first frame carries the secret, so it is never logged. Trusted
+    // clients skip authentication and have their first frame handled
+    // like any other.
+    let mut pending = None;
+    match auth {
+        Some((auth, transport)) => {
+            let authenticated = match dialect.decode(&first) {
+                Inbound::Single(msg) => auth.authenticate(Some(&msg), transport).await,
+                Inbound::Batch(_) => {
+                    warn!("Closing connection: first message was a batch");
+                    Err(IpcMessage::response_err(
+                        "null",
+                        IpcError::new(
+                            error_codes::AUTHENTICATION_FAILED,
+                            format!("Batches are refused before authentication; send {} on its own first", methods::SYSTEM_AUTHENTICATE),
+                        ),
+                    ))
+                }
+                Inbound::Invalid(_) => auth.authenticate(None, transport).await,
+            };
+            match authenticated {
+                Ok(response) => {
+                    let _ = outbound_tx.send(response).await;
+                }
+                Err(response) => {
+                    let _ = outbound_tx.send(response).await;
+                    drop(outbound_tx);
+                    drop(batch_tx);
+                    return writer_task.await?;
+                }
+            }
+        }
+        None => pending = Some(first),
+    }
+
+    let context = Arc::new(ConnectionContext::new(outbound_tx.clone()));
+    let state_events = tokio::spawn(forward_state_events(handler.subscribe_state(), context.event_sender()));
+    let dispatcher = Dispatcher {
+        handler,
+        context: context.clone(),
+        in_flight: Arc::new(Semaphore::new(config.max_in_flight_requests)),
+        max_in_flight: config.max_in_flight_requests,
+    };
+
+    loop {
+        let frame = match pending.take() {
+            Some(first) => Some(Ok(Frame::Message(first))),
+            None => tokio::select! {
+                frame = frames_in.next() => frame,
+                _ = wait_for_shutdown(shutdown.clone()) => {
+                    info!("Closing connection for shutdown");
+                    break;
+                }
+            },
+        };
+
+        match frame {
+            None => {
+                info!("Client disconnected");
+                break;
+            }
+            Some(Ok(Frame::Oversized)) => {
+                warn!("Discarded a message over {} bytes", config.max_frame_size);
+                let _ = outbound_tx.send(dialect.oversized(config.max_frame_size)).await;
+            }
+            Some(Ok(Frame::Message(frame))) => {
+                let trimmed = frame.trim();
+                if trimmed.is_empty() {
+                    continue;
+                }
+
+                // Frames carry secrets and passphrases, so only their
+                // method and ID are logged
+                let inbound = dialect.decode(trimmed);
+                match &inbound {
+                    Inbound::Single(msg) => debug!("Received {} ({})", msg.method.as_deref().unwrap_or("unknown"), msg.id),
+                    Inbound::Batch(items) => debug!("Received a batch of {} message(s)", items.len()),
+                    Inbound::Invalid(_) => debug!("Received an undecodable message"),
+                }
+                if let Some(recorder) = &recorder {
+                    match &inbound {
+                        Inbound::Single(msg) => recorder.inbound(msg),
+                        Inbound::Batch(items) => items.iter().flatten().for_each(|msg| recorder.inbound(msg)),
+                        Inbound::Invalid(_) => {}
+                    }
+                }
+
+                match inbound {
+                    Inbound::Single(msg) => {
+                        dispatcher.dispatch(msg, outbound_tx.clone()).await?;
+                    }
+                    Inbound::Batch(items) => {
+                        // Responses are gathered on a channel of their own and
+                        // written as one array once every entry has replied
+                        let (reply_tx, mut reply_rx) = mpsc::channel(items.len());
+                        for item in items {
+                            match item {
+                                Ok(msg) => dispatcher.dispatch(msg, reply_tx.clone()).await?,
+                                Err(response) => {
+                                    let _ = reply_tx.send(*response).await;
+                                }
+                            }
+                        }
+                        drop(reply_tx);
+
+                        let batch_tx = batch_tx.clone();
+                        tokio::spawn(async move {
+                            let mut responses = Vec::new();
+                            while let Some(response) = reply_rx.recv().await {
+                                responses.push(response);
+                            }
+                            let _ = batch_tx.send(responses).await;
+                        });
+                    }
+                    Inbound::Invalid(response) => {
+                        warn!("Failed to decode message: {:?}", response.error.as_ref().map(|e| &e.message));
+                        let _ = outbound_tx.send(response).await;
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                error!("Read error: {}", e);
+                break;
+            }
+        }
+    }
+
+    // On shutdown, running requests get until the deadline to deliver their
+    // responses. Otherwise nobody is left to read the results, so whatever
+    // is still running is aborted right away.
+    let shutdown_reason = shutdown.borrow().clone();
+    if shutdown_reason.is_some()
+        && tokio::time::timeout(config.shutdown_timeout, dispatcher.drain()).await.is_err()
+    {
+        warn!("Requests still running after {:?}; cancelling them", config.shutdown_timeout);
+    }
+
+    // The writer finishes once every request and stream has wound down
+    state_events.abort();
+    context.close().await;
+    if let Some(reason) = shutdown_reason {
+        let params = serde_json::to_value(ShutdownEvent { reason }).unwrap_or_default();
+        let _ = outbound_tx.send(IpcMessage::event(events::SHUTDOWN, params)).await;
+    }
+    drop(dispatcher);
+    drop(context);
+    drop(outbound_tx);
+    drop(batch_tx);
+    writer_task.await?
+}
+
+/// Forward state change events from the bus to this connection's client
+async fn forward_state_events(mut state_rx: broadcast::Receiver<IpcMessage>, events: mpsc::Sender<IpcMessage>) {
+    loop {
+        match state_rx.recv().await {
+            Ok(event) => {
+                if events.send(event).await.is_err() {
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Client fell behind and missed {} state change event(s)", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Runs requests of one connection on their own tasks
+struct Dispatcher {
+    handler: Arc<MessageHandler>,
+    context: Arc<ConnectionContext>,
+    /// Limits the number of concurrently running requests
+    in_flight: Arc<Semaphore>,
+    /// Number of permits in `in_flight`
+    max_in_flight: usize,
+}
+
+impl Dispatcher {
+    /// Wait until every running request has finished
+    async fn drain(&self) {
+        let _ = self.in_flight.acquire_many(self.max_in_flight as u32).await;
+    }
+
+    /// Start handling a request, sending its response to `reply_tx`
+    async fn dispatch(&self, msg: IpcMessage, reply_tx: mpsc::Sender<IpcMessage>) -> Result<()> {
+        // Wait for a free slot rather than reading ahead without bound.
+        // Cancellations skip the queue so they can reach a saturated
+        // connection.
+        let permit = if msg.method.as_deref() == Some(methods::SYSTEM_CANCEL) {
+            None
+        } else {
+            Some(self.in_flight.clone().acquire_owned().await?)
+        };
+
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let Some(generation) = self.context.register_request(&msg.id, abort_handle) else {
+            warn!("Rejecting duplicate request ID {}", msg.id);
+            let error_response = IpcMessage::response_err(
+                &msg.id,
+                IpcError::new(
+                    error_codes::INVALID_REQUEST,
+                    format!("Request {} is already in progress", msg.id),
+                ),
+            );
+            let _ = reply_tx.send(error_response).await;
+            return Ok(());
+        };
+
+        let handler = self.handler.clone();
+        let context = self.context.clone();
+
+        tokio::spawn(async move {
+            let id = msg.id.clone();
+            let handling = handler.handle_message(msg, &context);
+            let response = match Abortable::new(handling, abort_registration).await {
+                Ok(response) => response,
+                Err(_) => {
+                    info!("Request {} cancelled", id);
+                    IpcMessage::response_err(
+                        &id,
+                        IpcError::new(error_codes::REQUEST_CANCELLED, "Request cancelled"),
+                    )
+                }
+            };
+            context.unregister_request(&id, generation);
+            let _ = reply_tx.send(response).await;
+            drop(permit);
+        });
+
+        Ok(())
+    }
+}
+
+/// Write queued messages and batches to the client, one JSON document per frame
+async fn write_messages(
+    mut frames_out: FrameWriter,
+    dialect: Dialect,
+    mut outbound_rx: mpsc::Receiver<IpcMessage>,
+    mut batch_rx: mpsc::Receiver<Vec<IpcMessage>>,
+    recorder: Option<ConnectionRecorder>,
+) -> Result<()> {
+    loop {
+        let encoded = tokio::select! {
+            Some(message) = outbound_rx.recv() => {
+                if let Some(recorder) = &recorder {
+                    recorder.outbound(&message);
+                }
+                debug!("Sending {:?} {}", message.message_type, message.method.as_deref().unwrap_or(&message.id));
+                dialect.encode(&message)
+            }
+            Some(batch) = batch_rx.recv() => {
+                if let Some(recorder) = &recorder {
+                    batch.iter().for_each(|msg| recorder.outbound(msg));
+                }
+                debug!("Sending a batch of {} response(s)", batch.len());
+                dialect.encode_batch(&batch)
+            }
+            else => break,
+        };
+        let Some(message_json) = encoded else {
+            continue;
+        };
+
+        frames_out.send(message_json).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! IPC endpoint location and access control
+//!
+//! On Unix the socket lives in a directory only the current user can enter
+//! (`$XDG_RUNTIME_DIR`, or a private directory under the temp dir), is
+//! created with 0600 permissions, and every accepted peer must run as the
+//! same user. A socket that still has a live server behind it is never
+//! replaced.
+
+/// Named pipe name for Windows
+#[cfg(windows)]
+const DEFAULT_PIPE_NAME: &str = r"\\.\pipe\blindodon_ipc";
+
+/// Socket file name inside the per-user runtime directory
+#[cfg(not(windows))]
+const SOCKET_FILE_NAME: &str = "blindodon_ipc.sock";
+
+/// Default IPC endpoint for the current user
+#[cfg(windows)]
+pub fn default_endpoint() -> String {
+    DEFAULT_PIPE_NAME.to_string()
+}
+
+/// Default IPC endpoint for the current user
+///
+/// `$XDG_RUNTIME_DIR/blindodon_ipc.sock` where available, otherwise
+/// `<tmp>/blindodon-<uid>/blindodon_ipc.sock`.
+#[cfg(not(windows))]
+pub fn default_endpoint() -> String {
+    let dir = dirs::runtime_dir().unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("blindodon-{}", unix::current_uid()))
+    });
+
+    dir.join(SOCKET_FILE_NAME).to_string_lossy().into_owned()
+}
+
+#[cfg(not(windows))]
+pub use unix::{bind_socket, peer_is_current_user, remove_socket};
+
+#[cfg(not(windows))]
+mod unix {
+    use anyhow::{bail, Context, Result};
+    use std::io::ErrorKind;
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+    use std::path::Path;
+    use tokio::net::{UnixListener, UnixStream};
+    use tracing::{info, warn};
+
+    /// User ID of the running process
+    pub fn current_uid() -> u32 {
+        // SAFETY: getuid has no preconditions and cannot fail
+        unsafe { libc::getuid() }
+    }
+
+    /// Bind the IPC socket at `path` with owner-only permissions
+    ///
+    /// Fails if another server is listening on the socket or if something
+    /// other than a socket exists at the path. A socket left behind by a
+    /// server that is no longer running is removed first.
+    pub fn bind_socket(path: &Path) -> Result<UnixListener> {
+        if let Some(dir) = path.parent() {
+            ensure_private_dir(dir)?;
+        }
+
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) => {
+                if !metadata.file_type().is_socket() {
+                    bail!("{} exists and is not a socket; refusing to replace it", path.display());
+                }
+
+                match std::os::unix::net::UnixStream::connect(path) {
+                    Ok(_) => bail!(
+                        "Another Blindodon core is already listening on {}",
+                        path.display()
+                    ),
+                    Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                        info!("Removing stale socket {}", path.display());
+                        std::fs::remove_file(path)
+                            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+                    }
+                    Err(e) => {
+                        return Err(e)
+                            .with_context(|| format!("Failed to probe existing socket {}", path.display()));
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to inspect {}", path.display()));
+            }
+        }
+
+        let listener = UnixListener::bind(path).context("Failed to bind Unix socket")?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .context("Failed to restrict socket permissions")?;
+
+        Ok(listener)
+    }
+
+    /// Remove the socket file, if it is still there
+    pub fn remove_socket(path: &Path) {
+        if let Err(e) = std::fs::remove_file(path) {
+            if e.kind() != ErrorKind::NotFound {
+                warn!("Failed to remove socket {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Check that the peer of an accepted connection runs as the current user
+    pub fn peer_is_current_user(stream: &UnixStream) -> bool {
+        match stream.peer_cred() {
+            Ok(cred) if cred.uid() == current_uid() => true,
+            Ok(cred) => {
+                warn!("Rejecting IPC connection from uid {}", cred.uid());
+                false
+            }
+            Err(e) => {
+                warn!("Rejecting IPC connection with unknown credentials: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Create `dir` if needed and make sure no other user controls it
+    ///
+    /// Root-owned directories such as `/tmp` are accepted for explicitly
+    /// configured paths; the socket's own permissions protect it there.
+    fn ensure_private_dir(dir: &Path) -> Result<()> {
+        if !dir.exists() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .with_context(|| format!("Failed to create socket directory {}", dir.display()))?;
+        }
+
+        let metadata = std::fs::metadata(dir)
+            .with_context(|| format!("Failed to inspect socket directory {}", dir.display()))?;
+
+        if metadata.uid() != current_uid() && metadata.uid() != 0 {
+            bail!("Socket directory {} is owned by another user", dir.display());
+        }
+        if metadata.mode() & 0o077 != 0 {
+            warn!(
+                "Socket directory {} is accessible by other users; relying on socket permissions",
+                dir.display()
+            );
+        }
+
+        Ok(())
+    }
+}
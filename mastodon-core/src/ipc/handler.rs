@@ -16,65 +16,236 @@
 
 //! IPC message handler
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Weak};
 use chrono::Utc;
-use tokio::sync::RwLock;
+use serde::Deserialize;
+use tokio::sync::{broadcast, watch, RwLock};
 use tracing::{debug, error, info, warn};
 
-use crate::api::MastodonClient;
-use crate::cache::CacheManager;
+use crate::api::loopback::{self, LoopbackListener};
+use crate::api::{normalize_url, MastodonClient, PendingLogin, OOB_REDIRECT_URI};
+use crate::cache::{BackupError, CacheManager, VaultError};
 use crate::models::{
-    error_codes, methods,
-    IpcError, IpcMessage, MediaUploadRequest, NotificationRequest, StoredAccount,
-    TimelineRequest, TimelineType,
+    error_codes, events, methods,
+    AccountIdRequest, AccountSummary, CommonParams, AccountSwitchedEvent, AccountsResponse, AuthCallback, AuthCallbackResponse, AuthCompletedEvent, AuthExpiredEvent, BackupRequest, BackupResponse,
+    AuthRequest, AuthResponse, AuthenticateRequest, AuthenticateResponse, CancelRequest, CancelResponse, DescribeResponse, EmptyParams, FillGapRequest, HelloRequest, HelloResponse,
+    InstanceInfo, IpcError, IpcMessage, LogoutRequest, MediaAttachment, MediaUploadRequest, OAuthApp,
+    NewPost, NotificationDismissedEvent, NotificationEvent, NotificationIdRequest, NotificationRequest,
+    NotificationResponse, PingResponse, Post, PostActionEvent, PostDeletedEvent, PostEvent, PostIdRequest,
+    SettingChangedEvent, SettingDeleteRequest, SettingDeletedEvent, SettingGetRequest, SettingSetRequest, SettingsRequest, SettingValue, SettingsResponse, ShutdownEvent, ShutdownResponse, StoredAccount,
+    StreamConnectedEvent, StreamDisconnectedEvent, StreamRequest, StreamStartResponse,
+    StreamStopResponse, SuccessResponse, SwitchAccountResponse, TimelineRequest,
+    TimelineResponse, VaultSetPassphraseRequest, VaultStatus, VaultUnlockRequest, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
 };
+use crate::streaming::StreamManager;
 use crate::log_ipc;
 
+use super::bus::StateBus;
+use super::connection::ConnectionContext;
+use super::registry::{MethodRegistry, MethodRegistryBuilder, RequestContext};
+
 /// Handles incoming IPC messages and routes them to appropriate handlers
 pub struct MessageHandler {
     /// Active Mastodon client (if authenticated)
     client: RwLock<Option<Arc<MastodonClient>>>,
     /// Current account ID (if authenticated)
     current_account_id: RwLock<Option<String>>,
+    /// Clients of stored accounts, built from their tokens on first use
+    sessions: RwLock<HashMap<String, Arc<MastodonClient>>>,
     /// Cache manager for persistence
     cache: Arc<CacheManager>,
+    /// Registered methods and events
+    registry: MethodRegistry,
+    /// Set to the reason for shutting down to shut the server down
+    shutdown: watch::Sender<Option<String>>,
+    /// State changes broadcast to every connection
+    bus: StateBus,
+    /// The handler itself, for work that outlives a request
+    this: Weak<MessageHandler>,
 }
 
 impl MessageHandler {
     /// Create a new message handler with cache
-    pub fn new(cache: Arc<CacheManager>) -> Self {
-        Self {
+    ///
+    /// The `shutdown` request stores its reason in `shutdown`.
+    pub fn new(cache: Arc<CacheManager>, shutdown: watch::Sender<Option<String>>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
             client: RwLock::new(None),
             current_account_id: RwLock::new(None),
+            sessions: RwLock::new(HashMap::new()),
             cache,
-        }
+            registry: Self::build_registry(),
+            shutdown,
+            bus: StateBus::new(),
+            this: this.clone(),
+        })
+    }
+
+    /// Receive the state change events published from now on
+    pub fn subscribe_state(&self) -> broadcast::Receiver<IpcMessage> {
+        self.bus.subscribe()
+    }
+
+    /// Register every method and event the core implements
+    fn build_registry() -> MethodRegistry {
+        let mut registry = MethodRegistryBuilder::new();
+
+        // System methods
+        registry
+            .method(methods::SYSTEM_HELLO, "Negotiate the protocol version and list capabilities",
+                |h, _, p: HelloRequest| Box::pin(h.handle_system_hello(p)))
+            .method(methods::SYSTEM_DESCRIBE, "Describe every method and event with JSON Schemas",
+                |h, _, _: EmptyParams| Box::pin(h.handle_system_describe()))
+            .method(methods::SYSTEM_AUTHENTICATE, "Prove knowledge of the session secret; must be the first message",
+                |h, _, _: AuthenticateRequest| Box::pin(h.handle_system_authenticate()))
+            .method(methods::SYSTEM_CANCEL, "Cancel a running request on this connection",
+                |h, ctx, p: CancelRequest| Box::pin(h.handle_system_cancel(ctx, p)))
+            .method(methods::PING, "Check that the core is responsive",
+                |h, _, _: EmptyParams| Box::pin(h.handle_ping()))
+            .method(methods::SHUTDOWN, "Shut the core down",
+                |h, _, _: EmptyParams| Box::pin(h.handle_shutdown()));
+
+        // Authentication methods
+        registry
+            .method(methods::AUTH_START, "Start the OAuth flow for an instance",
+                |h, _, p: AuthRequest| Box::pin(h.handle_auth_start(p)))
+            .method(methods::AUTH_CALLBACK, "Complete the OAuth flow with an authorization code",
+                |h, _, p: AuthCallback| Box::pin(h.handle_auth_callback(p)))
+            .method(methods::AUTH_LOGOUT, "Log out of the active account",
+                |h, _, p: LogoutRequest| Box::pin(h.handle_auth_logout(p)))
+            .method(methods::AUTH_GET_ACCOUNTS, "List stored accounts",
+                |h, _, _: EmptyParams| Box::pin(h.handle_auth_get_accounts()))
+            .method(methods::AUTH_SWITCH_ACCOUNT, "Switch the active account",
+                |h, _, p: AccountIdRequest| Box::pin(h.handle_auth_switch_account(p)))
+            .method(methods::AUTH_DELETE_ACCOUNT, "Revoke the token of a stored account and remove it",
+                |h, _, p: AccountIdRequest| Box::pin(h.handle_auth_delete_account(p)));
+
+        // Vault methods
+        registry
+            .method(methods::VAULT_STATUS, "Report how stored tokens are protected and whether they are locked",
+                |h, _, _: EmptyParams| Box::pin(h.handle_vault_status()))
+            .method(methods::VAULT_UNLOCK, "Unlock stored tokens with the passphrase and restore the session",
+                |h, _, p: VaultUnlockRequest| Box::pin(h.handle_vault_unlock(p)))
+            .method(methods::VAULT_LOCK, "Lock stored tokens and end the session",
+                |h, _, _: EmptyParams| Box::pin(h.handle_vault_lock()))
+            .method(methods::VAULT_SET_PASSPHRASE, "Protect stored tokens with a passphrase, or remove it",
+                |h, _, p: VaultSetPassphraseRequest| Box::pin(h.handle_vault_set_passphrase(p)))
+            .method(methods::VAULT_RESET, "Drop stored tokens that can no longer be unlocked; every account must log in again",
+                |h, _, _: EmptyParams| Box::pin(h.handle_vault_reset()));
+
+        // Backup methods
+        registry
+            .method(methods::BACKUP_EXPORT, "Write stored accounts and settings to a passphrase-encrypted file",
+                |h, _, p: BackupRequest| Box::pin(h.handle_backup_export(p)))
+            .method(methods::BACKUP_IMPORT, "Merge stored accounts and settings from a passphrase-encrypted file",
+                |h, _, p: BackupRequest| Box::pin(h.handle_backup_import(p)));
+
+        // Settings methods
+        registry
+            .method(methods::SETTINGS_GET, "Get a setting",
+                |h, _, p: SettingGetRequest| Box::pin(h.handle_settings_get(p)))
+            .method(methods::SETTINGS_SET, "Set a setting",
+                |h, _, p: SettingSetRequest| Box::pin(h.handle_settings_set(p)))
+            .method(methods::SETTINGS_DELETE, "Delete a setting, or an account's own value so it falls back to the global one",
+                |h, _, p: SettingDeleteRequest| Box::pin(h.handle_settings_delete(p)))
+            .method(methods::SETTINGS_GET_ALL, "Get all settings",
+                |h, _, p: SettingsRequest| Box::pin(h.handle_settings_get_all(p)));
+
+        // Timeline methods
+        registry
+            .method(methods::TIMELINE_GET, "Fetch a page of a timeline",
+                |h, ctx, p: TimelineRequest| Box::pin(h.handle_timeline_get(ctx, p)))
+            .method(methods::TIMELINE_FILL_GAP, "Load the posts missing from a gap in a cached timeline",
+                |h, ctx, p: FillGapRequest| Box::pin(h.handle_timeline_fill_gap(ctx, p)))
+            .method(methods::TIMELINE_STREAM_START, "Subscribe this connection to a timeline stream",
+                |h, ctx, p: StreamRequest| Box::pin(h.handle_timeline_stream_start(ctx, p)))
+            .method(methods::TIMELINE_STREAM_STOP, "Unsubscribe this connection from a timeline stream",
+                |h, ctx, p: StreamRequest| Box::pin(h.handle_timeline_stream_stop(ctx, p)));
+
+        // Post methods
+        registry
+            .method(methods::POST_CREATE, "Publish a new post",
+                |h, ctx, p: NewPost| Box::pin(h.handle_post_create(ctx, p)))
+            .method(methods::POST_BOOST, "Boost a post",
+                |h, ctx, p: PostIdRequest| Box::pin(h.handle_post_action(ctx, p, "boost")))
+            .method(methods::POST_UNBOOST, "Undo a boost",
+                |h, ctx, p: PostIdRequest| Box::pin(h.handle_post_action(ctx, p, "unboost")))
+            .method(methods::POST_FAVOURITE, "Favourite a post",
+                |h, ctx, p: PostIdRequest| Box::pin(h.handle_post_action(ctx, p, "favourite")))
+            .method(methods::POST_UNFAVOURITE, "Undo a favourite",
+                |h, ctx, p: PostIdRequest| Box::pin(h.handle_post_action(ctx, p, "unfavourite")));
+
+        // Notification methods
+        registry
+            .method(methods::NOTIFICATIONS_GET, "Fetch a page of notifications",
+                |h, ctx, p: NotificationRequest| Box::pin(h.handle_notifications_get(ctx, p)))
+            .method(methods::NOTIFICATIONS_CLEAR, "Clear all notifications",
+                |h, ctx, _: EmptyParams| Box::pin(h.handle_notifications_clear(ctx)))
+            .method(methods::NOTIFICATIONS_DISMISS, "Dismiss a single notification",
+                |h, ctx, p: NotificationIdRequest| Box::pin(h.handle_notifications_dismiss(ctx, p)));
+
+        // Media methods
+        registry
+            .method(methods::MEDIA_UPLOAD, "Upload a media file",
+                |h, ctx, p: MediaUploadRequest| Box::pin(h.handle_media_upload(ctx, p)));
+
+        // Instance methods
+        registry
+            .method(methods::INSTANCE_GET, "Get information about the active instance",
+                |h, ctx, _: EmptyParams| Box::pin(h.handle_instance_get(ctx)));
+
+        // Events
+        registry
+            .event::<PostEvent>(events::NEW_POST, "A post arrived on a streamed timeline")
+            .event::<PostEvent>(events::POST_UPDATED, "A post on a streamed timeline was edited")
+            .event::<PostDeletedEvent>(events::POST_DELETED, "A post on a streamed timeline was deleted")
+            .event::<NotificationEvent>(events::NEW_NOTIFICATION, "A notification arrived on the home stream")
+            .event::<StreamConnectedEvent>(events::STREAM_CONNECTED, "A timeline stream connected")
+            .event::<StreamDisconnectedEvent>(events::STREAM_DISCONNECTED, "A timeline stream ended")
+            .event::<ShutdownEvent>(events::SHUTDOWN, "The core is shutting down; last message on the connection")
+            .event::<AccountSwitchedEvent>(events::ACCOUNT_SWITCHED, "A client switched, added or logged out of the active account")
+            .event::<PostActionEvent>(events::POST_ACTION, "A client boosted or favourited a post, or undid it")
+            .event::<SettingChangedEvent>(events::SETTING_CHANGED, "A client changed a setting")
+            .event::<SettingDeletedEvent>(events::SETTING_DELETED, "A client deleted a setting or an account's own value")
+            .event::<NotificationDismissedEvent>(events::NOTIFICATION_DISMISSED, "A client dismissed one or all notifications")
+            .event::<AuthCompletedEvent>(events::AUTH_COMPLETED, "A login started with loopback redirect finished or failed")
+            .event::<AuthExpiredEvent>(events::AUTH_EXPIRED, "The server rejected an account's token; it must log in again");
+
+        registry.build()
     }
 
     /// Initialize handler and restore saved session
     pub async fn initialize(&self) -> anyhow::Result<()> {
+        if self.cache.is_vault_locked() {
+            info!("Vault is locked; the session is restored once it is unlocked");
+            return Ok(());
+        }
+
         // Try to restore the default account
         if let Some(account) = self.cache.get_default_account().await? {
             info!("Restoring session for {}", account.acct);
 
-            match MastodonClient::from_token(&account.instance_url, &account.access_token) {
+            match MastodonClient::from_token(&account.instance_url, &account.access_token, account.software) {
                 Ok(client) => {
                     // Verify the token is still valid
                     match client.get_current_user().await {
                         Ok(user) => {
                             info!("Session restored for {}", user.acct);
-                            *self.client.write().await = Some(Arc::new(client));
-                            *self.current_account_id.write().await = Some(account.id.clone());
+                            self.activate(&account.id, Arc::new(client)).await;
 
                             // Update last used time
                             if let Err(e) = self.cache.set_default_account(&account.id).await {
                                 warn!("Failed to update last used time: {}", e);
                             }
                         }
+                        Err(e) if MastodonClient::is_token_rejected(&e) => {
+                            warn!("Saved token was rejected, will require re-authentication: {}", e);
+                            self.flag_needs_reauth(&account.id).await;
+                        }
                         Err(e) => {
-                            warn!(
-                                "Saved token invalid, will require re-authentication: {}",
-                                e
-                            );
+                            warn!("Failed to verify saved token: {}", e);
                         }
                     }
                 }
@@ -87,252 +258,601 @@ impl MessageHandler {
         Ok(())
     }
 
-    /// Handle an incoming IPC message
-    pub async fn handle_message(&self, msg: IpcMessage) -> IpcMessage {
+    /// Handle an incoming IPC message from the given connection
+    pub async fn handle_message(&self, msg: IpcMessage, conn: &Arc<ConnectionContext>) -> IpcMessage {
         let method = msg.method.as_deref().unwrap_or("unknown");
         log_ipc!(request, method, &msg.id);
 
-        let result = match method {
-            // System methods
-            methods::PING => self.handle_ping(&msg).await,
-            methods::SHUTDOWN => self.handle_shutdown(&msg).await,
-
-            // Authentication methods
-            methods::AUTH_START => self.handle_auth_start(&msg).await,
-            methods::AUTH_CALLBACK => self.handle_auth_callback(&msg).await,
-            methods::AUTH_LOGOUT => self.handle_auth_logout(&msg).await,
-            methods::AUTH_GET_ACCOUNTS => self.handle_auth_get_accounts(&msg).await,
-            methods::AUTH_SWITCH_ACCOUNT => self.handle_auth_switch_account(&msg).await,
-            methods::AUTH_DELETE_ACCOUNT => self.handle_auth_delete_account(&msg).await,
-
-            // Settings methods
-            methods::SETTINGS_GET => self.handle_settings_get(&msg).await,
-            methods::SETTINGS_SET => self.handle_settings_set(&msg).await,
-            methods::SETTINGS_GET_ALL => self.handle_settings_get_all(&msg).await,
-
-            // Timeline methods
-            methods::TIMELINE_GET => self.handle_timeline_get(&msg).await,
-
-            // Post methods
-            methods::POST_CREATE => self.handle_post_create(&msg).await,
-            methods::POST_BOOST => self.handle_post_boost(&msg).await,
-            methods::POST_UNBOOST => self.handle_post_unboost(&msg).await,
-            methods::POST_FAVOURITE => self.handle_post_favourite(&msg).await,
-            methods::POST_UNFAVOURITE => self.handle_post_unfavourite(&msg).await,
-
-            // Notification methods
-            methods::NOTIFICATIONS_GET => self.handle_notifications_get(&msg).await,
-            methods::NOTIFICATIONS_CLEAR => self.handle_notifications_clear(&msg).await,
-            methods::NOTIFICATIONS_DISMISS => self.handle_notifications_dismiss(&msg).await,
-
-            // Media methods
-            methods::MEDIA_UPLOAD => self.handle_media_upload(&msg).await,
-
-            // Instance methods
-            methods::INSTANCE_GET => self.handle_instance_get(&msg).await,
-
-            // Unknown method
-            _ => {
-                warn!("Unknown method: {}", method);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::METHOD_NOT_FOUND, format!("Unknown method: {}", method)),
-                )
+        let ctx = RequestContext {
+            conn: conn.clone(),
+            account_id: msg
+                .params
+                .as_ref()
+                .filter(|params| params.is_object())
+                .and_then(|params| CommonParams::deserialize(params).ok())
+                .and_then(|common| common.account_id),
+        };
+
+        // Stored accounts cannot be read without their tokens
+        let result = if method.starts_with("auth.") && self.cache.is_vault_locked() {
+            Err(vault_error(self.cache.vault_locked_error().into()))
+        } else {
+            match self.registry.call(method, self, &ctx, msg.params.clone()).await {
+                Some(result) => result,
+                None => {
+                    warn!("Unknown method: {}", method);
+                    Err(IpcError::new(
+                        error_codes::METHOD_NOT_FOUND,
+                        format!("Unknown method: {}", method),
+                    ))
+                }
+            }
+        };
+
+        // Only confirmed rejections mark the account for re-authentication
+        let result = match result {
+            Err(error) if error.code == error_codes::AUTH_EXPIRED => {
+                match self.target_account(&ctx).await {
+                    Some(account_id) if self.confirm_token_rejected(&account_id).await => Err(error),
+                    _ => Err(IpcError { code: error_codes::API_ERROR, ..error }),
+                }
             }
+            result => result,
         };
 
-        let success = result.error.is_none();
+        let response = match result {
+            Ok(value) => IpcMessage::response_ok(&msg.id, value),
+            Err(error) => IpcMessage::response_err(&msg.id, error),
+        };
+
+        let success = response.error.is_none();
         log_ipc!(response, method, &msg.id, success);
 
-        result
+        response
+    }
+
+    /// Get the active client or fail with NOT_AUTHENTICATED
+    async fn active_client(&self) -> Result<Arc<MastodonClient>, IpcError> {
+        self.client
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"))
+    }
+
+    /// Get the client a request acts on, along with its account ID
+    ///
+    /// Requests act on the active account unless they name another one
+    /// with `account_id`.
+    async fn client_for(&self, ctx: &RequestContext) -> Result<(Option<String>, Arc<MastodonClient>), IpcError> {
+        let current = self.current_account_id.read().await.clone();
+        match &ctx.account_id {
+            Some(account_id) if current.as_ref() != Some(account_id) => {
+                Ok((Some(account_id.clone()), self.session(account_id).await?))
+            }
+            _ => Ok((current, self.active_client().await?)),
+        }
+    }
+
+    /// Get the account a request acts on, without building its client
+    async fn target_account(&self, ctx: &RequestContext) -> Option<String> {
+        match &ctx.account_id {
+            Some(account_id) => Some(account_id.clone()),
+            None => self.current_account_id.read().await.clone(),
+        }
+    }
+
+    /// Get the client of a stored account, building it from its token on first use
+    async fn session(&self, account_id: &str) -> Result<Arc<MastodonClient>, IpcError> {
+        if let Some(client) = self.sessions.read().await.get(account_id) {
+            return Ok(client.clone());
+        }
+
+        let account = match self.cache.get_account(account_id).await {
+            Ok(Some(account)) => account,
+            Ok(None) => {
+                return Err(IpcError::new(
+                    error_codes::NOT_AUTHENTICATED,
+                    format!("Unknown account: {}", account_id),
+                ))
+            }
+            Err(e) if e.is::<VaultError>() => return Err(vault_error(e)),
+            Err(e) => {
+                return Err(IpcError::new(
+                    error_codes::INTERNAL_ERROR,
+                    format!("Database error: {}", e),
+                ))
+            }
+        };
+
+        if account.needs_reauth {
+            return Err(IpcError::new(
+                error_codes::AUTH_EXPIRED,
+                format!("Account {} needs to log in again", account_id),
+            ));
+        }
+
+        let client = MastodonClient::from_token(&account.instance_url, &account.access_token, account.software)
+            .map_err(|e| {
+                IpcError::new(
+                    error_codes::INTERNAL_ERROR,
+                    format!("Failed to create client: {}", e),
+                )
+            })?;
+        debug!("Opened session for {}", account_id);
+
+        let mut sessions = self.sessions.write().await;
+        Ok(sessions.entry(account_id.to_string()).or_insert_with(|| Arc::new(client)).clone())
+    }
+
+    /// Check with the server whether an account's token was rejected
+    ///
+    /// Flags the account the first time. Returns `false` if the token still
+    /// works, in which case the server only refused the request itself.
+    async fn confirm_token_rejected(&self, account_id: &str) -> bool {
+        match self.cache.get_account(account_id).await {
+            Ok(Some(account)) if account.needs_reauth => return true,
+            Ok(Some(_)) => {}
+            _ => return false,
+        }
+
+        let client = match self.session(account_id).await {
+            Ok(client) => client,
+            Err(_) => return false,
+        };
+        match client.get_current_user().await {
+            Err(e) if MastodonClient::is_token_rejected(&e) => {
+                warn!("Token of {} was rejected: {}", account_id, e);
+                self.flag_needs_reauth(account_id).await;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Mark an account as needing to log in again and tell the clients
+    async fn flag_needs_reauth(&self, account_id: &str) {
+        if let Err(e) = self.cache.set_needs_reauth(account_id, true).await {
+            error!("Failed to flag {} for re-authentication: {}", account_id, e);
+        }
+        self.sessions.write().await.remove(account_id);
+        self.bus.publish(events::AUTH_EXPIRED, AuthExpiredEvent {
+            account_id: account_id.to_string(),
+        });
+    }
+
+    /// Make a stored account's client the active one
+    async fn activate(&self, account_id: &str, client: Arc<MastodonClient>) {
+        self.sessions.write().await.insert(account_id.to_string(), client.clone());
+        *self.client.write().await = Some(client);
+        *self.current_account_id.write().await = Some(account_id.to_string());
+    }
+
+    // ===== SYSTEM HANDLERS =====
+
+    /// Handle hello request, negotiating the protocol version
+    async fn handle_system_hello(&self, request: HelloRequest) -> Result<HelloResponse, IpcError> {
+        info!(
+            "Client hello: {} {} (protocol {})",
+            request.client_name.as_deref().unwrap_or("unknown"),
+            request.client_version.as_deref().unwrap_or("unknown"),
+            request.protocol_version
+        );
+
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&request.protocol_version) {
+            warn!(
+                "Refusing client with protocol version {} (supported: {}-{})",
+                request.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            );
+            return Err(IpcError::new(
+                error_codes::INCOMPATIBLE_PROTOCOL,
+                format!(
+                    "Protocol version {} is not supported; this core speaks versions {} to {}",
+                    request.protocol_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+                ),
+            )
+            .with_data(serde_json::json!({
+                "protocol_version": PROTOCOL_VERSION,
+                "min_protocol_version": MIN_PROTOCOL_VERSION
+            })));
+        }
+
+        Ok(HelloResponse {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            core_version: env!("CARGO_PKG_VERSION").to_string(),
+            methods: self.registry.method_names(),
+            events: self.registry.event_names(),
+        })
+    }
+
+    /// Handle describe request
+    async fn handle_system_describe(&self) -> Result<DescribeResponse, IpcError> {
+        Ok(DescribeResponse {
+            protocol_version: PROTOCOL_VERSION,
+            core_version: env!("CARGO_PKG_VERSION").to_string(),
+            methods: self.registry.method_descriptions(),
+            events: self.registry.event_descriptions(),
+            common_params: self.registry.common_params_schema().clone(),
+            definitions: self.registry.definitions().clone(),
+        })
+    }
+
+    /// Handle authenticate request on an already authenticated connection
+    ///
+    /// The secret itself is checked by the connection before any message
+    /// reaches the handler.
+    async fn handle_system_authenticate(&self) -> Result<AuthenticateResponse, IpcError> {
+        Ok(AuthenticateResponse { authenticated: true })
+    }
+
+    /// Handle cancel request
+    async fn handle_system_cancel(
+        &self,
+        ctx: &RequestContext,
+        request: CancelRequest,
+    ) -> Result<CancelResponse, IpcError> {
+        let cancelled = ctx.conn.cancel_request(&request.id);
+        debug!("Cancel request for {}: cancelled={}", request.id, cancelled);
+
+        Ok(CancelResponse { cancelled })
     }
 
     /// Handle ping request
-    async fn handle_ping(&self, msg: &IpcMessage) -> IpcMessage {
-        IpcMessage::response_ok(&msg.id, serde_json::json!({
-            "pong": true,
-            "timestamp": chrono::Utc::now().to_rfc3339()
-        }))
+    async fn handle_ping(&self) -> Result<PingResponse, IpcError> {
+        Ok(PingResponse {
+            pong: true,
+            timestamp: Utc::now().to_rfc3339(),
+        })
     }
 
     /// Handle shutdown request
-    async fn handle_shutdown(&self, msg: &IpcMessage) -> IpcMessage {
+    async fn handle_shutdown(&self) -> Result<ShutdownResponse, IpcError> {
         info!("Shutdown requested via IPC");
-        // In a real implementation, we'd signal the main loop to shutdown
-        IpcMessage::response_ok(&msg.id, serde_json::json!({
-            "status": "shutting_down"
-        }))
+        // The response is still delivered: connections drain running
+        // requests before closing
+        self.shutdown.send_replace(Some("Shutdown requested by client".to_string()));
+        Ok(ShutdownResponse {
+            status: "shutting_down".to_string(),
+        })
     }
 
+    // ===== AUTHENTICATION HANDLERS =====
+
     /// Handle auth start request
-    async fn handle_auth_start(&self, msg: &IpcMessage) -> IpcMessage {
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
+    ///
+    /// With `loopback` set, the browser is redirected to a local listener
+    /// that completes the login in the background. With `account_id` set,
+    /// the login renews that stored account.
+    async fn handle_auth_start(&self, request: AuthRequest) -> Result<AuthResponse, IpcError> {
+        info!("Starting auth flow for instance: {}", request.instance_url);
+
+        if let Some(account_id) = &request.account_id {
+            let account = match self.cache.get_account(account_id).await {
+                Ok(Some(account)) => account,
+                Ok(None) => {
+                    return Err(IpcError::new(
+                        error_codes::INVALID_PARAMS,
+                        format!("Unknown account: {}", account_id),
+                    ))
+                }
+                Err(e) => {
+                    return Err(IpcError::new(
+                        error_codes::INTERNAL_ERROR,
+                        format!("Database error: {}", e),
+                    ))
+                }
+            };
+            if normalize_url(&request.instance_url) != account.instance_url {
+                return Err(IpcError::new(
+                    error_codes::INVALID_PARAMS,
+                    format!("Account {} is on {}", account_id, account.instance_url),
+                ));
             }
+        }
+
+        let auth_failed = |e: anyhow::Error| {
+            error!("Auth start failed: {}", e);
+            IpcError::new(error_codes::API_ERROR, format!("Auth failed: {}", e))
+        };
+
+        let (app, listener) = self.login_app(&request.instance_url, request.loopback)
+            .await
+            .map_err(auth_failed)?;
+        let response = MastodonClient::start_auth(&app, request.account_id.as_deref()).map_err(auth_failed)?;
+
+        let Some(listener) = listener else {
+            return Ok(response);
         };
+        if let Some(handler) = self.this.upgrade() {
+            let state = response.state.clone();
+            tokio::spawn(async move {
+                handler.complete_loopback_auth(listener, request.instance_url, state).await;
+            });
+        }
+
+        Ok(response)
+    }
 
-        let instance_url = match params.get("instance_url").and_then(|v| v.as_str()) {
-            Some(url) => url,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing instance_url"),
-                );
+    /// Find the app registration to log in with, registering one if none is cached
+    ///
+    /// Loopback logins reuse a registration whose port can be bound again,
+    /// and return the bound listener.
+    async fn login_app(
+        &self,
+        instance_url: &str,
+        loopback: bool,
+    ) -> anyhow::Result<(OAuthApp, Option<LoopbackListener>)> {
+        let instance_url = normalize_url(instance_url);
+        let cached = match self.cache.get_oauth_apps(&instance_url).await {
+            Ok(apps) => apps,
+            Err(e) => {
+                warn!("Failed to load app registrations: {}", e);
+                Vec::new()
+            }
+        };
+
+        let (redirect_uri, listener) = if loopback {
+            for app in cached {
+                if let Some(listener) = LoopbackListener::rebind(&app.redirect_uri).await {
+                    debug!("Reusing app registration {} on {}", app.client_id, instance_url);
+                    return Ok((app, Some(listener)));
+                }
+            }
+            let listener = LoopbackListener::bind().await?;
+            (listener.redirect_uri(), Some(listener))
+        } else {
+            if let Some(app) = cached.into_iter().find(|app| app.redirect_uri == OOB_REDIRECT_URI) {
+                debug!("Reusing app registration {} on {}", app.client_id, instance_url);
+                return Ok((app, None));
             }
+            (OOB_REDIRECT_URI.to_string(), None)
         };
 
-        info!("Starting auth flow for instance: {}", instance_url);
+        let software = MastodonClient::detect_software(&instance_url).await;
+        let app = MastodonClient::register_app(&instance_url, &redirect_uri, software).await?;
+        if let Err(e) = self.cache.save_oauth_app(&app).await {
+            warn!("Failed to save app registration: {}", e);
+        }
+
+        Ok((app, listener))
+    }
+
+    /// Revoke a saved account's token before it is deleted
+    ///
+    /// Failures are only logged: the account is deleted either way.
+    async fn revoke_account_token(&self, account_id: &str) {
+        let account = match self.cache.get_account(account_id).await {
+            Ok(Some(account)) => account,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to load account {} to revoke its token: {}", account_id, e);
+                return;
+            }
+        };
 
-        match MastodonClient::start_auth(instance_url).await {
-            Ok(auth_response) => {
-                IpcMessage::response_ok(&msg.id, serde_json::to_value(auth_response).unwrap())
+        let Some(client_id) = account.client_id.as_deref() else {
+            warn!("Not revoking token of {}: the app it was issued to is unknown", account_id);
+            return;
+        };
+        let app = match self.cache.get_oauth_app_by_client_id(&account.instance_url, client_id).await {
+            Ok(Some(app)) => app,
+            Ok(None) => {
+                warn!("Not revoking token of {}: app {} is no longer registered", account_id, client_id);
+                return;
             }
             Err(e) => {
-                error!("Auth start failed: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Auth failed: {}", e)),
-                )
+                warn!("Failed to load app registration to revoke token of {}: {}", account_id, e);
+                return;
             }
+        };
+
+        let result = match MastodonClient::from_token(&account.instance_url, &account.access_token, account.software) {
+            Ok(client) => client.revoke_token(&app).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("Failed to revoke token of {}: {}", account_id, e);
         }
     }
 
-    /// Handle auth callback
-    async fn handle_auth_callback(&self, msg: &IpcMessage) -> IpcMessage {
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
+    /// Wait for the browser to return to the loopback listener and finish the login
+    async fn complete_loopback_auth(&self, listener: LoopbackListener, instance_url: String, state: String) {
+        let (params, stream) = match listener.accept(&state).await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Loopback login did not complete: {}", e);
+                self.publish_auth_completed(state, Err(e.to_string()));
+                return;
             }
         };
 
-        let code = match params.get("code").and_then(|v| v.as_str()) {
-            Some(c) => c,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing code"),
-                );
-            }
+        let result = match (params.code, params.error) {
+            (_, Some(error)) => Err(format!("The server refused the login: {}", error)),
+            (Some(code), None) => match MastodonClient::complete_auth(&instance_url, &code, &state).await {
+                Ok((client, login)) => self
+                    .finish_login(&instance_url, client, &login)
+                    .await
+                    .map_err(|e| e.message),
+                Err(e) => Err(e.to_string()),
+            },
+            (None, None) => Err("The login returned no authorization code".to_string()),
         };
 
-        let instance_url = match params.get("instance_url").and_then(|v| v.as_str()) {
-            Some(url) => url,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing instance_url"),
-                );
+        match &result {
+            Ok(_) => {
+                info!("Loopback login completed");
+                loopback::respond(stream, true, "You are logged in to Blindodon. You can close this tab and return to the app.").await;
+            }
+            Err(e) => {
+                error!("Loopback login failed: {}", e);
+                loopback::respond(stream, false, &format!("{}. Return to Blindodon to try again.", e)).await;
             }
+        }
+
+        self.publish_auth_completed(state, result);
+    }
+
+    /// Announce how a loopback login ended
+    fn publish_auth_completed(&self, state: String, result: Result<AuthCallbackResponse, String>) {
+        let event = match result {
+            Ok(response) => AuthCompletedEvent {
+                state,
+                success: true,
+                account: response.account,
+                error: response.error_fetching_user,
+            },
+            Err(error) => AuthCompletedEvent {
+                state,
+                success: false,
+                account: None,
+                error: Some(error),
+            },
         };
+        self.bus.publish(events::AUTH_COMPLETED, event);
+    }
 
+    /// Handle auth callback
+    async fn handle_auth_callback(&self, request: AuthCallback) -> Result<AuthCallbackResponse, IpcError> {
+        let instance_url = request.instance_url.as_str();
         info!("Processing auth callback for instance: {}", instance_url);
 
-        match MastodonClient::complete_auth(instance_url, code).await {
-            Ok(client) => {
-                match client.get_current_user().await {
-                    Ok(user) => {
-                        // Create account ID from user@instance
+        let (client, login) = MastodonClient::complete_auth(instance_url, &request.code, &request.state)
+            .await
+            .map_err(|e| {
+                error!("Auth callback failed: {}", e);
+                IpcError::new(error_codes::API_ERROR, format!("Auth failed: {}", e))
+            })?;
+
+        self.finish_login(instance_url, client, &login).await
+    }
+
+    /// Save the account of a completed login and make it the active one
+    ///
+    /// An account that was logged in before is updated in place, keeping
+    /// its ID and settings.
+    async fn finish_login(
+        &self,
+        instance_url: &str,
+        client: MastodonClient,
+        login: &PendingLogin,
+    ) -> Result<AuthCallbackResponse, IpcError> {
+        match client.get_current_user().await {
+            Ok(user) => {
+                // Create account ID from user@instance, unless an account is logged in again
+                let account_id = match &login.account_id {
+                    Some(account_id) => account_id.clone(),
+                    None => {
                         let instance_domain = instance_url
                             .replace("https://", "")
                             .replace("http://", "");
-                        let account_id = format!("{}@{}", user.username, instance_domain);
-
-                        // Create StoredAccount for persistence
-                        let stored_account = StoredAccount {
-                            id: account_id.clone(),
-                            instance_url: client.instance_url().to_string(),
-                            username: user.username.clone(),
-                            acct: user.acct.clone(),
-                            display_name: user.display_name.clone(),
-                            access_token: client.access_token().to_string(),
-                            refresh_token: None,
-                            token_expires_at: None,
-                            added_at: Utc::now(),
-                            last_used_at: Utc::now(),
-                            is_default: true,
-                            avatar_url: Some(user.avatar.clone()),
-                            blindodon_pm_private_key: None,
-                            blindodon_pm_public_key: None,
-                        };
-
-                        // Save to database
-                        if let Err(e) = self.cache.save_account(&stored_account).await {
-                            error!("Failed to save account: {}", e);
-                            // Continue anyway - auth succeeded
-                        }
-
-                        // Set as default
-                        if let Err(e) = self.cache.set_default_account(&account_id).await {
-                            error!("Failed to set default account: {}", e);
-                        }
-
-                        // Store client in memory
-                        let client = Arc::new(client);
-                        *self.client.write().await = Some(client);
-                        *self.current_account_id.write().await = Some(account_id.clone());
-
-                        // Return account in the format expected by the UI
-                        IpcMessage::response_ok(&msg.id, serde_json::json!({
-                            "success": true,
-                            "account": {
-                                "id": account_id,
-                                "instance_url": stored_account.instance_url,
-                                "username": stored_account.username,
-                                "display_name": stored_account.display_name,
-                                "avatar_url": stored_account.avatar_url,
-                                "is_default": stored_account.is_default,
-                                "last_used_at": stored_account.last_used_at
-                            }
-                        }))
+                        format!("{}@{}", user.username, instance_domain)
                     }
+                };
+
+                let existing = match self.cache.get_account(&account_id).await {
+                    Ok(existing) => existing,
                     Err(e) => {
-                        // Auth succeeded but couldn't fetch user info - still save what we can
-                        let client = Arc::new(client);
-                        *self.client.write().await = Some(client);
-
-                        IpcMessage::response_ok(&msg.id, serde_json::json!({
-                            "success": true,
-                            "error_fetching_user": e.to_string()
-                        }))
+                        warn!("Failed to load account {}: {}", account_id, e);
+                        None
+                    }
+                };
+                if let Some(existing) = existing.as_ref().filter(|_| login.account_id.is_some()) {
+                    if !existing.username.eq_ignore_ascii_case(&user.username) {
+                        return Err(IpcError::new(
+                            error_codes::INVALID_REQUEST,
+                            format!("Logged in as {}, but the login was for {}", user.acct, existing.acct),
+                        ));
                     }
                 }
+
+                // Create StoredAccount for persistence
+                let stored_account = StoredAccount {
+                    id: account_id.clone(),
+                    instance_url: client.instance_url().to_string(),
+                    username: user.username.clone(),
+                    acct: user.acct.clone(),
+                    display_name: user.display_name.clone(),
+                    access_token: client.access_token().to_string(),
+                    refresh_token: None,
+                    token_expires_at: None,
+                    added_at: existing.as_ref().map_or_else(Utc::now, |existing| existing.added_at),
+                    last_used_at: Utc::now(),
+                    is_default: true,
+                    avatar_url: Some(user.avatar.clone()),
+                    blindodon_pm_private_key: existing.as_ref().and_then(|e| e.blindodon_pm_private_key.clone()),
+                    blindodon_pm_public_key: existing.as_ref().and_then(|e| e.blindodon_pm_public_key.clone()),
+                    client_id: Some(login.app.client_id.clone()),
+                    software: client.software(),
+                    capabilities: client.software().capabilities(),
+                    needs_reauth: false,
+                };
+
+                // Save to database
+                if let Err(e) = self.cache.save_account(&stored_account).await {
+                    error!("Failed to save account: {}", e);
+                    // Continue anyway - auth succeeded
+                }
+
+                // Set as default
+                if let Err(e) = self.cache.set_default_account(&account_id).await {
+                    error!("Failed to set default account: {}", e);
+                }
+
+                // Store client in memory
+                self.activate(&account_id, Arc::new(client)).await;
+                self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent {
+                    account_id: Some(account_id.clone()),
+                });
+
+                // Return account in the format expected by the UI
+                Ok(AuthCallbackResponse {
+                    success: true,
+                    account: Some(AccountSummary {
+                        id: account_id,
+                        instance_url: stored_account.instance_url,
+                        username: stored_account.username,
+                        display_name: stored_account.display_name,
+                        avatar_url: stored_account.avatar_url,
+                        is_default: stored_account.is_default,
+                        last_used_at: stored_account.last_used_at,
+                        software: stored_account.software,
+                        capabilities: stored_account.capabilities,
+                    }),
+                    error_fetching_user: None,
+                })
             }
             Err(e) => {
-                error!("Auth callback failed: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Auth failed: {}", e)),
-                )
+                // Auth succeeded but couldn't fetch user info. The client is
+                // still usable, but belongs to no stored account, so it must
+                // not be mistaken for the one that was active before.
+                *self.client.write().await = Some(Arc::new(client));
+                *self.current_account_id.write().await = None;
+                self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent { account_id: None });
+
+                Ok(AuthCallbackResponse {
+                    success: true,
+                    account: None,
+                    error_fetching_user: Some(e.to_string()),
+                })
             }
         }
     }
 
     /// Handle auth logout
-    async fn handle_auth_logout(&self, msg: &IpcMessage) -> IpcMessage {
-        let account_id = self.current_account_id.read().await.clone();
+    async fn handle_auth_logout(&self, request: LogoutRequest) -> Result<SuccessResponse, IpcError> {
+        let account_id = self.current_account_id.write().await.take();
 
         *self.client.write().await = None;
-        *self.current_account_id.write().await = None;
+        if let Some(id) = &account_id {
+            self.sessions.write().await.remove(id);
+        }
+        self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent { account_id: None });
 
         // Optionally delete the account from storage if requested
-        let delete_account = msg
-            .params
-            .as_ref()
-            .and_then(|p| p.get("delete_account"))
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
-
-        if delete_account {
+        if request.delete_account {
             if let Some(id) = &account_id {
+                self.revoke_account_token(id).await;
                 if let Err(e) = self.cache.delete_account(id).await {
                     error!("Failed to delete account: {}", e);
                 }
@@ -340,11 +860,11 @@ impl MessageHandler {
         }
 
         info!("User logged out");
-        IpcMessage::response_ok(&msg.id, serde_json::json!({ "success": true }))
+        Ok(SuccessResponse::ok())
     }
 
     /// Handle get accounts - returns saved accounts from storage
-    async fn handle_auth_get_accounts(&self, msg: &IpcMessage) -> IpcMessage {
+    async fn handle_auth_get_accounts(&self) -> Result<AccountsResponse, IpcError> {
         let has_client = self.client.read().await.is_some();
         let current_account_id = self.current_account_id.read().await.clone();
 
@@ -356,603 +876,616 @@ impl MessageHandler {
             }
         };
 
-        IpcMessage::response_ok(
-            &msg.id,
-            serde_json::json!({
-                "authenticated": has_client,
-                "current_account_id": current_account_id,
-                "accounts": accounts
-            }),
-        )
+        Ok(AccountsResponse {
+            authenticated: has_client,
+            current_account_id,
+            accounts,
+        })
     }
 
     /// Handle switch account
-    async fn handle_auth_switch_account(&self, msg: &IpcMessage) -> IpcMessage {
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
-
-        let account_id = match params.get("account_id").and_then(|v| v.as_str()) {
-            Some(id) => id,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing account_id"),
-                );
-            }
-        };
+    async fn handle_auth_switch_account(
+        &self,
+        request: AccountIdRequest,
+    ) -> Result<SwitchAccountResponse, IpcError> {
+        let account_id = request.account_id.as_str();
 
         let account = match self.cache.get_account(account_id).await {
             Ok(Some(acc)) => acc,
             Ok(None) => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Account not found"),
-                );
+                return Err(IpcError::new(error_codes::NOT_AUTHENTICATED, "Account not found"));
             }
             Err(e) => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(
-                        error_codes::INTERNAL_ERROR,
-                        format!("Database error: {}", e),
-                    ),
-                );
+                return Err(IpcError::new(
+                    error_codes::INTERNAL_ERROR,
+                    format!("Database error: {}", e),
+                ));
             }
         };
 
-        // Create client from saved token
-        match MastodonClient::from_token(&account.instance_url, &account.access_token) {
-            Ok(client) => {
-                // Verify token is still valid
-                match client.get_current_user().await {
-                    Ok(user) => {
-                        *self.client.write().await = Some(Arc::new(client));
-                        *self.current_account_id.write().await = Some(account_id.to_string());
-
-                        // Update default and last_used
-                        let _ = self.cache.set_default_account(account_id).await;
-
-                        info!("Switched to account {}", account_id);
-                        IpcMessage::response_ok(
-                            &msg.id,
-                            serde_json::json!({
-                                "success": true,
-                                "account": account,
-                                "user": user
-                            }),
-                        )
-                    }
-                    Err(e) => IpcMessage::response_err(
-                        &msg.id,
-                        IpcError::new(
-                            error_codes::API_ERROR,
-                            format!("Token expired, please re-authenticate: {}", e),
-                        ),
-                    ),
-                }
-            }
-            Err(e) => IpcMessage::response_err(
-                &msg.id,
-                IpcError::new(
-                    error_codes::INTERNAL_ERROR,
-                    format!("Failed to create client: {}", e),
-                ),
-            ),
-        }
+        // Reuse the account's session, or create one from the saved token
+        let client = self.session(account_id).await?;
+
+        // Verify token is still valid
+        let user = client.get_current_user().await.map_err(|e| {
+            api_error(&e, format!("Failed to verify the account: {}", e))
+        })?;
+
+        self.activate(account_id, client).await;
+
+        // Update default and last_used
+        let _ = self.cache.set_default_account(account_id).await;
+        self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent {
+            account_id: Some(account_id.to_string()),
+        });
+
+        info!("Switched to account {}", account_id);
+        Ok(SwitchAccountResponse {
+            success: true,
+            account,
+            user,
+        })
     }
 
     /// Handle delete account
-    async fn handle_auth_delete_account(&self, msg: &IpcMessage) -> IpcMessage {
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
-
-        let account_id = match params.get("account_id").and_then(|v| v.as_str()) {
-            Some(id) => id,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing account_id"),
-                );
-            }
-        };
+    async fn handle_auth_delete_account(
+        &self,
+        request: AccountIdRequest,
+    ) -> Result<SuccessResponse, IpcError> {
+        let account_id = request.account_id.as_str();
 
         // If this is the current account, log out first
         let current_id = self.current_account_id.read().await.clone();
         if current_id.as_deref() == Some(account_id) {
             *self.client.write().await = None;
             *self.current_account_id.write().await = None;
+            self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent { account_id: None });
         }
+        self.sessions.write().await.remove(account_id);
 
+        self.revoke_account_token(account_id).await;
         match self.cache.delete_account(account_id).await {
             Ok(()) => {
                 info!("Deleted account {}", account_id);
-                IpcMessage::response_ok(&msg.id, serde_json::json!({ "success": true }))
+                Ok(SuccessResponse::ok())
             }
-            Err(e) => IpcMessage::response_err(
-                &msg.id,
-                IpcError::new(
-                    error_codes::INTERNAL_ERROR,
-                    format!("Failed to delete account: {}", e),
-                ),
-            ),
+            Err(e) => Err(IpcError::new(
+                error_codes::INTERNAL_ERROR,
+                format!("Failed to delete account: {}", e),
+            )),
         }
     }
 
-    // ===== SETTINGS HANDLERS =====
+    // ===== VAULT HANDLERS =====
 
-    /// Handle settings get
-    async fn handle_settings_get(&self, msg: &IpcMessage) -> IpcMessage {
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
+    /// Handle vault status
+    async fn handle_vault_status(&self) -> Result<VaultStatus, IpcError> {
+        self.cache.vault_status().await.map_err(vault_error)
+    }
 
-        let key = match params.get("key").and_then(|v| v.as_str()) {
-            Some(k) => k,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing key"),
-                );
-            }
-        };
+    /// Handle vault unlock, restoring the saved session afterwards
+    async fn handle_vault_unlock(&self, request: VaultUnlockRequest) -> Result<VaultStatus, IpcError> {
+        self.cache.unlock_vault(&request.passphrase).await.map_err(vault_error)?;
 
-        match self.cache.get_setting(key).await {
-            Ok(value) => IpcMessage::response_ok(
-                &msg.id,
-                serde_json::json!({
-                    "key": key,
-                    "value": value
-                }),
-            ),
-            Err(e) => IpcMessage::response_err(
-                &msg.id,
-                IpcError::new(
-                    error_codes::INTERNAL_ERROR,
-                    format!("Database error: {}", e),
-                ),
-            ),
+        if let Err(e) = self.initialize().await {
+            warn!("Failed to restore session after unlocking: {}", e);
         }
+        if let Some(account_id) = self.current_account_id.read().await.clone() {
+            self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent {
+                account_id: Some(account_id),
+            });
+        }
+
+        self.handle_vault_status().await
     }
 
-    /// Handle settings set
-    async fn handle_settings_set(&self, msg: &IpcMessage) -> IpcMessage {
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
+    /// Handle vault lock; the active session goes with the key
+    async fn handle_vault_lock(&self) -> Result<VaultStatus, IpcError> {
+        self.cache.lock_vault().await.map_err(vault_error)?;
 
-        let key = match params.get("key").and_then(|v| v.as_str()) {
-            Some(k) => k,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing key"),
-                );
-            }
-        };
+        *self.client.write().await = None;
+        self.sessions.write().await.clear();
+        if self.current_account_id.write().await.take().is_some() {
+            self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent { account_id: None });
+        }
 
-        let value = match params.get("value").and_then(|v| v.as_str()) {
-            Some(v) => v,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing value"),
-                );
-            }
-        };
+        self.handle_vault_status().await
+    }
 
-        match self.cache.set_setting(key, value).await {
-            Ok(()) => IpcMessage::response_ok(&msg.id, serde_json::json!({ "success": true })),
-            Err(e) => IpcMessage::response_err(
-                &msg.id,
-                IpcError::new(
-                    error_codes::INTERNAL_ERROR,
-                    format!("Database error: {}", e),
-                ),
-            ),
+    /// Handle vault set passphrase
+    async fn handle_vault_set_passphrase(&self, request: VaultSetPassphraseRequest) -> Result<VaultStatus, IpcError> {
+        if request.passphrase.as_deref() == Some("") {
+            return Err(IpcError::new(error_codes::INVALID_PARAMS, "Passphrase must not be empty"));
         }
+
+        self.cache
+            .set_vault_passphrase(request.passphrase.as_deref())
+            .await
+            .map_err(vault_error)?;
+
+        self.handle_vault_status().await
     }
 
-    /// Handle settings get all
-    async fn handle_settings_get_all(&self, msg: &IpcMessage) -> IpcMessage {
-        match self.cache.get_all_settings().await {
-            Ok(settings) => IpcMessage::response_ok(
-                &msg.id,
-                serde_json::json!({
-                    "settings": settings
-                }),
-            ),
-            Err(e) => IpcMessage::response_err(
-                &msg.id,
-                IpcError::new(
-                    error_codes::INTERNAL_ERROR,
-                    format!("Database error: {}", e),
-                ),
-            ),
+    /// Handle vault reset, telling the UI which accounts need a new login
+    async fn handle_vault_reset(&self) -> Result<VaultStatus, IpcError> {
+        let account_ids = self.cache.reset_vault().await.map_err(vault_error)?;
+        for account_id in account_ids {
+            self.bus.publish(events::AUTH_EXPIRED, AuthExpiredEvent { account_id });
         }
+
+        self.handle_vault_status().await
     }
 
-    /// Handle timeline get request
-    async fn handle_timeline_get(&self, msg: &IpcMessage) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
-        };
+    // ===== BACKUP HANDLERS =====
 
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
+    /// Handle backup export
+    async fn handle_backup_export(&self, request: BackupRequest) -> Result<BackupResponse, IpcError> {
+        if request.passphrase.is_empty() {
+            return Err(IpcError::new(error_codes::INVALID_PARAMS, "Passphrase must not be empty"));
+        }
 
-        let request: TimelineRequest = match serde_json::from_value(params.clone()) {
-            Ok(r) => r,
-            Err(e) => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, format!("Invalid params: {}", e)),
-                );
-            }
-        };
+        self.cache
+            .export_backup(Path::new(&request.path), &request.passphrase)
+            .await
+            .map_err(backup_error)
+    }
 
-        debug!("Fetching timeline: {:?}", request.timeline_type);
+    /// Handle backup import, restoring a session if none is active
+    ///
+    /// Sessions of overwritten accounts still hold the old tokens, so they
+    /// are dropped and the active one is rebuilt from the imported account.
+    async fn handle_backup_import(&self, request: BackupRequest) -> Result<BackupResponse, IpcError> {
+        let (response, account_ids) = self.cache
+            .import_backup(Path::new(&request.path), &request.passphrase)
+            .await
+            .map_err(backup_error)?;
+
+        {
+            let mut sessions = self.sessions.write().await;
+            for account_id in &account_ids {
+                sessions.remove(account_id);
+            }
+        }
+        let current = self.current_account_id.read().await.clone();
+        if let Some(account_id) = current.filter(|id| account_ids.contains(id)) {
+            match self.session(&account_id).await {
+                Ok(client) => self.activate(&account_id, client).await,
+                Err(e) => {
+                    warn!("Imported account {} cannot stay active: {}", account_id, e.message);
+                    *self.client.write().await = None;
+                    *self.current_account_id.write().await = None;
+                    self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent { account_id: None });
+                }
+            }
+        }
 
-        match client.get_timeline(&request).await {
-            Ok(response) => {
-                IpcMessage::response_ok(&msg.id, serde_json::to_value(response).unwrap())
+        if self.current_account_id.read().await.is_none() {
+            if let Err(e) = self.initialize().await {
+                warn!("Failed to restore session after importing: {}", e);
             }
-            Err(e) => {
-                error!("Failed to fetch timeline: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to fetch timeline: {}", e)),
-                )
+            if let Some(account_id) = self.current_account_id.read().await.clone() {
+                self.bus.publish(events::ACCOUNT_SWITCHED, AccountSwitchedEvent {
+                    account_id: Some(account_id),
+                });
             }
         }
+
+        Ok(response)
     }
 
-    /// Handle post create
-    async fn handle_post_create(&self, msg: &IpcMessage) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
-        };
+    // ===== SETTINGS HANDLERS =====
 
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
+    /// Handle settings get
+    async fn handle_settings_get(&self, request: SettingGetRequest) -> Result<SettingValue, IpcError> {
+        match self.cache.get_setting(&request.key, request.account_id.as_deref()).await {
+            Ok(value) => Ok(SettingValue {
+                key: request.key,
+                value,
+            }),
+            Err(e) => Err(IpcError::new(
+                error_codes::INTERNAL_ERROR,
+                format!("Database error: {}", e),
+            )),
+        }
+    }
 
-        let new_post: crate::models::NewPost = match serde_json::from_value(params.clone()) {
-            Ok(p) => p,
-            Err(e) => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, format!("Invalid params: {}", e)),
-                );
+    /// Handle settings set
+    async fn handle_settings_set(&self, request: SettingSetRequest) -> Result<SuccessResponse, IpcError> {
+        // Settings of unknown accounts would never be cleaned up
+        if let Some(account_id) = &request.account_id {
+            match self.cache.has_account(account_id).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(IpcError::new(
+                        error_codes::INVALID_PARAMS,
+                        format!("Unknown account: {}", account_id),
+                    ))
+                }
+                Err(e) => {
+                    return Err(IpcError::new(
+                        error_codes::INTERNAL_ERROR,
+                        format!("Database error: {}", e),
+                    ))
+                }
             }
-        };
+        }
 
-        match client.create_post(&new_post).await {
-            Ok(post) => {
-                IpcMessage::response_ok(&msg.id, serde_json::to_value(post).unwrap())
-            }
-            Err(e) => {
-                error!("Failed to create post: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to create post: {}", e)),
-                )
+        match self.cache.set_setting(&request.key, &request.value, request.account_id.as_deref()).await {
+            Ok(()) => {
+                self.bus.publish(events::SETTING_CHANGED, SettingChangedEvent {
+                    key: request.key,
+                    value: request.value,
+                    account_id: request.account_id,
+                });
+                Ok(SuccessResponse::ok())
             }
+            Err(e) => Err(IpcError::new(
+                error_codes::INTERNAL_ERROR,
+                format!("Database error: {}", e),
+            )),
         }
     }
 
-    /// Handle post boost
-    async fn handle_post_boost(&self, msg: &IpcMessage) -> IpcMessage {
-        self.handle_post_action(msg, "boost").await
+    /// Handle settings delete
+    ///
+    /// Returns the value now in effect, which for an account is the global
+    /// value it falls back to.
+    async fn handle_settings_delete(&self, request: SettingDeleteRequest) -> Result<SettingValue, IpcError> {
+        let account_id = request.account_id.as_deref();
+        let value = match self.cache.delete_setting(&request.key, account_id).await {
+            Ok(()) => self.cache.get_setting(&request.key, account_id).await,
+            Err(e) => Err(e),
+        }
+        .map_err(|e| IpcError::new(error_codes::INTERNAL_ERROR, format!("Database error: {}", e)))?;
+
+        self.bus.publish(events::SETTING_DELETED, SettingDeletedEvent {
+            key: request.key.clone(),
+            account_id: request.account_id,
+            value: value.clone(),
+        });
+        Ok(SettingValue {
+            key: request.key,
+            value,
+        })
     }
 
-    /// Handle post unboost
-    async fn handle_post_unboost(&self, msg: &IpcMessage) -> IpcMessage {
-        self.handle_post_action(msg, "unboost").await
+    /// Handle settings get all
+    async fn handle_settings_get_all(&self, request: SettingsRequest) -> Result<SettingsResponse, IpcError> {
+        match self.cache.get_all_settings(request.account_id.as_deref()).await {
+            Ok(settings) => Ok(SettingsResponse { settings }),
+            Err(e) => Err(IpcError::new(
+                error_codes::INTERNAL_ERROR,
+                format!("Database error: {}", e),
+            )),
+        }
     }
 
-    /// Handle post favourite
-    async fn handle_post_favourite(&self, msg: &IpcMessage) -> IpcMessage {
-        self.handle_post_action(msg, "favourite").await
+    // ===== TIMELINE HANDLERS =====
+
+    /// Handle timeline get request
+    async fn handle_timeline_get(&self, ctx: &RequestContext, request: TimelineRequest) -> Result<TimelineResponse, IpcError> {
+        if request.cached {
+            return self.read_cached_timeline(ctx, &request).await;
+        }
+
+        let (account_id, client) = self.client_for(ctx).await?;
+
+        debug!("Fetching timeline: {:?}", request.timeline_type);
+
+        let page = client.get_timeline(&request).await.map_err(|e| {
+            error!("Failed to fetch timeline: {}", e);
+            api_error(&e, format!("Failed to fetch timeline: {}", e))
+        })?;
+
+        Ok(self.cache_timeline_page(account_id.as_deref(), &request, page).await)
     }
 
-    /// Handle post unfavourite
-    async fn handle_post_unfavourite(&self, msg: &IpcMessage) -> IpcMessage {
-        self.handle_post_action(msg, "unfavourite").await
+    /// Handle filling a gap in a cached timeline
+    async fn handle_timeline_fill_gap(&self, ctx: &RequestContext, request: FillGapRequest) -> Result<TimelineResponse, IpcError> {
+        if request.timeline_type.cache_key().is_none() {
+            return Err(IpcError::new(
+                error_codes::INVALID_PARAMS,
+                format!("The {} timeline is not cached", request.timeline_type.display_name()),
+            ));
+        }
+
+        let (account_id, client) = self.client_for(ctx).await?;
+
+        // Paging up from the older post loads the gap in reading order
+        let page_request = TimelineRequest {
+            timeline_type: request.timeline_type,
+            limit: request.limit,
+            max_id: Some(request.newer_id),
+            since_id: None,
+            min_id: Some(request.older_id),
+            cached: false,
+        };
+
+        debug!("Filling gap in timeline: {:?}", page_request.timeline_type);
+
+        let page = client.get_timeline(&page_request).await.map_err(|e| {
+            error!("Failed to fill timeline gap: {}", e);
+            api_error(&e, format!("Failed to fill timeline gap: {}", e))
+        })?;
+
+        Ok(self.cache_timeline_page(account_id.as_deref(), &page_request, page).await)
     }
 
-    /// Generic post action handler
-    async fn handle_post_action(&self, msg: &IpcMessage, action: &str) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
+    /// Serve a timeline page from the cache
+    async fn read_cached_timeline(&self, ctx: &RequestContext, request: &TimelineRequest) -> Result<TimelineResponse, IpcError> {
+        let Some(timeline) = request.timeline_type.cache_key() else {
+            return Err(IpcError::new(
+                error_codes::INVALID_PARAMS,
+                format!("The {} timeline is not cached", request.timeline_type.display_name()),
+            ));
+        };
+        let Some(account_id) = self.target_account(ctx).await else {
+            return Err(IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"));
         };
 
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
+        self.cache.cached_timeline(&account_id, &timeline, request).await.map_err(|e| {
+            IpcError::new(
+                error_codes::INTERNAL_ERROR,
+                format!("Database error: {}", e),
+            )
+        })
+    }
+
+    /// Store a fetched page of a cached timeline and attach the gaps around it
+    ///
+    /// Pages of other timelines are returned as they are. A page that
+    /// cannot be stored is still returned, without gaps.
+    async fn cache_timeline_page(
+        &self,
+        account_id: Option<&str>,
+        request: &TimelineRequest,
+        mut page: TimelineResponse,
+    ) -> TimelineResponse {
+        let (Some(account_id), Some(timeline)) = (account_id, request.timeline_type.cache_key()) else {
+            return page;
         };
 
-        let post_id = match params.get("post_id").and_then(|v| v.as_str()) {
-            Some(id) => id,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing post_id"),
-                );
+        match self.cache.store_timeline_page(account_id, &timeline, request, &page.posts).await {
+            Ok(gaps) => page.gaps = gaps,
+            Err(e) => warn!("Failed to cache {} timeline: {}", timeline, e),
+        }
+        page
+    }
+
+    /// Handle timeline stream start
+    async fn handle_timeline_stream_start(
+        &self,
+        ctx: &RequestContext,
+        request: StreamRequest,
+    ) -> Result<StreamStartResponse, IpcError> {
+        let (account_id, client) = self.client_for(ctx).await?;
+
+        let timeline_type = request.timeline_type;
+        let timeline_name = timeline_type.display_name();
+
+        let stream = Arc::new(StreamManager::new(
+            account_id.as_deref(),
+            client.instance_url(),
+            client.access_token(),
+            client.software(),
+        ));
+        let key = (account_id, timeline_type.clone());
+        if !ctx.conn.add_stream(key.clone(), stream.clone()).await {
+            return Ok(StreamStartResponse {
+                success: true,
+                timeline: timeline_name,
+                already_streaming: true,
+            });
+        }
+
+        let conn = ctx.conn.clone();
+        tokio::spawn(async move {
+            if let Err(e) = stream.start_stream(timeline_type.clone(), conn.event_sender()).await {
+                error!("Stream for {} failed: {}", timeline_type.display_name(), e);
             }
-        };
+            conn.remove_stream(&key, &stream).await;
+        });
+
+        info!("Streaming started for {}", timeline_name);
+        Ok(StreamStartResponse {
+            success: true,
+            timeline: timeline_name,
+            already_streaming: false,
+        })
+    }
+
+    /// Handle timeline stream stop
+    async fn handle_timeline_stream_stop(
+        &self,
+        ctx: &RequestContext,
+        request: StreamRequest,
+    ) -> Result<StreamStopResponse, IpcError> {
+        let account_id = self.target_account(ctx).await;
+        let was_streaming = ctx.conn.stop_stream(&(account_id, request.timeline_type.clone())).await;
+        debug!("Streaming stopped for {}", request.timeline_type.display_name());
+
+        Ok(StreamStopResponse {
+            success: true,
+            timeline: request.timeline_type.display_name(),
+            was_streaming,
+        })
+    }
+
+    // ===== POST HANDLERS =====
+
+    /// Handle post create
+    async fn handle_post_create(&self, ctx: &RequestContext, new_post: NewPost) -> Result<Post, IpcError> {
+        let (_, client) = self.client_for(ctx).await?;
+
+        client.create_post(&new_post).await.map_err(|e| {
+            error!("Failed to create post: {}", e);
+            api_error(&e, format!("Failed to create post: {}", e))
+        })
+    }
+
+    /// Generic post action handler
+    async fn handle_post_action(&self, ctx: &RequestContext, request: PostIdRequest, action: &str) -> Result<Post, IpcError> {
+        let (account_id, client) = self.client_for(ctx).await?;
+        let post_id = request.post_id.as_str();
 
         let result = match action {
             "boost" => client.boost_post(post_id).await,
             "unboost" => client.unboost_post(post_id).await,
             "favourite" => client.favourite_post(post_id).await,
             "unfavourite" => client.unfavourite_post(post_id).await,
-            _ => return IpcMessage::response_err(
-                &msg.id,
-                IpcError::new(error_codes::INTERNAL_ERROR, "Unknown action"),
-            ),
+            _ => return Err(IpcError::new(error_codes::INTERNAL_ERROR, "Unknown action")),
         };
 
-        match result {
-            Ok(post) => {
-                IpcMessage::response_ok(&msg.id, serde_json::to_value(post).unwrap())
-            }
-            Err(e) => {
-                error!("Failed to {} post: {}", action, e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to {} post: {}", action, e)),
-                )
-            }
-        }
+        let post = result.map_err(|e| {
+            error!("Failed to {} post: {}", action, e);
+            api_error(&e, format!("Failed to {} post: {}", action, e))
+        })?;
+
+        self.bus.publish(events::POST_ACTION, PostActionEvent {
+            account_id,
+            action: action.to_string(),
+            post: post.clone(),
+        });
+        Ok(post)
     }
 
+    // ===== INSTANCE HANDLERS =====
+
     /// Handle instance get
-    async fn handle_instance_get(&self, msg: &IpcMessage) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
-        };
+    async fn handle_instance_get(&self, ctx: &RequestContext) -> Result<InstanceInfo, IpcError> {
+        let (_, client) = self.client_for(ctx).await?;
 
-        match client.get_instance_info().await {
-            Ok(info) => {
-                IpcMessage::response_ok(&msg.id, serde_json::to_value(info).unwrap())
-            }
-            Err(e) => {
-                error!("Failed to get instance info: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to get instance info: {}", e)),
-                )
-            }
-        }
+        client.get_instance_info().await.map_err(|e| {
+            error!("Failed to get instance info: {}", e);
+            api_error(&e, format!("Failed to get instance info: {}", e))
+        })
     }
 
-    /// Handle notifications get
-    async fn handle_notifications_get(&self, msg: &IpcMessage) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
-        };
+    // ===== NOTIFICATION HANDLERS =====
 
-        let request: NotificationRequest = match &msg.params {
-            Some(p) => match serde_json::from_value(p.clone()) {
-                Ok(r) => r,
-                Err(e) => {
-                    return IpcMessage::response_err(
-                        &msg.id,
-                        IpcError::new(error_codes::INVALID_PARAMS, format!("Invalid params: {}", e)),
-                    );
-                }
-            },
-            None => NotificationRequest::default(),
-        };
+    /// Handle notifications get
+    async fn handle_notifications_get(
+        &self,
+        ctx: &RequestContext,
+        request: NotificationRequest,
+    ) -> Result<NotificationResponse, IpcError> {
+        let (_, client) = self.client_for(ctx).await?;
 
         debug!("Fetching notifications");
 
-        match client.get_notifications(&request).await {
-            Ok(response) => {
-                IpcMessage::response_ok(&msg.id, serde_json::to_value(response).unwrap())
-            }
-            Err(e) => {
-                error!("Failed to fetch notifications: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to fetch notifications: {}", e)),
-                )
-            }
-        }
+        client.get_notifications(&request).await.map_err(|e| {
+            error!("Failed to fetch notifications: {}", e);
+            api_error(&e, format!("Failed to fetch notifications: {}", e))
+        })
     }
 
     /// Handle notifications clear
-    async fn handle_notifications_clear(&self, msg: &IpcMessage) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
-        };
+    async fn handle_notifications_clear(&self, ctx: &RequestContext) -> Result<SuccessResponse, IpcError> {
+        let (account_id, client) = self.client_for(ctx).await?;
 
         match client.clear_notifications().await {
             Ok(()) => {
                 info!("All notifications cleared");
-                IpcMessage::response_ok(&msg.id, serde_json::json!({ "success": true }))
+                self.bus.publish(events::NOTIFICATION_DISMISSED, NotificationDismissedEvent {
+                    account_id,
+                    notification_id: None,
+                });
+                Ok(SuccessResponse::ok())
             }
             Err(e) => {
                 error!("Failed to clear notifications: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to clear notifications: {}", e)),
-                )
+                Err(api_error(
+                    &e,
+                    format!("Failed to clear notifications: {}", e),
+                ))
             }
         }
     }
 
     /// Handle notification dismiss
-    async fn handle_notifications_dismiss(&self, msg: &IpcMessage) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
-        };
-
-        let params = match &msg.params {
-            Some(p) => p,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
-
-        let notification_id = match params.get("notification_id").and_then(|v| v.as_str()) {
-            Some(id) => id,
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing notification_id"),
-                );
-            }
-        };
+    async fn handle_notifications_dismiss(
+        &self,
+        ctx: &RequestContext,
+        request: NotificationIdRequest,
+    ) -> Result<SuccessResponse, IpcError> {
+        let (account_id, client) = self.client_for(ctx).await?;
+        let notification_id = request.notification_id.as_str();
 
         match client.dismiss_notification(notification_id).await {
             Ok(()) => {
                 debug!("Notification {} dismissed", notification_id);
-                IpcMessage::response_ok(&msg.id, serde_json::json!({ "success": true }))
+                self.bus.publish(events::NOTIFICATION_DISMISSED, NotificationDismissedEvent {
+                    account_id,
+                    notification_id: Some(notification_id.to_string()),
+                });
+                Ok(SuccessResponse::ok())
             }
             Err(e) => {
                 error!("Failed to dismiss notification: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to dismiss notification: {}", e)),
-                )
+                Err(api_error(
+                    &e,
+                    format!("Failed to dismiss notification: {}", e),
+                ))
             }
         }
     }
 
-    /// Handle media upload
-    async fn handle_media_upload(&self, msg: &IpcMessage) -> IpcMessage {
-        let client = match self.client.read().await.as_ref() {
-            Some(c) => c.clone(),
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::NOT_AUTHENTICATED, "Not authenticated"),
-                );
-            }
-        };
+    // ===== MEDIA HANDLERS =====
 
-        let request: MediaUploadRequest = match &msg.params {
-            Some(p) => match serde_json::from_value(p.clone()) {
-                Ok(r) => r,
-                Err(e) => {
-                    return IpcMessage::response_err(
-                        &msg.id,
-                        IpcError::new(error_codes::INVALID_PARAMS, format!("Invalid params: {}", e)),
-                    );
-                }
-            },
-            None => {
-                return IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::INVALID_PARAMS, "Missing params"),
-                );
-            }
-        };
+    /// Handle media upload
+    async fn handle_media_upload(&self, ctx: &RequestContext, request: MediaUploadRequest) -> Result<MediaAttachment, IpcError> {
+        let (_, client) = self.client_for(ctx).await?;
 
         debug!("Uploading media from: {}", request.file_path);
 
         match client.upload_media(&request).await {
             Ok(attachment) => {
                 info!("Media uploaded: {}", attachment.id);
-                IpcMessage::response_ok(&msg.id, serde_json::to_value(attachment).unwrap())
+                Ok(attachment)
             }
             Err(e) => {
                 error!("Failed to upload media: {}", e);
-                IpcMessage::response_err(
-                    &msg.id,
-                    IpcError::new(error_codes::API_ERROR, format!("Failed to upload media: {}", e)),
-                )
+                Err(api_error(
+                    &e,
+                    format!("Failed to upload media: {}", e),
+                ))
             }
         }
     }
 }
+
+/// Error for a failed API call
+///
+/// Calls the server refused with 401 or 403 are reported as AUTH_EXPIRED,
+/// which `handle_message` confirms before flagging the account.
+fn api_error(e: &anyhow::Error, message: String) -> IpcError {
+    let code = if MastodonClient::is_token_rejected(e) {
+        error_codes::AUTH_EXPIRED
+    } else {
+        error_codes::API_ERROR
+    };
+    IpcError::new(code, message)
+}
+
+/// Map a backup failure to its error code
+fn backup_error(e: anyhow::Error) -> IpcError {
+    let code = match e.downcast_ref::<BackupError>() {
+        Some(BackupError::WrongPassphrase) => error_codes::AUTHENTICATION_FAILED,
+        Some(BackupError::Invalid | BackupError::UnsupportedVersion(_)) => error_codes::INVALID_PARAMS,
+        None if e.is::<VaultError>() => return vault_error(e),
+        None => error_codes::INTERNAL_ERROR,
+    };
+    IpcError::new(code, e.to_string())
+}
+
+/// Map a vault failure to its error code
+fn vault_error(e: anyhow::Error) -> IpcError {
+    let code = match e.downcast_ref::<VaultError>() {
+        Some(VaultError::Locked) => error_codes::VAULT_LOCKED,
+        Some(VaultError::WrongPassphrase) => error_codes::AUTHENTICATION_FAILED,
+        Some(VaultError::KeyLost) => error_codes::VAULT_KEY_LOST,
+        Some(VaultError::NoPassphrase | VaultError::NotLocked) => error_codes::INVALID_REQUEST,
+        None => error_codes::ENCRYPTION_ERROR,
+    };
+    IpcError::new(code, e.to_string())
+}
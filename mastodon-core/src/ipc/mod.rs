@@ -18,7 +18,17 @@
 //!
 //! Handles communication between the Rust core and C# UI using named pipes.
 
+pub mod client;
+pub mod replay;
 pub mod server;
+mod bus;
+mod connection;
+mod endpoint;
+mod protocol;
+mod recorder;
+mod registry;
+mod session;
+mod transport;
 mod handler;
 
 pub use server::IpcServer;
@@ -0,0 +1,475 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Wire dialects spoken over an IPC connection
+//!
+//! Besides the native `IpcMessage` envelope the core speaks strict JSON-RPC
+//! 2.0, detected per connection from the first message: an object carrying
+//! `"jsonrpc": "2.0"` or a batch array switches the connection to JSON-RPC.
+//!
+//! Internally every JSON-RPC request is turned into an `IpcMessage`. Its ID
+//! becomes the JSON encoding of the client's ID (so `1` and `"1"` stay
+//! distinct) and is decoded again on the way out. Notifications get an
+//! internal ID starting with `~`, which no JSON encoding can produce, and
+//! their responses are dropped. Requests with a `null` ID get a unique
+//! internal ID starting with `!`, so several can be in flight at once, and
+//! are answered with `null` again.
+//!
+//! When a frame cannot be decoded, its `id` and `method` are recovered on a
+//! best-effort basis so the error response can still be correlated with
+//! the request that caused it.
+
+use serde_json::{json, Map, Value};
+
+use crate::models::{error_codes, methods, IpcError, IpcMessage, MessageType};
+
+/// Prefix of internal IDs given to JSON-RPC notifications
+const NOTIFICATION_ID_PREFIX: &str = "~";
+
+/// Prefix of internal IDs given to JSON-RPC requests with a `null` ID
+const NULL_ID_PREFIX: &str = "!";
+
+/// LSP-style cancellation notification, accepted as `system.cancel`
+const JSONRPC_CANCEL_METHOD: &str = "$/cancelRequest";
+
+/// Wire dialect of a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// Native `IpcMessage` envelope
+    Native,
+    /// Strict JSON-RPC 2.0
+    JsonRpc,
+}
+
+/// A decoded inbound frame
+pub enum Inbound {
+    /// A single request
+    Single(IpcMessage),
+    /// A JSON-RPC batch; invalid entries carry the error response to send
+    Batch(Vec<Result<IpcMessage, Box<IpcMessage>>>),
+    /// A frame that could not be decoded, with the error response to send
+    Invalid(IpcMessage),
+}
+
+impl Dialect {
+    /// Detect the dialect from the first frame of a connection
+    pub fn detect(frame: &str) -> Self {
+        if frame.starts_with('[') {
+            return Dialect::JsonRpc;
+        }
+
+        match serde_json::from_str::<Map<String, Value>>(frame) {
+            Ok(object) if object.contains_key("jsonrpc") => Dialect::JsonRpc,
+            _ => Dialect::Native,
+        }
+    }
+
+    /// Decode an inbound frame
+    pub fn decode(self, frame: &str) -> Inbound {
+        match self {
+            Dialect::Native => match serde_json::from_str::<IpcMessage>(frame) {
+                Ok(msg) => Inbound::Single(msg),
+                Err(e) => {
+                    let recovered = Recovered::from_frame(frame);
+                    let id = match &recovered.id {
+                        Some(Value::String(id)) => id.clone(),
+                        Some(id) => id.to_string(),
+                        None => "unknown".to_string(),
+                    };
+                    Inbound::Invalid(IpcMessage::response_err(
+                        &id,
+                        recovered.annotate(IpcError::new(
+                            error_codes::PARSE_ERROR,
+                            format!("Failed to parse message: {}", e),
+                        )),
+                    ))
+                }
+            },
+            Dialect::JsonRpc => decode_jsonrpc(frame),
+        }
+    }
+
+    /// Error response to a frame discarded for being over `max_frame_size`
+    ///
+    /// Nothing of the frame is kept, so the response cannot be addressed
+    /// to the request.
+    pub fn oversized(self, max_frame_size: usize) -> IpcMessage {
+        let id = match self {
+            Dialect::Native => "unknown",
+            Dialect::JsonRpc => "null",
+        };
+        IpcMessage::response_err(
+            id,
+            IpcError::new(
+                error_codes::INVALID_REQUEST,
+                format!("Message exceeds the maximum frame size of {} bytes", max_frame_size),
+            ),
+        )
+    }
+
+    /// Encode an outbound message
+    ///
+    /// Returns `None` for messages the client must not see, such as
+    /// responses to JSON-RPC notifications.
+    pub fn encode(self, msg: &IpcMessage) -> Option<String> {
+        match self {
+            Dialect::Native => serde_json::to_string(msg).ok(),
+            Dialect::JsonRpc => encode_jsonrpc(msg).map(|value| value.to_string()),
+        }
+    }
+
+    /// Encode the responses to a batch
+    ///
+    /// Returns `None` if nothing needs to be sent, which is the case when
+    /// the batch held only notifications.
+    pub fn encode_batch(self, msgs: &[IpcMessage]) -> Option<String> {
+        let encoded: Vec<String> = msgs.iter().filter_map(|msg| self.encode(msg)).collect();
+        if encoded.is_empty() {
+            None
+        } else {
+            Some(format!("[{}]", encoded.join(",")))
+        }
+    }
+}
+
+/// Decode a JSON-RPC frame
+fn decode_jsonrpc(frame: &str) -> Inbound {
+    let value: Value = match serde_json::from_str(frame) {
+        Ok(value) => value,
+        Err(e) => {
+            let recovered = Recovered::from_frame(frame);
+            let id = recovered.id.as_ref().map_or_else(|| "null".to_string(), Value::to_string);
+            return Inbound::Invalid(IpcMessage::response_err(
+                &id,
+                recovered.annotate(IpcError::new(
+                    error_codes::PARSE_ERROR,
+                    format!("Parse error: {}", e),
+                )),
+            ));
+        }
+    };
+
+    match value {
+        Value::Array(items) if items.is_empty() => {
+            Inbound::Invalid(jsonrpc_error(error_codes::INVALID_REQUEST, "Empty batch"))
+        }
+        Value::Array(items) => Inbound::Batch(items.into_iter().map(decode_jsonrpc_request).collect()),
+        value => match decode_jsonrpc_request(value) {
+            Ok(msg) => Inbound::Single(msg),
+            Err(response) => Inbound::Invalid(*response),
+        },
+    }
+}
+
+/// Turn a single JSON-RPC request object into an `IpcMessage`
+///
+/// On failure returns the error response to send instead.
+fn decode_jsonrpc_request(value: Value) -> Result<IpcMessage, Box<IpcMessage>> {
+    let Value::Object(mut object) = value else {
+        return Err(Box::new(jsonrpc_error(error_codes::INVALID_REQUEST, "Request must be an object")));
+    };
+
+    // Once the ID is known, errors can be addressed to it
+    let id = match object.remove("id") {
+        None => format!("{}{}", NOTIFICATION_ID_PREFIX, uuid::Uuid::new_v4()),
+        Some(Value::Null) => format!("{}{}", NULL_ID_PREFIX, uuid::Uuid::new_v4()),
+        Some(id @ (Value::String(_) | Value::Number(_))) => id.to_string(),
+        Some(_) => {
+            return Err(Box::new(jsonrpc_error(
+                error_codes::INVALID_REQUEST,
+                "Request id must be a string, number or null",
+            )))
+        }
+    };
+    // Invalid requests are answered even without an ID, addressed to null
+    let error_id = if id.starts_with(NOTIFICATION_ID_PREFIX) || id.starts_with(NULL_ID_PREFIX) {
+        "null"
+    } else {
+        id.as_str()
+    };
+    let invalid = |message: &str| {
+        Box::new(IpcMessage::response_err(
+            error_id,
+            IpcError::new(error_codes::INVALID_REQUEST, message),
+        ))
+    };
+
+    if object.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid("jsonrpc must be \"2.0\""));
+    }
+
+    let mut method = match object.remove("method") {
+        Some(Value::String(method)) => method,
+        _ => return Err(invalid("method must be a string")),
+    };
+
+    let mut params = match object.remove("params") {
+        None => None,
+        Some(params @ (Value::Object(_) | Value::Array(_))) => Some(params),
+        Some(_) => return Err(invalid("params must be an object or an array")),
+    };
+
+    // Cancellation refers to requests by their client-side ID
+    if method == JSONRPC_CANCEL_METHOD {
+        method = methods::SYSTEM_CANCEL.to_string();
+    }
+    if method == methods::SYSTEM_CANCEL {
+        if let Some(Value::Object(params)) = params.as_mut() {
+            if let Some(target) = params.get_mut("id") {
+                *target = Value::String(target.to_string());
+            }
+        }
+    }
+
+    Ok(IpcMessage {
+        id,
+        message_type: MessageType::Request,
+        method: Some(method),
+        params,
+        result: None,
+        error: None,
+    })
+}
+
+/// Encode an `IpcMessage` as a JSON-RPC response or notification
+fn encode_jsonrpc(msg: &IpcMessage) -> Option<Value> {
+    match msg.message_type {
+        MessageType::Event => {
+            let mut notification = json!({ "jsonrpc": "2.0", "method": msg.method });
+            if let Some(params) = &msg.params {
+                notification["params"] = params.clone();
+            }
+            Some(notification)
+        }
+        MessageType::Response | MessageType::Request => {
+            if msg.id.starts_with(NOTIFICATION_ID_PREFIX) {
+                return None;
+            }
+
+            // IDs the core made up itself (such as for unparseable frames
+            // and null-ID requests) become null
+            let id = serde_json::from_str::<Value>(&msg.id).unwrap_or(Value::Null);
+
+            let mut response = json!({ "jsonrpc": "2.0", "id": id });
+            match &msg.error {
+                Some(error) => {
+                    let mut error_object = json!({ "code": error.code, "message": error.message });
+                    if let Some(data) = &error.data {
+                        error_object["data"] = data.clone();
+                    }
+                    response["error"] = error_object;
+                }
+                None => response["result"] = msg.result.clone().unwrap_or(Value::Null),
+            }
+            Some(response)
+        }
+    }
+}
+
+/// Error response to a JSON-RPC frame whose ID could not be determined
+fn jsonrpc_error(code: i32, message: impl Into<String>) -> IpcMessage {
+    IpcMessage::response_err("null", IpcError::new(code, message))
+}
+
+/// The `id` and `method` of a frame that failed to decode, if they could be found
+struct Recovered {
+    id: Option<Value>,
+    method: Option<String>,
+}
+
+impl Recovered {
+    /// Look for `id` and `method` in a frame
+    ///
+    /// Frames that are valid JSON but not a valid message are read
+    /// properly. Anything else is scanned for the first `"id": <value>` and
+    /// `"method": "<name>"` pairs, which finds them in truncated or
+    /// otherwise malformed frames as long as those pairs are intact.
+    fn from_frame(frame: &str) -> Self {
+        if let Ok(Value::Object(object)) = serde_json::from_str::<Value>(frame) {
+            return Self {
+                id: object.get("id").filter(|id| id.is_string() || id.is_number()).cloned(),
+                method: object.get("method").and_then(Value::as_str).map(str::to_string),
+            };
+        }
+
+        Self {
+            id: scan_value(frame, "id").filter(|id| id.is_string() || id.is_number()),
+            method: scan_value(frame, "method").and_then(|method| method.as_str().map(str::to_string)),
+        }
+    }
+
+    /// Attach the recovered method to an error
+    fn annotate(&self, error: IpcError) -> IpcError {
+        match &self.method {
+            Some(method) => error.with_data(json!({ "method": method })),
+            None => error,
+        }
+    }
+}
+
+/// Find the value of the first `"key": <value>` pair in possibly malformed JSON
+fn scan_value(frame: &str, key: &str) -> Option<Value> {
+    let needle = format!("\"{}\"", key);
+    let mut rest = frame;
+
+    while let Some(pos) = rest.find(&needle) {
+        rest = &rest[pos + needle.len()..];
+        if let Some(value) = rest.trim_start().strip_prefix(':') {
+            return serde_json::Deserializer::from_str(value.trim_start())
+                .into_iter::<Value>()
+                .next()
+                .and_then(Result::ok);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single(inbound: Inbound) -> IpcMessage {
+        match inbound {
+            Inbound::Single(msg) => msg,
+            _ => panic!("expected a single request"),
+        }
+    }
+
+    fn invalid(inbound: Inbound) -> IpcMessage {
+        match inbound {
+            Inbound::Invalid(msg) => msg,
+            _ => panic!("expected an error response"),
+        }
+    }
+
+    #[test]
+    fn detects_dialect_from_first_frame() {
+        assert_eq!(Dialect::detect(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#), Dialect::JsonRpc);
+        assert_eq!(Dialect::detect(r#"[{"jsonrpc":"2.0","id":1,"method":"ping"}]"#), Dialect::JsonRpc);
+        assert_eq!(Dialect::detect(r#"{"id":"a","type":"request","method":"ping"}"#), Dialect::Native);
+        assert_eq!(Dialect::detect("not json"), Dialect::Native);
+    }
+
+    #[test]
+    fn native_round_trip() {
+        let request = IpcMessage::request("ping", None);
+        let frame = Dialect::Native.encode(&request).unwrap();
+        let decoded = single(Dialect::Native.decode(&frame));
+        assert_eq!(decoded.id, request.id);
+        assert_eq!(decoded.method.as_deref(), Some("ping"));
+    }
+
+    #[test]
+    fn jsonrpc_ids_keep_their_type() {
+        let number = single(Dialect::JsonRpc.decode(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#));
+        let string = single(Dialect::JsonRpc.decode(r#"{"jsonrpc":"2.0","id":"1","method":"ping"}"#));
+        assert_ne!(number.id, string.id);
+
+        let response = Dialect::JsonRpc.encode(&IpcMessage::response_ok(&string.id, json!("pong"))).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response, json!({ "jsonrpc": "2.0", "id": "1", "result": "pong" }));
+    }
+
+    #[test]
+    fn jsonrpc_null_ids_are_unique_and_answered_with_null() {
+        let frame = r#"{"jsonrpc":"2.0","id":null,"method":"ping"}"#;
+        let first = single(Dialect::JsonRpc.decode(frame));
+        let second = single(Dialect::JsonRpc.decode(frame));
+        assert_ne!(first.id, second.id);
+
+        let response = Dialect::JsonRpc.encode(&IpcMessage::response_ok(&first.id, json!("pong"))).unwrap();
+        let response: Value = serde_json::from_str(&response).unwrap();
+        assert_eq!(response["id"], Value::Null);
+    }
+
+    #[test]
+    fn jsonrpc_notification_responses_are_dropped() {
+        let notification = single(Dialect::JsonRpc.decode(r#"{"jsonrpc":"2.0","method":"ping"}"#));
+        assert!(Dialect::JsonRpc.encode(&IpcMessage::response_ok(&notification.id, json!("pong"))).is_none());
+        assert!(Dialect::JsonRpc
+            .encode_batch(&[IpcMessage::response_ok(&notification.id, json!("pong"))])
+            .is_none());
+    }
+
+    #[test]
+    fn jsonrpc_batch_keeps_invalid_entries() {
+        let Inbound::Batch(entries) = Dialect::JsonRpc.decode(r#"[{"jsonrpc":"2.0","id":1,"method":"ping"},42]"#) else {
+            panic!("expected a batch");
+        };
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_ok());
+        let error = entries[1].as_ref().unwrap_err();
+        assert_eq!(error.error.as_ref().unwrap().code, error_codes::INVALID_REQUEST);
+
+        let empty = invalid(Dialect::JsonRpc.decode("[]"));
+        assert_eq!(empty.error.unwrap().code, error_codes::INVALID_REQUEST);
+    }
+
+    #[test]
+    fn jsonrpc_rejects_invalid_requests_with_their_id() {
+        let response = invalid(Dialect::JsonRpc.decode(r#"{"jsonrpc":"1.0","id":7,"method":"ping"}"#));
+        assert_eq!(response.id, "7");
+        assert_eq!(response.error.unwrap().code, error_codes::INVALID_REQUEST);
+
+        let response = invalid(Dialect::JsonRpc.decode(r#"{"jsonrpc":"2.0","id":7,"method":"ping","params":3}"#));
+        assert_eq!(response.error.unwrap().code, error_codes::INVALID_REQUEST);
+    }
+
+    #[test]
+    fn jsonrpc_cancel_targets_the_internal_id() {
+        let cancel = single(Dialect::JsonRpc.decode(r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":5}}"#));
+        assert_eq!(cancel.method.as_deref(), Some(methods::SYSTEM_CANCEL));
+        assert_eq!(cancel.params.unwrap()["id"], json!("5"));
+    }
+
+    #[test]
+    fn jsonrpc_events_become_notifications() {
+        let event = IpcMessage::event("event.new_post", json!({ "id": "1" }));
+        let encoded: Value = serde_json::from_str(&Dialect::JsonRpc.encode(&event).unwrap()).unwrap();
+        assert_eq!(encoded, json!({ "jsonrpc": "2.0", "method": "event.new_post", "params": { "id": "1" } }));
+    }
+
+    #[test]
+    fn parse_errors_are_addressed_to_the_recovered_id() {
+        let response = invalid(Dialect::Native.decode(r#"{"id":"abc","method":"timeline.get","type":"#));
+        assert_eq!(response.id, "abc");
+        let error = response.error.unwrap();
+        assert_eq!(error.code, error_codes::PARSE_ERROR);
+        assert_eq!(error.data, Some(json!({ "method": "timeline.get" })));
+
+        let response = invalid(Dialect::JsonRpc.decode(r#"{"jsonrpc":"2.0","id":9,"method":"ping""#));
+        assert_eq!(response.id, "9");
+    }
+
+    #[test]
+    fn recovers_id_and_method() {
+        let valid = Recovered::from_frame(r#"{"id":3,"method":"ping","type":"bogus"}"#);
+        assert_eq!(valid.id, Some(json!(3)));
+        assert_eq!(valid.method.as_deref(), Some("ping"));
+
+        let truncated = Recovered::from_frame(r#"{"method": "post.create", "id": "x1", "params": {"#);
+        assert_eq!(truncated.id, Some(json!("x1")));
+        assert_eq!(truncated.method.as_deref(), Some("post.create"));
+
+        let object_id = Recovered::from_frame(r#"{"id":{"nested":1},"method":"ping"}"#);
+        assert_eq!(object_id.id, None);
+
+        let garbage = Recovered::from_frame("garbage");
+        assert_eq!(garbage.id, None);
+        assert_eq!(garbage.method, None);
+    }
+}
@@ -0,0 +1,304 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Opt-in recording of IPC sessions
+//!
+//! With `--record <file>` every message a connection receives or sends is
+//! appended to a JSONL file, one `RecordedMessage` per line, so a session a
+//! user reports can be replayed later (see `replay`). Credentials and, by
+//! default, post content are redacted before anything reaches the file.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::models::IpcMessage;
+
+/// Placeholder written in place of redacted values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Keys holding tokens and secrets
+const TOKEN_KEYS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "token",
+    "secret",
+    "client_secret",
+    "password",
+    "passphrase",
+    "blindodon_pm_private_key",
+];
+
+/// Keys holding OAuth authorization codes
+const CODE_KEYS: &[&str] = &["code", "code_verifier"];
+
+/// Keys holding post and profile content
+const CONTENT_KEYS: &[&str] = &["content", "plain_content", "spoiler_text", "note", "description", "text"];
+
+/// Which kinds of values are redacted from recordings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redaction {
+    /// Access tokens, client secrets and the session secret
+    pub tokens: bool,
+    /// OAuth authorization codes
+    pub codes: bool,
+    /// Post text, content warnings, bios and media descriptions
+    pub content: bool,
+}
+
+impl Default for Redaction {
+    fn default() -> Self {
+        Self {
+            tokens: true,
+            codes: true,
+            content: true,
+        }
+    }
+}
+
+impl std::str::FromStr for Redaction {
+    type Err = anyhow::Error;
+
+    /// Parse a comma-separated list of `tokens`, `codes` and `content`,
+    /// or `none`
+    fn from_str(value: &str) -> Result<Self> {
+        let mut redaction = Self {
+            tokens: false,
+            codes: false,
+            content: false,
+        };
+
+        for kind in value.split(',').map(str::trim).filter(|kind| !kind.is_empty()) {
+            match kind {
+                "tokens" => redaction.tokens = true,
+                "codes" => redaction.codes = true,
+                "content" => redaction.content = true,
+                "none" => {}
+                _ => anyhow::bail!("Unknown redaction {:?} (expected tokens, codes, content or none)", kind),
+            }
+        }
+
+        Ok(redaction)
+    }
+}
+
+impl Redaction {
+    /// Whether values under `key` are redacted
+    fn covers(&self, key: &str) -> bool {
+        (self.tokens && TOKEN_KEYS.contains(&key))
+            || (self.codes && CODE_KEYS.contains(&key))
+            || (self.content && CONTENT_KEYS.contains(&key))
+    }
+
+    /// Replace redacted string values anywhere in `value`
+    pub fn apply(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    if value.is_string() && self.covers(key) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.apply(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply(item)),
+            _ => {}
+        }
+    }
+}
+
+/// Whether a message was received or sent by the core
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    /// From the client to the core
+    #[serde(rename = "in")]
+    Inbound,
+    /// From the core to the client
+    #[serde(rename = "out")]
+    Outbound,
+}
+
+/// One line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    /// When the message passed through the connection
+    pub timestamp: DateTime<Utc>,
+    /// Connection the message belongs to, numbered from 1
+    pub connection: u64,
+    /// Whether the core received or sent the message
+    pub direction: Direction,
+    /// The message, redacted
+    pub message: IpcMessage,
+}
+
+/// Appends the messages of every connection to a recording file
+pub struct Recorder {
+    file: Mutex<LineWriter<File>>,
+    redaction: Redaction,
+    next_connection: AtomicU64,
+}
+
+impl Recorder {
+    /// Start a new recording at `path`, replacing any existing file
+    pub fn create(path: &Path, redaction: Redaction) -> Result<Self> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let file = options
+            .open(path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+        info!("Recording IPC messages to {} ({:?})", path.display(), redaction);
+
+        Ok(Self {
+            file: Mutex::new(LineWriter::new(file)),
+            redaction,
+            next_connection: AtomicU64::new(1),
+        })
+    }
+
+    /// Start recording a new connection
+    pub fn connection(self: &Arc<Self>) -> ConnectionRecorder {
+        ConnectionRecorder {
+            recorder: self.clone(),
+            connection: self.next_connection.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    fn write(&self, connection: u64, direction: Direction, msg: &IpcMessage) {
+        let mut message = match serde_json::to_value(msg) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to record message: {}", e);
+                return;
+            }
+        };
+        self.redaction.apply(&mut message);
+
+        let line = serde_json::json!({
+            "timestamp": Utc::now(),
+            "connection": connection,
+            "direction": direction,
+            "message": message,
+        });
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{}", line) {
+            warn!("Failed to write to recording: {}", e);
+        }
+    }
+}
+
+/// Records the messages of one connection
+#[derive(Clone)]
+pub struct ConnectionRecorder {
+    recorder: Arc<Recorder>,
+    connection: u64,
+}
+
+impl ConnectionRecorder {
+    /// Record a message received from the client
+    pub fn inbound(&self, msg: &IpcMessage) {
+        self.recorder.write(self.connection, Direction::Inbound, msg);
+    }
+
+    /// Record a message sent to the client
+    pub fn outbound(&self, msg: &IpcMessage) {
+        self.recorder.write(self.connection, Direction::Outbound, msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn redacts_nested_values_by_default() {
+        let mut value = json!({
+            "access_token": "abc",
+            "params": { "secret": "s3cret", "code": "xyz" },
+            "posts": [{ "id": "1", "content": "<p>hello</p>", "spoiler_text": "cw" }],
+        });
+        Redaction::default().apply(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "access_token": REDACTED,
+                "params": { "secret": REDACTED, "code": REDACTED },
+                "posts": [{ "id": "1", "content": REDACTED, "spoiler_text": REDACTED }],
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_kinds_that_are_not_redacted() {
+        let mut value = json!({ "token": "abc", "code": "xyz", "note": "bio" });
+        "tokens".parse::<Redaction>().unwrap().apply(&mut value);
+        assert_eq!(value, json!({ "token": REDACTED, "code": "xyz", "note": "bio" }));
+
+        let mut value = json!({ "token": "abc" });
+        "none".parse::<Redaction>().unwrap().apply(&mut value);
+        assert_eq!(value, json!({ "token": "abc" }));
+    }
+
+    #[test]
+    fn leaves_non_string_values_under_redacted_keys() {
+        let mut value = json!({ "content": { "text": "hi", "id": 3 }, "token": null });
+        Redaction::default().apply(&mut value);
+        assert_eq!(value, json!({ "content": { "text": REDACTED, "id": 3 }, "token": null }));
+    }
+
+    #[test]
+    fn parses_redaction_lists() {
+        let redaction: Redaction = "tokens, content".parse().unwrap();
+        assert_eq!(redaction, Redaction { tokens: true, codes: false, content: true });
+        assert!("tokens,everything".parse::<Redaction>().is_err());
+    }
+
+    #[test]
+    fn recorded_lines_are_redacted() {
+        let path = std::env::temp_dir().join(format!("blindodon-recording-{}.jsonl", uuid::Uuid::new_v4()));
+        let recorder = Arc::new(Recorder::create(&path, Redaction::default()).unwrap());
+        let connection = recorder.connection();
+        connection.inbound(&IpcMessage::request("system.authenticate", Some(json!({ "secret": "s3cret" }))));
+        connection.outbound(&IpcMessage::response_ok("1", json!({ "authenticated": true })));
+        drop(connection);
+        drop(recorder);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<RecordedMessage> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].connection, 1);
+        assert_eq!(lines[0].direction, Direction::Inbound);
+        assert_eq!(lines[0].message.params, Some(json!({ "secret": REDACTED })));
+        assert_eq!(lines[1].direction, Direction::Outbound);
+        assert!(!contents.contains("s3cret"));
+    }
+}
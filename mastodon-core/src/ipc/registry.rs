@@ -0,0 +1,248 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Typed IPC method registry
+//!
+//! Every method is registered with serde-typed parameters and result. The
+//! registry deserializes parameters before the method runs, reporting the
+//! failing field path in INVALID_PARAMS errors, and keeps a JSON Schema of
+//! every method and event for `system.describe`, along with one of the
+//! `CommonParams` that any request may carry.
+
+use schemars::{JsonSchema, SchemaGenerator};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::models::{error_codes, CommonParams, EventDescription, IpcError, MethodDescription};
+
+use super::connection::ConnectionContext;
+use super::handler::MessageHandler;
+
+/// Future returned by a registered method
+pub type MethodFuture<'a, R> = Pin<Box<dyn Future<Output = Result<R, IpcError>> + Send + 'a>>;
+
+/// A method with its parameter and result types erased to JSON
+type ErasedMethod = Box<
+    dyn for<'a> Fn(&'a MessageHandler, &'a RequestContext, Value) -> MethodFuture<'a, Value>
+        + Send
+        + Sync,
+>;
+
+/// Context of a single request, passed to every method
+pub struct RequestContext {
+    /// Connection the request arrived on
+    pub conn: Arc<ConnectionContext>,
+    /// Account named by the request's `account_id` common parameter, if any
+    pub account_id: Option<String>,
+}
+
+/// A registered method
+struct MethodEntry {
+    description: &'static str,
+    params_schema: Value,
+    result_schema: Value,
+    call: ErasedMethod,
+}
+
+/// A registered event
+struct EventEntry {
+    name: &'static str,
+    description: &'static str,
+    params_schema: Value,
+}
+
+/// Registry of IPC methods and events
+pub struct MethodRegistry {
+    methods: BTreeMap<&'static str, MethodEntry>,
+    events: Vec<EventEntry>,
+    common_params_schema: Value,
+    definitions: serde_json::Map<String, Value>,
+}
+
+/// Builder collecting methods, events and their shared schema definitions
+pub struct MethodRegistryBuilder {
+    methods: BTreeMap<&'static str, MethodEntry>,
+    events: Vec<EventEntry>,
+    generator: SchemaGenerator,
+}
+
+impl MethodRegistryBuilder {
+    /// Create an empty builder
+    pub fn new() -> Self {
+        Self {
+            methods: BTreeMap::new(),
+            events: Vec::new(),
+            generator: SchemaGenerator::default(),
+        }
+    }
+
+    /// Register a method with typed parameters and result
+    ///
+    /// Missing parameters are treated as an empty object, so methods whose
+    /// parameters are all optional may be called without any.
+    pub fn method<P, R, F>(&mut self, name: &'static str, description: &'static str, method: F) -> &mut Self
+    where
+        P: DeserializeOwned + JsonSchema + Send + 'static,
+        R: Serialize + JsonSchema + 'static,
+        F: for<'a> Fn(&'a MessageHandler, &'a RequestContext, P) -> MethodFuture<'a, R>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let params_schema = self.generator.subschema_for::<P>().to_value();
+        let result_schema = self.generator.subschema_for::<R>().to_value();
+
+        let call: ErasedMethod = Box::new(move |handler, ctx, params| {
+            let params = match parse_params::<P>(params) {
+                Ok(p) => p,
+                Err(e) => return Box::pin(async move { Err(e) }),
+            };
+            let future = method(handler, ctx, params);
+            Box::pin(async move {
+                let result = future.await?;
+                serde_json::to_value(result).map_err(|e| {
+                    IpcError::new(
+                        error_codes::INTERNAL_ERROR,
+                        format!("Failed to serialize result: {}", e),
+                    )
+                })
+            })
+        });
+
+        let previous = self.methods.insert(
+            name,
+            MethodEntry {
+                description,
+                params_schema,
+                result_schema,
+                call,
+            },
+        );
+        debug_assert!(previous.is_none(), "method {} registered twice", name);
+
+        self
+    }
+
+    /// Register an event with typed parameters
+    pub fn event<P>(&mut self, name: &'static str, description: &'static str) -> &mut Self
+    where
+        P: JsonSchema,
+    {
+        let params_schema = self.generator.subschema_for::<P>().to_value();
+        self.events.push(EventEntry {
+            name,
+            description,
+            params_schema,
+        });
+        self
+    }
+
+    /// Finish building the registry
+    pub fn build(mut self) -> MethodRegistry {
+        let common_params_schema = self.generator.subschema_for::<CommonParams>().to_value();
+        MethodRegistry {
+            methods: self.methods,
+            events: self.events,
+            common_params_schema,
+            definitions: self.generator.take_definitions(true),
+        }
+    }
+}
+
+impl Default for MethodRegistryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MethodRegistry {
+    /// Call a registered method
+    ///
+    /// Returns `None` if no method with this name is registered.
+    pub async fn call(
+        &self,
+        name: &str,
+        handler: &MessageHandler,
+        ctx: &RequestContext,
+        params: Option<Value>,
+    ) -> Option<Result<Value, IpcError>> {
+        let entry = self.methods.get(name)?;
+        let params = params.unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+        Some((entry.call)(handler, ctx, params).await)
+    }
+
+    /// Names of all registered methods, sorted
+    pub fn method_names(&self) -> Vec<String> {
+        self.methods.keys().map(|name| name.to_string()).collect()
+    }
+
+    /// Names of all registered events
+    pub fn event_names(&self) -> Vec<String> {
+        self.events.iter().map(|e| e.name.to_string()).collect()
+    }
+
+    /// Descriptions and schemas of all registered methods
+    pub fn method_descriptions(&self) -> Vec<MethodDescription> {
+        self.methods
+            .iter()
+            .map(|(name, entry)| MethodDescription {
+                name: name.to_string(),
+                description: entry.description.to_string(),
+                params: entry.params_schema.clone(),
+                result: entry.result_schema.clone(),
+            })
+            .collect()
+    }
+
+    /// Descriptions and schemas of all registered events
+    pub fn event_descriptions(&self) -> Vec<EventDescription> {
+        self.events
+            .iter()
+            .map(|entry| EventDescription {
+                name: entry.name.to_string(),
+                description: entry.description.to_string(),
+                params: entry.params_schema.clone(),
+            })
+            .collect()
+    }
+
+    /// Schema of the parameters every method accepts alongside its own
+    pub fn common_params_schema(&self) -> &Value {
+        &self.common_params_schema
+    }
+
+    /// Schema definitions shared by all method and event schemas
+    pub fn definitions(&self) -> &serde_json::Map<String, Value> {
+        &self.definitions
+    }
+}
+
+/// Deserialize method parameters, reporting the path of the failing field
+fn parse_params<P: DeserializeOwned>(params: Value) -> Result<P, IpcError> {
+    serde_path_to_error::deserialize(params).map_err(|e| {
+        let path = e.path().to_string();
+        IpcError::new(
+            error_codes::INVALID_PARAMS,
+            format!("Invalid params at '{}': {}", path, e.inner()),
+        )
+        .with_data(serde_json::json!({ "path": path }))
+    })
+}
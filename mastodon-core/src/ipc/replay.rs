@@ -0,0 +1,241 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Replaying recorded IPC sessions
+//!
+//! `--replay <file>` feeds the requests of a recording (see `recorder`)
+//! back through a fresh `MessageHandler`, one connection after another and
+//! one request at a time, and compares each response with the recorded
+//! one. Differences are printed by JSON path. Values redacted in the
+//! recording match anything.
+//!
+//! The requests run against the real local account, so by default only
+//! those that read are replayed. Requests that would post, follow, log out,
+//! import a backup or change anything else are skipped unless mutating
+//! requests are explicitly allowed.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tracing::{info, warn};
+
+use crate::cache::CacheManager;
+use crate::models::{methods, IpcMessage, MessageType};
+
+use super::connection::ConnectionContext;
+use super::handler::MessageHandler;
+use super::recorder::{Direction, RecordedMessage, REDACTED};
+
+/// Number of streamed events buffered while replaying; they are discarded
+const EVENT_QUEUE_SIZE: usize = 64;
+
+/// Methods that change nothing on the server or in local data
+const READ_ONLY_METHODS: &[&str] = &[
+    methods::AUTH_GET_ACCOUNTS,
+    methods::VAULT_STATUS,
+    methods::SETTINGS_GET,
+    methods::SETTINGS_GET_ALL,
+    methods::TIMELINE_GET,
+    methods::TIMELINE_FILL_GAP,
+    methods::POST_GET_CONTEXT,
+    methods::USER_GET,
+    methods::NOTIFICATIONS_GET,
+    methods::SEARCH,
+    methods::INSTANCE_GET,
+    methods::SYSTEM_HELLO,
+    methods::SYSTEM_DESCRIBE,
+    methods::SYSTEM_CANCEL,
+    methods::SYSTEM_AUTHENTICATE,
+    methods::PING,
+];
+
+/// Outcome of a replay
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    /// Requests whose response matched the recording
+    pub matched: usize,
+    /// Requests whose response differed from the recording
+    pub differed: usize,
+    /// Requests the recording holds no response for
+    pub unanswered: usize,
+    /// Mutating requests that were not replayed
+    pub skipped: usize,
+}
+
+/// Replay a recording file against a fresh handler
+///
+/// The handler starts like the server's, restoring the saved session, so
+/// requests run against the local account. Requests that change anything
+/// are only replayed with `allow_mutating`. Fails if any response differed.
+pub async fn run_replay(path: &Path, allow_mutating: bool) -> Result<()> {
+    let recording = read_recording(path)?;
+    info!("Replaying {} recorded message(s) from {}", recording.len(), path.display());
+
+    let cache = Arc::new(CacheManager::new().await?);
+    // Nothing listens for shutdown requests while replaying
+    let (shutdown_tx, _) = watch::channel(None);
+    let handler = MessageHandler::new(cache.clone(), shutdown_tx);
+    if let Err(e) = handler.initialize().await {
+        warn!("Failed to initialize handler (will continue anyway): {}", e);
+    }
+
+    if allow_mutating {
+        warn!("Replaying mutating requests against the local account");
+    }
+    let summary = replay(&handler, recording, allow_mutating).await;
+    cache.close().await;
+
+    let summary = summary?;
+    if summary.differed > 0 {
+        anyhow::bail!("{} response(s) differed from the recording", summary.differed);
+    }
+    Ok(())
+}
+
+/// Read a recording file
+pub fn read_recording(path: &Path) -> Result<Vec<RecordedMessage>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Failed to open recording {}", path.display()))?;
+
+    std::io::BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|(index, line)| {
+            let line = line?;
+            serde_json::from_str(&line).with_context(|| format!("Invalid recording entry on line {}", index + 1))
+        })
+        .collect()
+}
+
+/// Replay the requests in a recording and print how the responses compare
+///
+/// Only read-only requests are replayed unless `allow_mutating` is set.
+pub async fn replay(handler: &MessageHandler, recording: Vec<RecordedMessage>, allow_mutating: bool) -> Result<ReplaySummary> {
+    let mut connections: BTreeMap<u64, Vec<RecordedMessage>> = BTreeMap::new();
+    for entry in recording {
+        connections.entry(entry.connection).or_default().push(entry);
+    }
+
+    let mut summary = ReplaySummary::default();
+
+    for (connection, entries) in connections {
+        println!("Connection {}", connection);
+
+        let (events_tx, mut events_rx) = mpsc::channel(EVENT_QUEUE_SIZE);
+        tokio::spawn(async move { while events_rx.recv().await.is_some() {} });
+        let context = Arc::new(ConnectionContext::new(events_tx));
+
+        let requests = entries.iter().filter(|entry| {
+            entry.direction == Direction::Inbound && entry.message.message_type == MessageType::Request
+        });
+
+        for request in requests {
+            let msg = &request.message;
+            let method = msg.method.as_deref().unwrap_or("unknown");
+            if !allow_mutating && !READ_ONLY_METHODS.contains(&method) {
+                println!("  {} ({}): skipped, may change data (use --replay-mutating)", method, msg.id);
+                summary.skipped += 1;
+                continue;
+            }
+
+            let recorded = entries.iter().find(|entry| {
+                entry.direction == Direction::Outbound
+                    && entry.message.message_type == MessageType::Response
+                    && entry.message.id == msg.id
+            });
+
+            let response = handler.handle_message(msg.clone(), &context).await;
+
+            let Some(recorded) = recorded else {
+                println!("  {} ({}): no recorded response", method, msg.id);
+                summary.unanswered += 1;
+                continue;
+            };
+
+            let mut differences = Vec::new();
+            diff("", &outcome(&recorded.message), &outcome(&response), &mut differences);
+
+            if differences.is_empty() {
+                println!("  {} ({}): matches", method, msg.id);
+                summary.matched += 1;
+            } else {
+                println!("  {} ({}): differs", method, msg.id);
+                for difference in differences {
+                    println!("    {}", difference);
+                }
+                summary.differed += 1;
+            }
+        }
+
+        context.close().await;
+    }
+
+    println!(
+        "{} matched, {} differed, {} without a recorded response, {} skipped",
+        summary.matched, summary.differed, summary.unanswered, summary.skipped
+    );
+    Ok(summary)
+}
+
+/// The part of a response that is compared
+fn outcome(msg: &IpcMessage) -> Value {
+    serde_json::json!({ "result": msg.result, "error": msg.error })
+}
+
+/// Collect the paths at which `replayed` differs from `recorded`
+fn diff(path: &str, recorded: &Value, replayed: &Value, differences: &mut Vec<String>) {
+    match (recorded, replayed) {
+        (Value::String(redacted), _) if redacted == REDACTED => {}
+        (Value::Object(recorded), Value::Object(replayed)) => {
+            for (key, recorded_value) in recorded {
+                let child = join(path, key);
+                match replayed.get(key) {
+                    Some(replayed_value) => diff(&child, recorded_value, replayed_value, differences),
+                    None => differences.push(format!("{}: missing from replay", child)),
+                }
+            }
+            for key in replayed.keys().filter(|key| !recorded.contains_key(*key)) {
+                differences.push(format!("{}: not in recording", join(path, key)));
+            }
+        }
+        (Value::Array(recorded), Value::Array(replayed)) if recorded.len() == replayed.len() => {
+            for (index, (recorded, replayed)) in recorded.iter().zip(replayed).enumerate() {
+                diff(&format!("{}[{}]", path, index), recorded, replayed, differences);
+            }
+        }
+        (Value::Array(recorded), Value::Array(replayed)) => differences.push(format!(
+            "{}: recorded {} item(s), replayed {}",
+            path,
+            recorded.len(),
+            replayed.len()
+        )),
+        _ if recorded == replayed => {}
+        _ => differences.push(format!("{}: recorded {}, replayed {}", path, recorded, replayed)),
+    }
+}
+
+/// Path of a key below `path`
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
@@ -14,98 +14,524 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-//! IPC Server implementation using named pipes
+//! IPC server and its listeners
+//!
+//! The core listens on a named pipe (Windows) or Unix socket, and optionally
+//! on a localhost TCP port serving both line-delimited JSON and WebSockets.
+//! With `--stdio` it instead serves the parent process that spawned it over
+//! stdin and stdout.
+//! Web pages can reach that port too, so WebSocket handshakes sent by a
+//! browser are only accepted from configured origins.
 
 use anyhow::{Context, Result};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::{broadcast, Mutex};
-use tracing::{debug, error, info, warn};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{header, StatusCode};
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_util::task::TaskTracker;
+use tracing::{error, info, warn};
 
 use crate::cache::CacheManager;
-use crate::models::{IpcMessage, MessageType};
 
+use super::connection::serve_connection;
+use super::endpoint;
 use super::handler::MessageHandler;
+use super::recorder::{Recorder, Redaction};
+use super::session::{self, SessionAuth, Transport};
+use super::transport::{self, FrameReader, FrameWriter, Framing};
+
+/// Default number of requests a single connection may have in flight
+const DEFAULT_MAX_IN_FLIGHT_REQUESTS: usize = 32;
+
+/// Default time running requests get to finish during shutdown
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Age in days after which cached timeline posts are dropped at startup
+const CACHE_MAX_AGE_DAYS: u32 = 30;
+
+/// Default maximum size of an inbound message in bytes
+pub(crate) const DEFAULT_MAX_FRAME_SIZE: usize = 4 * 1024 * 1024;
+
+/// Time a TCP client gets to show its transport and finish a WebSocket handshake
+const TCP_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause between peeks while the start of a TCP connection trickles in
+const SNIFF_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// IPC server configuration
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Named pipe name (Windows) or socket path (Unix) to listen on
+    pub endpoint: String,
+    /// Maximum number of concurrently running requests per connection
+    pub max_in_flight_requests: usize,
+    /// How long running requests get to finish during shutdown
+    pub shutdown_timeout: Duration,
+    /// Where to write the session secret (`None` for the default location)
+    pub secret_file: Option<PathBuf>,
+    /// Print the session secret on stdout for a parent process instead of
+    /// writing it to a file
+    pub print_secret: bool,
+    /// Also listen on this 127.0.0.1 port for TCP and WebSocket clients
+    /// (0 picks a free port)
+    pub tcp_port: Option<u16>,
+    /// Origins, such as `moz-extension://<uuid>`, whose pages may open a
+    /// WebSocket; handshakes without an `Origin` header are always accepted
+    pub allowed_origins: Vec<String>,
+    /// Serve a single client on stdin/stdout instead of the endpoint
+    pub stdio: bool,
+    /// How messages are delimited on byte-stream transports
+    pub framing: Framing,
+    /// Largest inbound message accepted, in bytes
+    pub max_frame_size: usize,
+    /// Record every message to this JSONL file
+    pub record: Option<PathBuf>,
+    /// What to redact from the recording
+    pub record_redaction: Redaction,
+    /// Replay this recording instead of serving clients
+    pub replay: Option<PathBuf>,
+    /// Also replay requests that change the account or local data
+    pub replay_mutating: bool,
+}
 
-/// Named pipe name for Windows
-#[cfg(windows)]
-const PIPE_NAME: &str = r"\\.\pipe\blindodon_ipc";
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: endpoint::default_endpoint(),
+            max_in_flight_requests: DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            secret_file: None,
+            print_secret: false,
+            tcp_port: None,
+            allowed_origins: Vec::new(),
+            stdio: false,
+            framing: Framing::Lines,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            record: None,
+            record_redaction: Redaction::default(),
+            replay: None,
+            replay_mutating: false,
+        }
+    }
+}
 
-/// Unix socket path
-#[cfg(not(windows))]
-const PIPE_NAME: &str = "/tmp/blindodon_ipc.sock";
+impl ServerConfig {
+    /// Build a configuration from defaults overridden by environment variables
+    ///
+    /// `BLINDODON_SOCKET` sets the endpoint, `BLINDODON_SECRET_FILE` the
+    /// session secret file, `BLINDODON_TCP_PORT` the localhost TCP port,
+    /// `BLINDODON_ALLOWED_ORIGINS` a comma-separated list of WebSocket origins,
+    /// `BLINDODON_MAX_IN_FLIGHT` the per-connection in-flight request limit,
+    /// `BLINDODON_FRAMING` the framing, `BLINDODON_MAX_FRAME_SIZE` the
+    /// largest inbound message and `BLINDODON_RECORD` a file to record the
+    /// session to.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(value) = std::env::var("BLINDODON_SOCKET") {
+            if !value.is_empty() {
+                config.endpoint = value;
+            }
+        }
+
+        if let Ok(value) = std::env::var("BLINDODON_SECRET_FILE") {
+            if !value.is_empty() {
+                config.secret_file = Some(PathBuf::from(value));
+            }
+        }
+
+        if let Ok(value) = std::env::var("BLINDODON_TCP_PORT") {
+            match value.parse::<u16>() {
+                Ok(port) => config.tcp_port = Some(port),
+                Err(_) => warn!("Ignoring invalid BLINDODON_TCP_PORT value: {}", value),
+            }
+        }
+
+        if let Ok(value) = std::env::var("BLINDODON_ALLOWED_ORIGINS") {
+            config.allowed_origins = value
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        if let Ok(value) = std::env::var("BLINDODON_MAX_IN_FLIGHT") {
+            match value.parse::<usize>() {
+                Ok(limit) if limit > 0 => config.max_in_flight_requests = limit,
+                _ => warn!("Ignoring invalid BLINDODON_MAX_IN_FLIGHT value: {}", value),
+            }
+        }
+
+        if let Ok(value) = std::env::var("BLINDODON_FRAMING") {
+            match value.parse::<Framing>() {
+                Ok(framing) => config.framing = framing,
+                Err(_) => warn!("Ignoring invalid BLINDODON_FRAMING value: {}", value),
+            }
+        }
+
+        if let Ok(value) = std::env::var("BLINDODON_MAX_FRAME_SIZE") {
+            match value.parse::<usize>() {
+                Ok(size) if size > 0 => config.max_frame_size = size,
+                _ => warn!("Ignoring invalid BLINDODON_MAX_FRAME_SIZE value: {}", value),
+            }
+        }
+
+        if let Ok(value) = std::env::var("BLINDODON_RECORD") {
+            if !value.is_empty() {
+                config.record = Some(PathBuf::from(value));
+            }
+        }
+
+        config
+    }
+
+    /// Override settings from command line arguments
+    ///
+    /// Supported arguments:
+    /// - `--socket <path>`: endpoint to listen on
+    /// - `--secret-file <path>`: where to write the session secret
+    /// - `--print-secret`: print the session secret on stdout instead
+    /// - `--tcp-port <port>`: also listen on 127.0.0.1 for TCP and WebSocket
+    /// - `--allow-origin <origin>`: let pages from this origin open a
+    ///   WebSocket (may be repeated)
+    /// - `--stdio`: serve the parent process over stdin/stdout
+    /// - `--framing <lines|length-prefixed>`: framing on byte-stream transports
+    /// - `--max-frame-size <bytes>`: largest inbound message accepted
+    /// - `--record <path>`: record every message to a JSONL file
+    /// - `--record-redact <kinds>`: comma-separated `tokens`, `codes` and
+    ///   `content` to redact from the recording (all by default), or `none`
+    /// - `--replay <path>`: replay a recording and compare the responses
+    /// - `--replay-mutating`: also replay requests that post, follow, log
+    ///   out or otherwise change anything
+    ///
+    /// Options taking a value also accept the `--option=value` form.
+    pub fn apply_args<I>(&mut self, args: I) -> Result<()>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .with_context(|| format!("{} requires a value", name))
+            };
+
+            match name.as_str() {
+                "--socket" => self.endpoint = value()?,
+                "--secret-file" => self.secret_file = Some(PathBuf::from(value()?)),
+                "--print-secret" if inline_value.is_none() => self.print_secret = true,
+                "--stdio" if inline_value.is_none() => self.stdio = true,
+                "--tcp-port" => {
+                    let port = value()?;
+                    self.tcp_port = Some(port.parse().with_context(|| format!("Invalid TCP port: {}", port))?);
+                }
+                "--allow-origin" => self.allowed_origins.push(value()?),
+                "--framing" => self.framing = value()?.parse()?,
+                "--record" => self.record = Some(PathBuf::from(value()?)),
+                "--record-redact" => self.record_redaction = value()?.parse()?,
+                "--replay" => self.replay = Some(PathBuf::from(value()?)),
+                "--replay-mutating" if inline_value.is_none() => self.replay_mutating = true,
+                "--max-frame-size" => {
+                    let size = value()?;
+                    self.max_frame_size = match size.parse() {
+                        Ok(size) if size > 0 => size,
+                        _ => anyhow::bail!("Invalid maximum frame size: {}", size),
+                    };
+                }
+                _ => anyhow::bail!("Unknown argument: {}", arg),
+            }
+        }
+
+        if self.stdio && self.print_secret {
+            anyhow::bail!("--print-secret cannot be combined with --stdio, which needs stdout for messages");
+        }
+
+        Ok(())
+    }
+}
 
-/// IPC Server that listens for connections from the C# UI
+/// State shared by every listener of the IPC server
+#[derive(Clone)]
 pub struct IpcServer {
     handler: Arc<MessageHandler>,
-    shutdown_tx: broadcast::Sender<()>,
+    auth: Arc<SessionAuth>,
+    config: ServerConfig,
+    /// Becomes `Some(reason)` once the server is shutting down
+    shutdown: watch::Receiver<Option<String>>,
+    /// Tasks serving client connections
+    connections: TaskTracker,
+    /// Records the messages of every connection, if enabled
+    recorder: Option<Arc<Recorder>>,
 }
 
 impl IpcServer {
-    /// Create a new IPC server with the given handler
-    pub fn new(handler: Arc<MessageHandler>) -> Self {
-        let (shutdown_tx, _) = broadcast::channel(1);
-        Self {
-            handler,
-            shutdown_tx,
+    /// Frame a byte-stream connection as configured
+    fn stream_framed<R, W>(&self, reader: R, writer: W) -> (FrameReader, FrameWriter)
+    where
+        R: tokio::io::AsyncRead + Send + 'static,
+        W: tokio::io::AsyncWrite + Send + 'static,
+    {
+        transport::stream_framed(reader, writer, self.config.framing, self.config.max_frame_size)
+    }
+
+    /// Serve a connection on a tracked task, so shutdown can wait for it
+    fn spawn_connection(&self, frames_in: FrameReader, frames_out: FrameWriter, transport: Transport) {
+        let server = self.clone();
+        self.connections
+            .spawn(async move { server.serve(frames_in, frames_out, transport).await });
+    }
+
+    /// Serve a connection until it closes
+    async fn serve(self, frames_in: FrameReader, frames_out: FrameWriter, transport: Transport) {
+        if let Err(e) = serve_connection(
+            frames_in,
+            frames_out,
+            self.handler,
+            Some((self.auth, transport)),
+            &self.config,
+            self.shutdown,
+            self.recorder.map(|recorder| recorder.connection()),
+        )
+        .await
+        {
+            error!("Client handler error: {}", e);
         }
     }
 
-    /// Get a shutdown signal receiver
-    pub fn shutdown_signal(&self) -> broadcast::Receiver<()> {
-        self.shutdown_tx.subscribe()
+    /// Frame and serve a TCP client on a tracked task
+    ///
+    /// The client gets `TCP_HANDSHAKE_TIMEOUT` to reveal its transport and
+    /// complete a WebSocket handshake, so idle connections do not pile up.
+    fn spawn_tcp_connection(&self, stream: tokio::net::TcpStream) {
+        let server = self.clone();
+        self.connections.spawn(async move {
+            let framing = tokio::time::timeout(TCP_HANDSHAKE_TIMEOUT, tcp_frames(stream, &server.config));
+            let frames = tokio::select! {
+                result = framing => result,
+                _ = wait_for_shutdown(server.shutdown.clone()) => return,
+            };
+
+            match frames {
+                Ok(Ok((frames_in, frames_out))) => server.serve(frames_in, frames_out, Transport::Tcp).await,
+                Ok(Err(e)) => error!("TCP client handler error: {}", e),
+                Err(_) => warn!("Closing TCP connection idle for {:?} before its handshake", TCP_HANDSHAKE_TIMEOUT),
+            }
+        });
     }
+}
 
-    /// Signal shutdown
-    pub fn shutdown(&self) {
-        let _ = self.shutdown_tx.send(());
+/// Wait until the server starts shutting down
+pub async fn wait_for_shutdown(mut shutdown: watch::Receiver<Option<String>>) {
+    if shutdown.wait_for(Option::is_some).await.is_err() {
+        // Nobody can trigger a shutdown any more
+        std::future::pending::<()>().await;
     }
 }
 
 /// Run the IPC server
-pub async fn run_server() -> Result<()> {
-    info!("Starting IPC server on {}", PIPE_NAME);
+pub async fn run_server(config: ServerConfig) -> Result<()> {
+    if config.stdio {
+        info!("Starting IPC server on stdin/stdout");
+    } else {
+        info!("Starting IPC server on {}", config.endpoint);
+    }
 
     // Initialize the cache manager
     let cache = Arc::new(CacheManager::new().await?);
     info!("Cache manager initialized");
+    if let Err(e) = cache.cleanup(CACHE_MAX_AGE_DAYS).await {
+        warn!("Failed to clean up cached timelines: {}", e);
+    }
+    info!(
+        "Allowing up to {} in-flight requests per connection",
+        config.max_in_flight_requests
+    );
+    info!(
+        "Using {:?} framing with messages of up to {} bytes",
+        config.framing, config.max_frame_size
+    );
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(None);
 
     // Create handler with cache
-    let handler = Arc::new(MessageHandler::new(cache));
+    let handler = MessageHandler::new(cache.clone(), shutdown_tx.clone());
 
     // Initialize handler and restore saved session
     if let Err(e) = handler.initialize().await {
         warn!("Failed to initialize handler (will continue anyway): {}", e);
     }
 
-    let (shutdown_tx, _) = broadcast::channel(1);
-    let shutdown_rx = shutdown_tx.subscribe();
+    // Hand out the secret every client has to present. The stdio client is
+    // our parent and needs none, so the secret is only written out when
+    // other listeners are running too.
+    let auth = Arc::new(SessionAuth::generate()?);
+    let secret_file = if config.stdio && config.tcp_port.is_none() {
+        None
+    } else if config.print_secret {
+        use std::io::Write;
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", auth.secret())?;
+        stdout.flush()?;
+        None
+    } else {
+        let path = config
+            .secret_file
+            .clone()
+            .unwrap_or_else(|| session::default_secret_path(&config.endpoint));
+        auth.write_secret_file(&path)?;
+        Some(path)
+    };
+
+    let recorder = match &config.record {
+        Some(path) => Some(Arc::new(Recorder::create(path, config.record_redaction)?)),
+        None => None,
+    };
+
+    tokio::spawn(shutdown_on_signal(shutdown_tx.clone()));
+
+    let shutdown_timeout = config.shutdown_timeout;
+    let server = IpcServer {
+        handler,
+        auth,
+        config,
+        shutdown: shutdown_rx,
+        connections: TaskTracker::new(),
+        recorder,
+    };
+
+    let result = run_listeners(server.clone()).await;
+
+    // However the listeners ended, take the connections down with them
+    shutdown_tx.send_if_modified(|reason| {
+        reason.get_or_insert_with(|| "IPC listener stopped".to_string());
+        true
+    });
+    server.connections.close();
+    info!("Waiting for {} connection(s) to close", server.connections.len());
+
+    // Connections cancel their own requests at the deadline; the extra
+    // second covers sending the final event
+    let deadline = shutdown_timeout + Duration::from_secs(1);
+    if tokio::time::timeout(deadline, server.connections.wait()).await.is_err() {
+        warn!("Connections still open after {:?}; exiting anyway", deadline);
+    }
+
+    cache.close().await;
+
+    if let Some(path) = secret_file {
+        let _ = std::fs::remove_file(path);
+    }
+
+    result
+}
+
+/// Run every configured listener until shutdown
+async fn run_listeners(server: IpcServer) -> Result<()> {
+    if let Some(port) = server.config.tcp_port {
+        let listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, port))
+            .await
+            .with_context(|| format!("Failed to bind 127.0.0.1:{}", port))?;
+        info!("Listening for TCP and WebSocket clients on {}", listener.local_addr()?);
+
+        tokio::spawn(run_tcp_server(listener, server.clone()));
+    }
+
+    if server.config.stdio {
+        return run_stdio(server).await;
+    }
 
     #[cfg(windows)]
     {
-        run_windows_pipe_server(handler, shutdown_rx).await
+        run_windows_pipe_server(server).await
     }
 
     #[cfg(not(windows))]
     {
-        run_unix_socket_server(handler, shutdown_rx).await
+        run_unix_socket_server(server).await
+    }
+}
+
+/// Serve the parent process over stdin/stdout until either side is done
+///
+/// The parent spawned us and owns both pipes, so it does not need to
+/// authenticate. Once it closes stdin the whole server shuts down, which
+/// keeps the core from outliving the UI.
+async fn run_stdio(server: IpcServer) -> Result<()> {
+    let (frames_in, frames_out) = transport::stdio_framed(server.config.framing, server.config.max_frame_size);
+
+    serve_connection(
+        frames_in,
+        frames_out,
+        server.handler,
+        None,
+        &server.config,
+        server.shutdown,
+        server.recorder.map(|recorder| recorder.connection()),
+    )
+    .await?;
+
+    info!("Stdio client finished");
+    Ok(())
+}
+
+/// Start shutting down on SIGINT or SIGTERM (Ctrl+C on Windows)
+async fn shutdown_on_signal(shutdown: watch::Sender<Option<String>>) {
+    #[cfg(unix)]
+    let signal = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|_| "SIGINT"),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    };
+
+    #[cfg(not(unix))]
+    let signal = async { tokio::signal::ctrl_c().await.map(|_| "Ctrl+C") };
+
+    match signal.await {
+        Ok(name) => {
+            info!("Received {}, shutting down", name);
+            shutdown.send_replace(Some(format!("Received {}", name)));
+        }
+        Err(e) => warn!("Failed to listen for shutdown signals: {}", e),
     }
 }
 
 #[cfg(windows)]
-async fn run_windows_pipe_server(
-    handler: Arc<MessageHandler>,
-    mut shutdown: broadcast::Receiver<()>,
-) -> Result<()> {
+async fn run_windows_pipe_server(server: IpcServer) -> Result<()> {
     use tokio::net::windows::named_pipe::{ServerOptions, PipeMode};
 
+    let endpoint = &server.config.endpoint;
+    let mut first_instance = true;
+
     loop {
-        // Create a new pipe instance
+        // Create a new pipe instance. The first one must not join a pipe
+        // that another running core already owns.
         let pipe = ServerOptions::new()
-            .first_pipe_instance(false)
+            .first_pipe_instance(first_instance)
             .pipe_mode(PipeMode::Message)
-            .create(PIPE_NAME)
-            .context("Failed to create named pipe")?;
+            .create(endpoint)
+            .with_context(|| {
+                if first_instance {
+                    format!("Failed to create named pipe {} (is another core running?)", endpoint)
+                } else {
+                    format!("Failed to create named pipe {}", endpoint)
+                }
+            })?;
+        first_instance = false;
 
         info!("Waiting for client connection...");
 
@@ -114,19 +540,16 @@ async fn run_windows_pipe_server(
                 match result {
                     Ok(()) => {
                         info!("Client connected");
-                        let handler_clone = handler.clone();
-                        tokio::spawn(async move {
-                            if let Err(e) = handle_client_windows(pipe, handler_clone).await {
-                                error!("Client handler error: {}", e);
-                            }
-                        });
+                        let (reader, writer) = tokio::io::split(pipe);
+                        let (frames_in, frames_out) = server.stream_framed(reader, writer);
+                        server.spawn_connection(frames_in, frames_out, Transport::Local);
                     }
                     Err(e) => {
                         error!("Failed to accept connection: {}", e);
                     }
                 }
             }
-            _ = shutdown.recv() => {
+            _ = wait_for_shutdown(server.shutdown.clone()) => {
                 info!("Shutdown signal received");
                 break;
             }
@@ -136,180 +559,131 @@ async fn run_windows_pipe_server(
     Ok(())
 }
 
-#[cfg(windows)]
-async fn handle_client_windows(
-    pipe: tokio::net::windows::named_pipe::NamedPipeServer,
-    handler: Arc<MessageHandler>,
-) -> Result<()> {
-    use tokio::io::{split, AsyncBufReadExt, AsyncWriteExt, BufReader};
-
-    let (reader, writer) = tokio::io::split(pipe);
-    let mut reader = BufReader::new(reader);
-    let writer = Arc::new(Mutex::new(writer));
+#[cfg(not(windows))]
+async fn run_unix_socket_server(server: IpcServer) -> Result<()> {
+    let socket_path = std::path::PathBuf::from(&server.config.endpoint);
+    let listener = endpoint::bind_socket(&socket_path)?;
 
-    let mut line = String::new();
+    info!("Listening on {}", socket_path.display());
 
     loop {
-        line.clear();
-        match reader.read_line(&mut line).await {
-            Ok(0) => {
-                info!("Client disconnected");
-                break;
-            }
-            Ok(_) => {
-                let trimmed = line.trim();
-                if trimmed.is_empty() {
-                    continue;
-                }
-
-                debug!("Received message: {}", trimmed);
-
-                match serde_json::from_str::<IpcMessage>(trimmed) {
-                    Ok(msg) => {
-                        let response = handler.handle_message(msg).await;
-                        let response_json = serde_json::to_string(&response)?;
-
-                        let mut w = writer.lock().await;
-                        w.write_all(response_json.as_bytes()).await?;
-                        w.write_all(b"\n").await?;
-                        w.flush().await?;
+        tokio::select! {
+            result = listener.accept() => {
+                match result {
+                    Ok((stream, _)) => {
+                        if !endpoint::peer_is_current_user(&stream) {
+                            continue;
+                        }
 
-                        debug!("Sent response: {}", response_json);
+                        info!("Client connected");
+                        let (reader, writer) = stream.into_split();
+                        let (frames_in, frames_out) = server.stream_framed(reader, writer);
+                        server.spawn_connection(frames_in, frames_out, Transport::Local);
                     }
                     Err(e) => {
-                        warn!("Failed to parse message: {}", e);
-                        let error_response = IpcMessage::response_err(
-                            "unknown",
-                            crate::models::IpcError::new(
-                                crate::models::error_codes::PARSE_ERROR,
-                                format!("Failed to parse message: {}", e),
-                            ),
-                        );
-                        let response_json = serde_json::to_string(&error_response)?;
-                        let mut w = writer.lock().await;
-                        w.write_all(response_json.as_bytes()).await?;
-                        w.write_all(b"\n").await?;
-                        w.flush().await?;
+                        error!("Failed to accept connection: {}", e);
                     }
                 }
             }
-            Err(e) => {
-                error!("Read error: {}", e);
+            _ = wait_for_shutdown(server.shutdown.clone()) => {
+                info!("Shutdown signal received");
                 break;
             }
         }
     }
 
+    // Stop accepting before connections drain
+    drop(listener);
+    endpoint::remove_socket(&socket_path);
+
     Ok(())
 }
 
-#[cfg(not(windows))]
-async fn run_unix_socket_server(
-    handler: Arc<MessageHandler>,
-    mut shutdown: broadcast::Receiver<()>,
-) -> Result<()> {
-    use tokio::net::UnixListener;
-
-    // Remove existing socket file
-    let _ = std::fs::remove_file(PIPE_NAME);
-
-    let listener = UnixListener::bind(PIPE_NAME)
-        .context("Failed to bind Unix socket")?;
-
-    info!("Listening on {}", PIPE_NAME);
-
+/// Serve TCP and WebSocket clients on a localhost listener
+///
+/// Both share one port: a connection opening with an HTTP `GET` is upgraded
+/// to a WebSocket, anything else is treated as line-delimited JSON.
+async fn run_tcp_server(listener: tokio::net::TcpListener, server: IpcServer) {
     loop {
         tokio::select! {
             result = listener.accept() => {
                 match result {
-                    Ok((stream, _)) => {
-                        info!("Client connected");
-                        let handler_clone = handler.clone();
-                        tokio::spawn(async move {
-                            if let Err(e) = handle_client_unix(stream, handler_clone).await {
-                                error!("Client handler error: {}", e);
-                            }
-                        });
+                    Ok((stream, peer)) => {
+                        if !peer.ip().is_loopback() {
+                            warn!("Rejecting TCP connection from {}", peer);
+                            continue;
+                        }
+
+                        info!("TCP client connected from {}", peer);
+                        server.spawn_tcp_connection(stream);
                     }
                     Err(e) => {
-                        error!("Failed to accept connection: {}", e);
+                        error!("Failed to accept TCP connection: {}", e);
                     }
                 }
             }
-            _ = shutdown.recv() => {
+            _ = wait_for_shutdown(server.shutdown.clone()) => {
                 info!("Shutdown signal received");
                 break;
             }
         }
     }
-
-    // Cleanup
-    let _ = std::fs::remove_file(PIPE_NAME);
-
-    Ok(())
 }
 
-#[cfg(not(windows))]
-async fn handle_client_unix(
-    stream: tokio::net::UnixStream,
-    handler: Arc<MessageHandler>,
-) -> Result<()> {
-    let (reader, writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
-    let writer = Arc::new(Mutex::new(writer));
+/// Frame a TCP client, upgrading it to a WebSocket if it asks for one
+async fn tcp_frames(stream: tokio::net::TcpStream, config: &ServerConfig) -> Result<(FrameReader, FrameWriter)> {
+    if opens_with_get(&stream).await? {
+        let ws_config = WebSocketConfig::default()
+            .max_message_size(Some(config.max_frame_size))
+            .max_frame_size(Some(config.max_frame_size));
+        let allowed_origins = config.allowed_origins.clone();
+        #[allow(clippy::result_large_err)]
+        let callback = move |request: &Request, response: Response| check_origin(&allowed_origins, request, response);
+        let socket = tokio_tungstenite::accept_hdr_async_with_config(stream, callback, Some(ws_config))
+            .await
+            .context("WebSocket handshake failed")?;
+        Ok(transport::websocket_framed(socket))
+    } else {
+        let (reader, writer) = stream.into_split();
+        Ok(transport::stream_framed(reader, writer, config.framing, config.max_frame_size))
+    }
+}
 
-    let mut line = String::new();
+/// Whether a TCP client starts with an HTTP `GET`
+///
+/// A single peek may return only part of the request line, so peek again
+/// until four bytes are there or they can no longer spell `GET `.
+async fn opens_with_get(stream: &tokio::net::TcpStream) -> Result<bool> {
+    const GET: &[u8] = b"GET ";
+    let mut prefix = [0u8; 4];
 
     loop {
-        line.clear();
-        match reader.read_line(&mut line).await {
-            Ok(0) => {
-                info!("Client disconnected");
-                break;
-            }
-            Ok(_) => {
-                let trimmed = line.trim();
-                if trimmed.is_empty() {
-                    continue;
-                }
-
-                debug!("Received message: {}", trimmed);
-
-                match serde_json::from_str::<IpcMessage>(trimmed) {
-                    Ok(msg) => {
-                        let response = handler.handle_message(msg).await;
-                        let response_json = serde_json::to_string(&response)?;
-
-                        let mut w = writer.lock().await;
-                        w.write_all(response_json.as_bytes()).await?;
-                        w.write_all(b"\n").await?;
-                        w.flush().await?;
-
-                        debug!("Sent response: {}", response_json);
-                    }
-                    Err(e) => {
-                        warn!("Failed to parse message: {}", e);
-                        let error_response = IpcMessage::response_err(
-                            "unknown",
-                            crate::models::IpcError::new(
-                                crate::models::error_codes::PARSE_ERROR,
-                                format!("Failed to parse message: {}", e),
-                            ),
-                        );
-                        let response_json = serde_json::to_string(&error_response)?;
-                        let mut w = writer.lock().await;
-                        w.write_all(response_json.as_bytes()).await?;
-                        w.write_all(b"\n").await?;
-                        w.flush().await?;
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Read error: {}", e);
-                break;
-            }
+        let peeked = stream.peek(&mut prefix).await?;
+        if peeked == 0 || peeked == prefix.len() || !GET.starts_with(&prefix[..peeked]) {
+            return Ok(&prefix[..peeked] == GET);
         }
+        // Peeking returns at once while data is waiting, so wait for more
+        tokio::time::sleep(SNIFF_RETRY_DELAY).await;
     }
+}
 
-    Ok(())
+/// Refuse WebSocket handshakes from browser pages of other origins
+///
+/// Browsers always send `Origin` on a WebSocket handshake, so a missing
+/// header means a client outside the browser.
+// The error type is set by tungstenite's handshake callback
+#[allow(clippy::result_large_err)]
+fn check_origin(allowed_origins: &[String], request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    let Some(origin) = request.headers().get(header::ORIGIN) else {
+        return Ok(response);
+    };
+
+    if allowed_origins.iter().any(|allowed| allowed.as_bytes() == origin.as_bytes()) {
+        return Ok(response);
+    }
+
+    warn!("Rejecting WebSocket handshake from origin {:?}", origin);
+    let mut error = ErrorResponse::new(Some("Origin not allowed".to_string()));
+    *error.status_mut() = StatusCode::FORBIDDEN;
+    Err(error)
 }
@@ -0,0 +1,289 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Per-session client authentication
+//!
+//! The core generates a random secret at startup and hands it to the UI,
+//! either through an owner-only file or on stdout for a parent process.
+//! The first message on every connection must be `system.authenticate`
+//! carrying that secret; anything else closes the connection. Failed
+//! attempts are logged and answered after a delay. After too many failures
+//! in a short window, further attempts over TCP and WebSocket are refused
+//! until the window has passed. The TCP listener cannot tell which user a
+//! peer belongs to, so the lockout never extends to the socket or pipe,
+//! where only the current user can connect.
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::models::{error_codes, methods, AuthenticateRequest, AuthenticateResponse, IpcError, IpcMessage};
+
+/// Number of random bytes in a session secret
+const SECRET_LEN: usize = 32;
+
+/// Failed TCP attempts allowed within `FAILURE_WINDOW` before refusing more
+const MAX_FAILURES: usize = 5;
+
+/// Window over which failed attempts are counted
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Delay before answering a failed attempt
+const FAILURE_DELAY: Duration = Duration::from_millis(500);
+
+/// Kind of listener a connection arrived on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// The Unix socket or named pipe, reachable only by the current user
+    Local,
+    /// The localhost TCP and WebSocket listener, reachable by any local user
+    Tcp,
+}
+
+/// Secret shared with the UI for the lifetime of this process
+pub struct SessionAuth {
+    secret: String,
+    /// Recent failed attempts over TCP
+    tcp_failures: Mutex<VecDeque<Instant>>,
+}
+
+impl SessionAuth {
+    /// Generate a new random session secret
+    pub fn generate() -> Result<Self> {
+        let mut bytes = [0u8; SECRET_LEN];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow::anyhow!("Failed to generate session secret"))?;
+
+        Ok(Self {
+            secret: URL_SAFE_NO_PAD.encode(bytes),
+            tcp_failures: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// The secret clients must present
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// Write the secret to `path`, readable only by the current user
+    ///
+    /// Any existing file is replaced rather than written through, so a
+    /// planted symlink cannot redirect the secret elsewhere.
+    pub fn write_secret_file(&self, path: &Path) -> Result<()> {
+        use std::io::Write;
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to replace {}", path.display())),
+        }
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Failed to create secret file {}", path.display()))?;
+        file.write_all(self.secret.as_bytes())?;
+
+        info!("Session secret written to {}", path.display());
+        Ok(())
+    }
+
+    /// Check the first message of a connection (`None` if it did not parse)
+    ///
+    /// Returns the response to send back. On error the connection must be
+    /// closed after sending it.
+    pub async fn authenticate(&self, msg: Option<&IpcMessage>, transport: Transport) -> Result<IpcMessage, IpcMessage> {
+        let id = msg.map_or("unknown", |msg| msg.id.as_str());
+        let method = msg.and_then(|msg| msg.method.as_deref());
+
+        if transport == Transport::Tcp && self.is_locked_out() {
+            warn!("Refusing TCP authentication attempt: too many recent failures");
+            return Err(IpcMessage::response_err(
+                id,
+                IpcError::new(
+                    error_codes::RATE_LIMITED,
+                    "Too many failed authentication attempts; try again later",
+                ),
+            ));
+        }
+
+        let presented = if method == Some(methods::SYSTEM_AUTHENTICATE) {
+            msg.and_then(|msg| msg.params.clone())
+                .and_then(|params| serde_json::from_value::<AuthenticateRequest>(params).ok())
+                .map(|request| request.secret)
+        } else {
+            None
+        };
+
+        // Compare digests so timing does not reveal how much of the secret matched
+        let valid = presented.as_deref().is_some_and(|secret| {
+            digest(&SHA256, secret.as_bytes()).as_ref() == digest(&SHA256, self.secret.as_bytes()).as_ref()
+        });
+
+        if valid {
+            info!("Client authenticated");
+            let result = serde_json::to_value(AuthenticateResponse { authenticated: true })
+                .unwrap_or_default();
+            return Ok(IpcMessage::response_ok(id, result));
+        }
+
+        match presented {
+            Some(_) => warn!("IPC authentication failed: wrong session secret"),
+            None => warn!(
+                "IPC authentication failed: first message was {:?}, expected {}",
+                method,
+                methods::SYSTEM_AUTHENTICATE
+            ),
+        }
+        if transport == Transport::Tcp {
+            self.record_failure();
+        }
+        tokio::time::sleep(FAILURE_DELAY).await;
+
+        Err(IpcMessage::response_err(
+            id,
+            IpcError::new(
+                error_codes::AUTHENTICATION_FAILED,
+                format!("Connection must start with a valid {} request", methods::SYSTEM_AUTHENTICATE),
+            ),
+        ))
+    }
+
+    /// Whether too many TCP attempts failed recently
+    fn is_locked_out(&self) -> bool {
+        let mut failures = self.tcp_failures.lock().unwrap();
+        prune_failures(&mut failures);
+        failures.len() >= MAX_FAILURES
+    }
+
+    /// Remember a failed TCP attempt
+    fn record_failure(&self) {
+        let mut failures = self.tcp_failures.lock().unwrap();
+        prune_failures(&mut failures);
+        failures.push_back(Instant::now());
+    }
+}
+
+/// Forget failed attempts that are older than the window
+fn prune_failures(failures: &mut VecDeque<Instant>) {
+    while failures
+        .front()
+        .is_some_and(|failed_at| failed_at.elapsed() > FAILURE_WINDOW)
+    {
+        failures.pop_front();
+    }
+}
+
+/// Default location of the secret file for an endpoint
+///
+/// On Unix the file sits next to the socket, inside the per-user runtime
+/// directory. On Windows it goes to the local application data directory.
+pub fn default_secret_path(endpoint: &str) -> PathBuf {
+    if cfg!(windows) {
+        dirs::data_local_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("Blindodon")
+            .join("ipc_session.secret")
+    } else {
+        Path::new(endpoint).with_extension("secret")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticate_request(secret: &str) -> IpcMessage {
+        IpcMessage::request(methods::SYSTEM_AUTHENTICATE, Some(serde_json::json!({ "secret": secret })))
+    }
+
+    fn error_code(response: IpcMessage) -> i32 {
+        response.error.expect("expected an error response").code
+    }
+
+    #[tokio::test]
+    async fn accepts_the_session_secret() {
+        let auth = SessionAuth::generate().unwrap();
+        let request = authenticate_request(auth.secret());
+        let response = auth.authenticate(Some(&request), Transport::Tcp).await.unwrap();
+        assert_eq!(response.id, request.id);
+        assert_eq!(response.result.unwrap()["authenticated"], true);
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_secret_and_other_methods() {
+        let auth = SessionAuth::generate().unwrap();
+
+        let response = auth.authenticate(Some(&authenticate_request("wrong")), Transport::Local).await;
+        assert_eq!(error_code(response.unwrap_err()), error_codes::AUTHENTICATION_FAILED);
+
+        let ping = IpcMessage::request(methods::PING, None);
+        let response = auth.authenticate(Some(&ping), Transport::Local).await;
+        assert_eq!(error_code(response.unwrap_err()), error_codes::AUTHENTICATION_FAILED);
+
+        let response = auth.authenticate(None, Transport::Local).await.unwrap_err();
+        assert_eq!(response.id, "unknown");
+    }
+
+    #[tokio::test]
+    async fn locks_out_tcp_only() {
+        let auth = SessionAuth::generate().unwrap();
+        for _ in 0..MAX_FAILURES {
+            auth.record_failure();
+        }
+
+        let request = authenticate_request(auth.secret());
+        let response = auth.authenticate(Some(&request), Transport::Tcp).await;
+        assert_eq!(error_code(response.unwrap_err()), error_codes::RATE_LIMITED);
+        assert!(auth.authenticate(Some(&request), Transport::Local).await.is_ok());
+    }
+
+    #[test]
+    fn failures_expire_after_the_window() {
+        let auth = SessionAuth::generate().unwrap();
+        let expired = Instant::now() - FAILURE_WINDOW - Duration::from_secs(1);
+        auth.tcp_failures.lock().unwrap().extend(std::iter::repeat(expired).take(MAX_FAILURES));
+        assert!(!auth.is_locked_out());
+        assert!(auth.tcp_failures.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn secrets_are_random() {
+        let first = SessionAuth::generate().unwrap();
+        let second = SessionAuth::generate().unwrap();
+        assert_ne!(first.secret(), second.secret());
+        assert_eq!(URL_SAFE_NO_PAD.decode(first.secret()).unwrap().len(), SECRET_LEN);
+    }
+}
@@ -0,0 +1,271 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Message framing for the IPC transports
+//!
+//! Every transport is turned into a stream of inbound text frames and a
+//! sink of outbound ones, so the connection loop does not care whether it
+//! talks over a named pipe, a Unix socket, TCP or a WebSocket. Byte-stream
+//! transports carry one JSON document per line, or per length-prefixed
+//! frame if the server is configured for it; WebSockets carry one per text
+//! message.
+//!
+//! Inbound frames are bounded by a maximum size. A frame over the limit is
+//! skipped and reported as `Frame::Oversized`, so the connection can answer
+//! it and carry on with the next one.
+
+use anyhow::Result;
+use futures::{future, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
+use std::io::Read;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tokio_util::bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite, LinesCodec, LinesCodecError};
+use tokio_util::io::StreamReader;
+
+/// Size of the big-endian length prefix in length-prefixed framing
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Size of the chunks read from stdin
+const STDIN_CHUNK_SIZE: usize = 8 * 1024;
+
+/// An inbound frame
+#[derive(Debug)]
+pub enum Frame {
+    /// A complete message
+    Message(String),
+    /// A message larger than the maximum frame size, which was discarded
+    Oversized,
+}
+
+/// Inbound frames of a connection
+pub type FrameReader = Pin<Box<dyn Stream<Item = Result<Frame>> + Send>>;
+
+/// Outbound frames of a connection
+pub type FrameWriter = Pin<Box<dyn Sink<String, Error = anyhow::Error> + Send>>;
+
+/// How messages are delimited on byte-stream transports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// One message per line
+    Lines,
+    /// Each message preceded by its length as a 4-byte big-endian integer
+    LengthPrefixed,
+}
+
+impl std::str::FromStr for Framing {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "lines" => Ok(Framing::Lines),
+            "length-prefixed" => Ok(Framing::LengthPrefixed),
+            _ => anyhow::bail!("Unknown framing {:?} (expected lines or length-prefixed)", value),
+        }
+    }
+}
+
+/// Codec for byte-stream transports, bounded by a maximum frame size
+struct FrameCodec {
+    framing: Framing,
+    max_frame_size: usize,
+    lines: LinesCodec,
+    /// Bytes of an oversized length-prefixed frame still to be skipped
+    discarding: usize,
+}
+
+impl FrameCodec {
+    fn new(framing: Framing, max_frame_size: usize) -> Self {
+        Self {
+            framing,
+            max_frame_size,
+            lines: LinesCodec::new_with_max_length(max_frame_size),
+            discarding: 0,
+        }
+    }
+
+    fn decode_length_prefixed(&mut self, buf: &mut BytesMut) -> std::io::Result<Option<Frame>> {
+        if self.discarding > 0 {
+            let skipped = self.discarding.min(buf.len());
+            buf.advance(skipped);
+            self.discarding -= skipped;
+            if self.discarding > 0 {
+                return Ok(None);
+            }
+        }
+
+        if buf.len() < LENGTH_PREFIX_SIZE {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+
+        // Skip the payload as it arrives instead of buffering it
+        if len > self.max_frame_size {
+            buf.advance(LENGTH_PREFIX_SIZE);
+            self.discarding = len;
+            return Ok(Some(Frame::Oversized));
+        }
+
+        if buf.len() < LENGTH_PREFIX_SIZE + len {
+            buf.reserve(LENGTH_PREFIX_SIZE + len - buf.len());
+            return Ok(None);
+        }
+        buf.advance(LENGTH_PREFIX_SIZE);
+        let payload = buf.split_to(len);
+
+        String::from_utf8(payload.to_vec())
+            .map(|message| Some(Frame::Message(message)))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame is not valid UTF-8"))
+    }
+}
+
+/// Turn a line that went over the limit into an oversized frame
+fn lines_result(result: Result<Option<String>, LinesCodecError>) -> std::io::Result<Option<Frame>> {
+    match result {
+        Ok(line) => Ok(line.map(Frame::Message)),
+        Err(LinesCodecError::MaxLineLengthExceeded) => Ok(Some(Frame::Oversized)),
+        Err(LinesCodecError::Io(e)) => Err(e),
+    }
+}
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> std::io::Result<Option<Frame>> {
+        match self.framing {
+            Framing::Lines => lines_result(self.lines.decode(buf)),
+            Framing::LengthPrefixed => self.decode_length_prefixed(buf),
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> std::io::Result<Option<Frame>> {
+        match self.framing {
+            Framing::Lines => lines_result(self.lines.decode_eof(buf)),
+            Framing::LengthPrefixed => match self.decode_length_prefixed(buf)? {
+                Some(frame) => Ok(Some(frame)),
+                None if buf.is_empty() || self.discarding > 0 => Ok(None),
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Connection closed in the middle of a frame",
+                )),
+            },
+        }
+    }
+}
+
+impl Encoder<String> for FrameCodec {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, message: String, buf: &mut BytesMut) -> std::io::Result<()> {
+        match self.framing {
+            Framing::Lines => self.lines.encode(message, buf).map_err(|e| match e {
+                LinesCodecError::Io(e) => e,
+                e => std::io::Error::other(e),
+            }),
+            Framing::LengthPrefixed => {
+                let len = u32::try_from(message.len())
+                    .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Message too large to frame"))?;
+                buf.reserve(LENGTH_PREFIX_SIZE + message.len());
+                buf.put_u32(len);
+                buf.put_slice(message.as_bytes());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Frame a byte stream with the given framing and inbound size limit
+pub fn stream_framed<R, W>(reader: R, writer: W, framing: Framing, max_frame_size: usize) -> (FrameReader, FrameWriter)
+where
+    R: AsyncRead + Send + 'static,
+    W: AsyncWrite + Send + 'static,
+{
+    let frames_in = FramedRead::new(reader, FrameCodec::new(framing, max_frame_size)).map_err(anyhow::Error::from);
+    let frames_out = SinkExt::<String>::sink_map_err(
+        FramedWrite::new(writer, FrameCodec::new(framing, max_frame_size)),
+        anyhow::Error::from,
+    );
+
+    (Box::pin(frames_in), Box::pin(frames_out))
+}
+
+/// Frame stdin and stdout with the given framing and inbound size limit
+///
+/// Stdin is read on a detached thread: a blocking read cannot be
+/// interrupted, and the runtime would otherwise wait for it on shutdown
+/// until the parent happened to write something.
+pub fn stdio_framed(framing: Framing, max_frame_size: usize) -> (FrameReader, FrameWriter) {
+    let (chunk_tx, chunk_rx) = tokio::sync::mpsc::channel::<std::io::Result<std::io::Cursor<Vec<u8>>>>(16);
+
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut chunk = vec![0u8; STDIN_CHUNK_SIZE];
+        loop {
+            let read = match stdin.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => Ok(std::io::Cursor::new(chunk[..read].to_vec())),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = read.is_err();
+            if chunk_tx.blocking_send(read).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let chunks = futures::stream::unfold(chunk_rx, |mut chunk_rx| async move {
+        let chunk = chunk_rx.recv().await?;
+        Some((chunk, chunk_rx))
+    });
+
+    stream_framed(StreamReader::new(chunks), tokio::io::stdout(), framing, max_frame_size)
+}
+
+/// Frame a WebSocket as one message per text frame
+///
+/// Binary frames holding UTF-8 are accepted as well. Control frames are
+/// answered by the WebSocket layer and never reach the connection loop.
+/// The size limit is enforced by the WebSocket configuration, which fails
+/// the connection on an oversized message.
+pub fn websocket_framed<S>(socket: WebSocketStream<S>) -> (FrameReader, FrameWriter)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sink, stream) = socket.split();
+
+    let frames_in = stream
+        .map_err(anyhow::Error::from)
+        .try_take_while(|message| future::ready(Ok(!message.is_close())))
+        .try_filter_map(|message| {
+            future::ready(match message {
+                Message::Text(text) => Ok(Some(Frame::Message(text.to_string()))),
+                Message::Binary(data) => String::from_utf8(data.to_vec())
+                    .map(|text| Some(Frame::Message(text)))
+                    .map_err(|_| anyhow::anyhow!("Binary WebSocket frame is not valid UTF-8")),
+                _ => Ok(None),
+            })
+        });
+
+    let frames_out = sink
+        .sink_map_err(anyhow::Error::from)
+        .with(|frame: String| future::ready(Ok::<_, anyhow::Error>(Message::text(frame))));
+
+    (Box::pin(frames_in), Box::pin(frames_out))
+}
@@ -0,0 +1,30 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Mastodon Core - Rust backend for Blindodon
+//!
+//! The core runs as a background process (`mastodon-core`) and talks to its
+//! clients over IPC. The library exposes the same modules to the terminal
+//! client (`blindodon-cli`), so both binaries share the message types and
+//! connection handling.
+
+pub mod api;
+pub mod cache;
+pub mod crypto;
+pub mod ipc;
+pub mod logger;
+pub mod models;
+pub mod streaming;
@@ -38,7 +38,7 @@ pub struct LoggerConfig {
     pub file_prefix: String,
     /// Maximum log level
     pub level: Level,
-    /// Whether to log to console
+    /// Whether to log to console (always stderr, as stdout may carry IPC)
     pub console_output: bool,
     /// Whether to log to file
     pub file_output: bool,
@@ -19,16 +19,9 @@
 //! This binary runs as a background process and communicates with the C# UI
 //! via named pipes using JSON-based IPC protocol.
 
-mod api;
-mod cache;
-mod crypto;
-mod ipc;
-mod logger;
-mod models;
-mod streaming;
-
 use anyhow::Result;
-use logger::Logger;
+use mastodon_core::ipc;
+use mastodon_core::logger::Logger;
 use tracing::{info, error};
 
 #[tokio::main]
@@ -39,8 +32,15 @@ async fn main() -> Result<()> {
     info!("Mastodon Core starting up...");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
 
-    // Initialize the IPC server
-    match ipc::server::run_server().await {
+    let mut config = ipc::server::ServerConfig::from_env();
+    config.apply_args(std::env::args().skip(1))?;
+
+    // Replay a recording, or initialize the IPC server
+    let result = match config.replay.clone() {
+        Some(path) => ipc::replay::run_replay(&path, config.replay_mutating).await,
+        None => ipc::server::run_server(config).await,
+    };
+    match result {
         Ok(_) => {
             info!("Mastodon Core shutting down gracefully");
         }
@@ -17,10 +17,11 @@
 //! Account model for managing Mastodon accounts
 
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// A stored Mastodon account (for multi-account support)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct StoredAccount {
     /// Unique identifier (local)
     pub id: String,
@@ -66,26 +67,164 @@ pub struct StoredAccount {
 
     /// Blindodon PM public key
     pub blindodon_pm_public_key: Option<String>,
+
+    /// Client ID of the app the token was issued to, needed to revoke it
+    #[serde(default)]
+    pub client_id: Option<String>,
+
+    /// Software the account's server runs
+    #[serde(default)]
+    pub software: ServerSoftware,
+
+    /// Features the account's server supports
+    #[serde(default)]
+    pub capabilities: ServerCapabilities,
+
+    /// Whether the server rejected the token, so the account must log in again
+    #[serde(default)]
+    pub needs_reauth: bool,
 }
 
-/// OAuth application registration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Fediverse server software an account lives on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerSoftware {
+    #[default]
+    Mastodon,
+    /// Pleroma and Akkoma
+    Pleroma,
+    Friendica,
+    /// Firefish and Iceshrimp
+    Firefish,
+    Gotosocial,
+    Pixelfed,
+}
+
+impl ServerSoftware {
+    /// Name used in storage and on the wire
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ServerSoftware::Mastodon => "mastodon",
+            ServerSoftware::Pleroma => "pleroma",
+            ServerSoftware::Friendica => "friendica",
+            ServerSoftware::Firefish => "firefish",
+            ServerSoftware::Gotosocial => "gotosocial",
+            ServerSoftware::Pixelfed => "pixelfed",
+        }
+    }
+
+    /// Parse a stored name, treating anything unknown as Mastodon
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "pleroma" => ServerSoftware::Pleroma,
+            "friendica" => ServerSoftware::Friendica,
+            "firefish" => ServerSoftware::Firefish,
+            "gotosocial" => ServerSoftware::Gotosocial,
+            "pixelfed" => ServerSoftware::Pixelfed,
+            _ => ServerSoftware::Mastodon,
+        }
+    }
+
+    /// Whether the token endpoint is known to accept a standard code
+    /// exchange with a PKCE code verifier
+    ///
+    /// Other software gets its token through megalodon, which knows how
+    /// each of them issues tokens.
+    pub fn supports_pkce(self) -> bool {
+        matches!(self, ServerSoftware::Mastodon)
+    }
+
+    /// Features this software supports, so the UI can hide the rest
+    pub fn capabilities(self) -> ServerCapabilities {
+        match self {
+            ServerSoftware::Mastodon => ServerCapabilities {
+                scheduled_posts: true,
+                quotes: false,
+                reactions: false,
+                markdown: false,
+            },
+            ServerSoftware::Pleroma => ServerCapabilities {
+                scheduled_posts: true,
+                quotes: true,
+                reactions: true,
+                markdown: true,
+            },
+            ServerSoftware::Friendica => ServerCapabilities {
+                scheduled_posts: true,
+                quotes: false,
+                reactions: false,
+                markdown: false,
+            },
+            ServerSoftware::Firefish => ServerCapabilities {
+                scheduled_posts: false,
+                quotes: true,
+                reactions: true,
+                markdown: true,
+            },
+            ServerSoftware::Gotosocial => ServerCapabilities {
+                scheduled_posts: false,
+                quotes: false,
+                reactions: false,
+                markdown: true,
+            },
+            ServerSoftware::Pixelfed => ServerCapabilities {
+                scheduled_posts: false,
+                quotes: false,
+                reactions: false,
+                markdown: false,
+            },
+        }
+    }
+}
+
+/// Optional features a server may support
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ServerCapabilities {
+    /// Posts can be scheduled for later
+    pub scheduled_posts: bool,
+    /// Posts can quote other posts
+    pub quotes: bool,
+    /// Posts can get emoji reactions
+    pub reactions: bool,
+    /// Posts can be written in Markdown
+    pub markdown: bool,
+}
+
+impl Default for ServerCapabilities {
+    fn default() -> Self {
+        ServerSoftware::default().capabilities()
+    }
+}
+
+/// OAuth application registration, reused for every login to an instance
+/// with the same redirect URI
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct OAuthApp {
     pub client_id: String,
     pub client_secret: String,
     pub redirect_uri: String,
     pub instance_url: String,
+    /// Software the instance was detected to run
+    #[serde(default)]
+    pub software: ServerSoftware,
 }
 
 /// OAuth authorization request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthRequest {
     /// Instance URL
     pub instance_url: String,
+    /// Have the browser redirect to a local listener instead of showing a
+    /// code to copy; the login then ends with `event.auth_completed`
+    #[serde(default)]
+    pub loopback: bool,
+    /// Stored account to log in again, whose token is then replaced in place
+    #[serde(default)]
+    pub account_id: Option<String>,
 }
 
 /// OAuth authorization response with auth URL
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthResponse {
     /// URL to open in browser for authorization
     pub auth_url: String,
@@ -94,16 +233,18 @@ pub struct AuthResponse {
 }
 
 /// OAuth callback with authorization code
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthCallback {
+    /// Instance URL the flow was started for
+    pub instance_url: String,
     /// Authorization code from callback
     pub code: String,
-    /// State parameter for verification
+    /// State returned by `auth.start` for this login
     pub state: String,
 }
 
 /// Result of successful authentication
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AuthResult {
     /// The stored account
     pub account: StoredAccount,
@@ -112,7 +253,7 @@ pub struct AuthResult {
 }
 
 /// Instance information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct InstanceInfo {
     /// Instance URL
     pub url: String,
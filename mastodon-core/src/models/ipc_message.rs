@@ -16,11 +16,18 @@
 
 //! IPC message models for communication between Rust and C#
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+/// Version of the IPC protocol spoken by this core
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest client protocol version this core still accepts
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
 /// Type of IPC message
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum MessageType {
     Request,
@@ -29,7 +36,7 @@ pub enum MessageType {
 }
 
 /// An IPC message
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct IpcMessage {
     /// Unique message ID (UUID)
     pub id: String,
@@ -102,7 +109,7 @@ impl IpcMessage {
 }
 
 /// Error in an IPC response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct IpcError {
     /// Error code
     pub code: i32,
@@ -143,6 +150,12 @@ pub mod error_codes {
     pub const NETWORK_ERROR: i32 = -1003;
     pub const API_ERROR: i32 = -1004;
     pub const ENCRYPTION_ERROR: i32 = -1005;
+    pub const INCOMPATIBLE_PROTOCOL: i32 = -1006;
+    pub const REQUEST_CANCELLED: i32 = -1007;
+    pub const AUTHENTICATION_FAILED: i32 = -1008;
+    pub const VAULT_LOCKED: i32 = -1009;
+    pub const AUTH_EXPIRED: i32 = -1010;
+    pub const VAULT_KEY_LOST: i32 = -1011;
 }
 
 /// IPC method names
@@ -156,13 +169,26 @@ pub mod methods {
     pub const AUTH_SWITCH_ACCOUNT: &str = "auth.switch_account";
     pub const AUTH_DELETE_ACCOUNT: &str = "auth.delete_account";
 
+    // Vault
+    pub const VAULT_STATUS: &str = "vault.status";
+    pub const VAULT_UNLOCK: &str = "vault.unlock";
+    pub const VAULT_LOCK: &str = "vault.lock";
+    pub const VAULT_SET_PASSPHRASE: &str = "vault.set_passphrase";
+    pub const VAULT_RESET: &str = "vault.reset";
+
+    // Backup
+    pub const BACKUP_EXPORT: &str = "backup.export";
+    pub const BACKUP_IMPORT: &str = "backup.import";
+
     // Settings
     pub const SETTINGS_GET: &str = "settings.get";
     pub const SETTINGS_SET: &str = "settings.set";
+    pub const SETTINGS_DELETE: &str = "settings.delete";
     pub const SETTINGS_GET_ALL: &str = "settings.get_all";
 
     // Timeline
     pub const TIMELINE_GET: &str = "timeline.get";
+    pub const TIMELINE_FILL_GAP: &str = "timeline.fill_gap";
     pub const TIMELINE_STREAM_START: &str = "timeline.stream.start";
     pub const TIMELINE_STREAM_STOP: &str = "timeline.stream.stop";
 
@@ -202,6 +228,10 @@ pub mod methods {
     pub const INSTANCE_GET: &str = "instance.get";
 
     // System
+    pub const SYSTEM_HELLO: &str = "system.hello";
+    pub const SYSTEM_DESCRIBE: &str = "system.describe";
+    pub const SYSTEM_CANCEL: &str = "system.cancel";
+    pub const SYSTEM_AUTHENTICATE: &str = "system.authenticate";
     pub const PING: &str = "ping";
     pub const SHUTDOWN: &str = "shutdown";
 }
@@ -214,6 +244,14 @@ pub mod events {
     pub const NEW_NOTIFICATION: &str = "event.new_notification";
     pub const STREAM_CONNECTED: &str = "event.stream_connected";
     pub const STREAM_DISCONNECTED: &str = "event.stream_disconnected";
+    pub const SHUTDOWN: &str = "event.shutdown";
+    pub const ACCOUNT_SWITCHED: &str = "event.account_switched";
+    pub const POST_ACTION: &str = "event.post_action";
+    pub const SETTING_CHANGED: &str = "event.setting_changed";
+    pub const SETTING_DELETED: &str = "event.setting_deleted";
+    pub const NOTIFICATION_DISMISSED: &str = "event.notification_dismissed";
+    pub const AUTH_COMPLETED: &str = "event.auth_completed";
+    pub const AUTH_EXPIRED: &str = "event.auth_expired";
     pub const RATE_LIMIT_WARNING: &str = "event.rate_limit_warning";
     pub const ERROR: &str = "event.error";
 }
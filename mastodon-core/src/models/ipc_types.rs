@@ -0,0 +1,526 @@
+// Blindodon - An accessibility-first Mastodon client
+// Copyright (C) 2025 Blindodon Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Typed parameters and results of IPC methods and events
+//!
+//! Requests whose parameters or results are plain domain models (such as
+//! `TimelineRequest` or `Post`) use those directly; the types here cover the
+//! remaining methods. All of them are published through `system.describe`.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{Notification, Post, ServerCapabilities, ServerSoftware, StoredAccount, User};
+
+/// Parameters of a method that takes none
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct EmptyParams {}
+
+/// Parameters every method accepts alongside its own
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct CommonParams {
+    /// Stored account to run the request as, instead of the active one
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+/// Generic result of a method that only reports success
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SuccessResponse {
+    /// Always `true`; failures are reported as errors
+    pub success: bool,
+}
+
+impl SuccessResponse {
+    /// Create a successful result
+    pub fn ok() -> Self {
+        Self { success: true }
+    }
+}
+
+// ===== SYSTEM =====
+
+/// Parameters of a `system.hello` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HelloRequest {
+    /// Protocol version the client speaks
+    pub protocol_version: u32,
+    /// Client name, for logging
+    #[serde(default)]
+    pub client_name: Option<String>,
+    /// Client version, for logging
+    #[serde(default)]
+    pub client_version: Option<String>,
+}
+
+/// Result of a `system.hello` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct HelloResponse {
+    /// Protocol version spoken by the core
+    pub protocol_version: u32,
+    /// Oldest client protocol version the core accepts
+    pub min_protocol_version: u32,
+    /// Version of the core binary
+    pub core_version: String,
+    /// Methods the core implements
+    pub methods: Vec<String>,
+    /// Events the core can emit
+    pub events: Vec<String>,
+}
+
+/// Description of a single method in `system.describe`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MethodDescription {
+    /// Method name
+    pub name: String,
+    /// What the method does
+    pub description: String,
+    /// JSON Schema of the parameters
+    pub params: Value,
+    /// JSON Schema of the result
+    pub result: Value,
+}
+
+/// Description of a single event in `system.describe`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct EventDescription {
+    /// Event name
+    pub name: String,
+    /// When the event is emitted
+    pub description: String,
+    /// JSON Schema of the event parameters
+    pub params: Value,
+}
+
+/// Result of a `system.describe` request
+///
+/// Schemas reference shared definitions under `$defs` with `#/$defs/...`
+/// pointers, so the whole document can be fed to a code generator as is.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DescribeResponse {
+    /// Protocol version spoken by the core
+    pub protocol_version: u32,
+    /// Version of the core binary
+    pub core_version: String,
+    /// Every method the core implements
+    pub methods: Vec<MethodDescription>,
+    /// Every event the core can emit
+    pub events: Vec<EventDescription>,
+    /// JSON Schema of the parameters every method accepts alongside its own
+    pub common_params: Value,
+    /// Shared schema definitions
+    #[serde(rename = "$defs")]
+    pub definitions: serde_json::Map<String, Value>,
+}
+
+/// Parameters of a `system.authenticate` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuthenticateRequest {
+    /// Session secret generated by the core at startup
+    pub secret: String,
+}
+
+/// Result of a `system.authenticate` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuthenticateResponse {
+    /// Always `true`; failures are reported as errors and close the connection
+    pub authenticated: bool,
+}
+
+/// Parameters of a `system.cancel` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CancelRequest {
+    /// ID of the request to cancel
+    pub id: String,
+}
+
+/// Result of a `system.cancel` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CancelResponse {
+    /// Whether a matching request was still running and has been cancelled
+    pub cancelled: bool,
+}
+
+/// Result of a `ping` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PingResponse {
+    /// Always `true`
+    pub pong: bool,
+    /// Current time on the core (RFC 3339)
+    pub timestamp: String,
+}
+
+/// Result of a `shutdown` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShutdownResponse {
+    /// Shutdown status
+    pub status: String,
+}
+
+// ===== AUTHENTICATION =====
+
+/// Parameters of an `auth.logout` request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct LogoutRequest {
+    /// Also remove the account from storage, revoking its token first
+    #[serde(default)]
+    pub delete_account: bool,
+}
+
+/// Parameters of requests that target a stored account
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AccountIdRequest {
+    /// Stored account ID (user@instance)
+    pub account_id: String,
+}
+
+/// Summary of a stored account returned after logging in
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AccountSummary {
+    pub id: String,
+    pub instance_url: String,
+    pub username: String,
+    pub display_name: String,
+    pub avatar_url: Option<String>,
+    pub is_default: bool,
+    pub last_used_at: DateTime<Utc>,
+    pub software: ServerSoftware,
+    /// Features the account's server supports
+    pub capabilities: ServerCapabilities,
+}
+
+/// Result of an `auth.callback` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuthCallbackResponse {
+    /// Always `true`; failures are reported as errors
+    pub success: bool,
+    /// The account that was logged in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<AccountSummary>,
+    /// Set when the token was obtained but the user could not be fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_fetching_user: Option<String>,
+}
+
+/// Result of an `auth.get_accounts` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AccountsResponse {
+    /// Whether an account is currently active
+    pub authenticated: bool,
+    /// ID of the active account
+    pub current_account_id: Option<String>,
+    /// All stored accounts
+    pub accounts: Vec<StoredAccount>,
+}
+
+/// Result of an `auth.switch_account` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SwitchAccountResponse {
+    /// Always `true`; failures are reported as errors
+    pub success: bool,
+    /// The account switched to
+    pub account: StoredAccount,
+    /// The account's user profile
+    pub user: User,
+}
+
+// ===== VAULT =====
+
+/// How the key sealing stored tokens is protected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultMode {
+    /// Kept in a key file readable only by the current user
+    KeyFile,
+    /// Derived from a passphrase that must be given after every start
+    Passphrase,
+}
+
+/// Result of the `vault.*` requests
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VaultStatus {
+    pub mode: VaultMode,
+    /// Whether stored tokens are out of reach until `vault.unlock`
+    pub locked: bool,
+    /// Whether the key file is gone, so only `vault.reset` can unlock
+    pub key_lost: bool,
+}
+
+/// Parameters of a `vault.unlock` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VaultUnlockRequest {
+    pub passphrase: String,
+}
+
+/// Parameters of a `vault.set_passphrase` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct VaultSetPassphraseRequest {
+    /// New passphrase, or `None` to go back to a key file
+    pub passphrase: Option<String>,
+}
+
+// ===== BACKUP =====
+
+/// Parameters of a `backup.export` or `backup.import` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BackupRequest {
+    /// Path of the backup file
+    pub path: String,
+    /// Passphrase the backup is encrypted with
+    pub passphrase: String,
+}
+
+/// Result of a `backup.export` or `backup.import` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BackupResponse {
+    /// Path of the backup file
+    pub path: String,
+    /// Number of accounts in the backup
+    pub accounts: usize,
+    /// Number of global and per-account settings in the backup
+    pub settings: usize,
+}
+
+// ===== SETTINGS =====
+
+/// Parameters of a `settings.get` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingGetRequest {
+    /// Setting key
+    pub key: String,
+    /// Account whose value to get, falling back to the global one
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+/// Parameters of a `settings.set` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingSetRequest {
+    /// Setting key
+    pub key: String,
+    /// New value
+    pub value: String,
+    /// Account to set the value for; the global value is set if omitted
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+/// Parameters of a `settings.delete` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingDeleteRequest {
+    /// Setting key
+    pub key: String,
+    /// Account whose own value to delete, so it falls back to the global
+    /// one; the global value is deleted if omitted
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+/// Parameters of a `settings.get_all` request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct SettingsRequest {
+    /// Account whose settings to get, on top of the global ones
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+/// Result of a `settings.get` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingValue {
+    /// Setting key
+    pub key: String,
+    /// Stored value, if any
+    pub value: Option<String>,
+}
+
+/// Result of a `settings.get_all` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingsResponse {
+    /// All stored settings
+    pub settings: HashMap<String, String>,
+}
+
+// ===== TIMELINE STREAMING =====
+
+/// Result of a `timeline.stream.start` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamStartResponse {
+    /// Always `true`; failures are reported as errors
+    pub success: bool,
+    /// Display name of the streamed timeline
+    pub timeline: String,
+    /// Whether the connection was already subscribed to this timeline
+    pub already_streaming: bool,
+}
+
+/// Result of a `timeline.stream.stop` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamStopResponse {
+    /// Always `true`; failures are reported as errors
+    pub success: bool,
+    /// Display name of the timeline
+    pub timeline: String,
+    /// Whether the connection was subscribed to this timeline
+    pub was_streaming: bool,
+}
+
+// ===== POSTS AND NOTIFICATIONS =====
+
+/// Parameters of requests that act on a single post
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PostIdRequest {
+    /// Post ID
+    pub post_id: String,
+}
+
+/// Parameters of a `notifications.dismiss` request
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct NotificationIdRequest {
+    /// Notification ID
+    pub notification_id: String,
+}
+
+// ===== EVENTS =====
+
+/// Parameters of `event.new_post` and `event.post_updated`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PostEvent {
+    /// Stored account the event belongs to, if known
+    pub account_id: Option<String>,
+    /// Display name of the timeline the post arrived on
+    pub timeline: String,
+    /// The new or updated post
+    pub post: Post,
+}
+
+/// Parameters of `event.post_deleted`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PostDeletedEvent {
+    /// Stored account the event belongs to, if known
+    pub account_id: Option<String>,
+    /// Display name of the timeline
+    pub timeline: String,
+    /// ID of the deleted post
+    pub post_id: String,
+}
+
+/// Parameters of `event.new_notification`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct NotificationEvent {
+    /// Stored account the event belongs to, if known
+    pub account_id: Option<String>,
+    /// The new notification
+    pub notification: Notification,
+}
+
+/// Parameters of `event.stream_connected`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamConnectedEvent {
+    /// Stored account the event belongs to, if known
+    pub account_id: Option<String>,
+    /// Display name of the timeline
+    pub timeline: String,
+}
+
+/// Parameters of `event.stream_disconnected`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamDisconnectedEvent {
+    /// Stored account the event belongs to, if known
+    pub account_id: Option<String>,
+    /// Display name of the timeline
+    pub timeline: String,
+    /// Why the stream ended
+    pub reason: String,
+}
+
+/// Parameters of `event.shutdown`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShutdownEvent {
+    /// Why the core is shutting down
+    pub reason: String,
+}
+
+/// Parameters of `event.account_switched`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AccountSwitchedEvent {
+    /// ID of the now active account, or `None` after logging out
+    pub account_id: Option<String>,
+}
+
+/// Parameters of `event.post_action`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PostActionEvent {
+    /// Account that acted, if known
+    pub account_id: Option<String>,
+    /// `boost`, `unboost`, `favourite` or `unfavourite`
+    pub action: String,
+    /// The post as returned by the server after the action
+    pub post: Post,
+}
+
+/// Parameters of `event.setting_changed`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingChangedEvent {
+    /// Setting key
+    pub key: String,
+    /// New value
+    pub value: String,
+    /// Account the value was set for, or `None` for the global value
+    pub account_id: Option<String>,
+}
+
+/// Parameters of `event.setting_deleted`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingDeletedEvent {
+    /// Setting key
+    pub key: String,
+    /// Account whose value was deleted, or `None` for the global value
+    pub account_id: Option<String>,
+    /// Value now in effect for the account, or the global one, if any
+    pub value: Option<String>,
+}
+
+/// Parameters of `event.notification_dismissed`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct NotificationDismissedEvent {
+    /// Account whose notifications were dismissed, if known
+    pub account_id: Option<String>,
+    /// ID of the dismissed notification, or `None` if all were cleared
+    pub notification_id: Option<String>,
+}
+
+/// Parameters of `event.auth_completed`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuthCompletedEvent {
+    /// State returned by `auth.start` for this login
+    pub state: String,
+    /// Whether the login succeeded
+    pub success: bool,
+    /// The account logged in to, if its details could be fetched
+    pub account: Option<AccountSummary>,
+    /// Why the login failed
+    pub error: Option<String>,
+}
+
+/// Parameters of `event.auth_expired`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuthExpiredEvent {
+    /// Account whose token the server rejected
+    pub account_id: String,
+}
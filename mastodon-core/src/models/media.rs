@@ -16,10 +16,11 @@
 
 //! Media attachment model
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Type of media attachment
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum MediaType {
     Image,
@@ -30,7 +31,7 @@ pub enum MediaType {
 }
 
 /// A media attachment on a post
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MediaAttachment {
     /// Unique identifier
     pub id: String,
@@ -59,7 +60,7 @@ pub struct MediaAttachment {
 }
 
 /// Metadata about a media attachment
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MediaMeta {
     pub original: Option<MediaDimensions>,
     pub small: Option<MediaDimensions>,
@@ -73,7 +74,7 @@ pub struct MediaMeta {
 }
 
 /// Dimensions of a media file
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MediaDimensions {
     pub width: Option<u32>,
     pub height: Option<u32>,
@@ -85,14 +86,14 @@ pub struct MediaDimensions {
 }
 
 /// Focus point for cropping
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MediaFocus {
     pub x: f64,
     pub y: f64,
 }
 
 /// Request to upload media
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct MediaUploadRequest {
     /// Path to the file to upload
     pub file_path: String,
@@ -24,6 +24,7 @@ mod user;
 mod notification;
 mod timeline;
 mod ipc_message;
+mod ipc_types;
 mod account;
 mod media;
 
@@ -32,5 +33,6 @@ pub use user::*;
 pub use notification::*;
 pub use timeline::*;
 pub use ipc_message::*;
+pub use ipc_types::*;
 pub use account::*;
 pub use media::*;
@@ -17,12 +17,13 @@
 //! Notification model
 
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::{Post, User};
 
 /// Type of notification
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum NotificationType {
     /// Someone mentioned you
@@ -51,7 +52,7 @@ pub enum NotificationType {
 }
 
 /// A notification from Mastodon
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Notification {
     /// Unique identifier
     pub id: String,
@@ -75,7 +76,7 @@ pub struct Notification {
 }
 
 /// Notification filter settings
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct NotificationFilter {
     /// Exclude mentions
     pub exclude_mentions: bool,
@@ -94,7 +95,7 @@ pub struct NotificationFilter {
 }
 
 /// Request for fetching notifications
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
 pub struct NotificationRequest {
     /// Return results older than this ID
     pub max_id: Option<String>,
@@ -111,7 +112,7 @@ pub struct NotificationRequest {
 }
 
 /// Response from fetching notifications
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NotificationResponse {
     /// List of notifications
     pub notifications: Vec<Notification>,
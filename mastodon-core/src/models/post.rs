@@ -17,12 +17,13 @@
 //! Post model representing a Mastodon status/toot
 
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::{MediaAttachment, User};
 
 /// Visibility level for a post
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Visibility {
     Public,
@@ -38,7 +39,7 @@ impl Default for Visibility {
 }
 
 /// A poll attached to a post
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Poll {
     pub id: String,
     pub expires_at: Option<DateTime<Utc>>,
@@ -52,21 +53,21 @@ pub struct Poll {
 }
 
 /// A single option in a poll
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PollOption {
     pub title: String,
     pub votes_count: Option<u64>,
 }
 
 /// Application that posted the status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Application {
     pub name: String,
     pub website: Option<String>,
 }
 
 /// A Mastodon post/status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Post {
     /// Unique identifier for this post
     pub id: String,
@@ -161,14 +162,14 @@ pub struct Post {
 }
 
 /// A hashtag mentioned in a post
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Tag {
     pub name: String,
     pub url: String,
 }
 
 /// An account mentioned in a post
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Mention {
     pub id: String,
     pub username: String,
@@ -177,7 +178,7 @@ pub struct Mention {
 }
 
 /// A custom emoji
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct CustomEmoji {
     pub shortcode: String,
     pub url: String,
@@ -187,7 +188,7 @@ pub struct CustomEmoji {
 }
 
 /// Request to create a new post
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NewPost {
     pub content: String,
     pub spoiler_text: Option<String>,
@@ -204,7 +205,7 @@ pub struct NewPost {
 }
 
 /// Request to create a poll
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NewPoll {
     pub options: Vec<String>,
     pub expires_in: u64,
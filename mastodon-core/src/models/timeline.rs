@@ -16,10 +16,11 @@
 
 //! Timeline model and configuration
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Type of timeline
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum TimelineType {
     /// Home timeline (posts from followed accounts)
@@ -66,10 +67,25 @@ impl TimelineType {
             TimelineType::Search { query } => format!("Search: {}", query),
         }
     }
+
+    /// Key this timeline is stored under in the cache
+    ///
+    /// `None` for timelines that are not cached, because they are not
+    /// ordered by post ID or cannot be paged through with `min_id`.
+    pub fn cache_key(&self) -> Option<String> {
+        match self {
+            TimelineType::Home => Some("home".to_string()),
+            TimelineType::Local => Some("local".to_string()),
+            TimelineType::Federated => Some("federated".to_string()),
+            TimelineType::Hashtag { tag } => Some(format!("hashtag:{}", tag.to_lowercase())),
+            TimelineType::List { list_id } => Some(format!("list:{}", list_id)),
+            _ => None,
+        }
+    }
 }
 
 /// Settings for a specific timeline
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TimelineSettings {
     /// Unique identifier for this timeline instance
     pub id: String,
@@ -128,7 +144,7 @@ impl Default for TimelineSettings {
 }
 
 /// Display density for timeline
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum DisplayDensity {
     Compact,
@@ -137,8 +153,11 @@ pub enum DisplayDensity {
     Comfortable,
 }
 
+/// Number of posts in a timeline page unless the request says otherwise
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+
 /// Request to fetch a timeline
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TimelineRequest {
     /// Type of timeline to fetch
     pub timeline_type: TimelineType,
@@ -150,10 +169,20 @@ pub struct TimelineRequest {
     pub since_id: Option<String>,
     /// Return posts immediately newer than this ID
     pub min_id: Option<String>,
+    /// Serve the page from the local cache without contacting the server
+    #[serde(default)]
+    pub cached: bool,
+}
+
+impl TimelineRequest {
+    /// Number of posts to return
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
 }
 
 /// Response containing timeline posts
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct TimelineResponse {
     /// The posts in the timeline
     pub posts: Vec<super::Post>,
@@ -163,4 +192,43 @@ pub struct TimelineResponse {
     pub min_id: Option<String>,
     /// Whether there are more posts available
     pub has_more: bool,
+    /// Gaps next to the returned posts, on timelines kept in the cache
+    #[serde(default)]
+    pub gaps: Vec<TimelineGap>,
+}
+
+/// Stretch of a cached timeline whose posts have not been loaded
+///
+/// Sits between two posts whose continuity is unknown: posts older than
+/// `newer_id` and newer than `older_id` may be missing.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+pub struct TimelineGap {
+    /// ID of the post just above the gap
+    pub newer_id: String,
+    /// ID of the post just below the gap
+    pub older_id: String,
+}
+
+/// Request to load the posts missing from a gap
+///
+/// Loads the oldest missing posts first, so the timeline can be read in
+/// chronological order. Whatever is still missing is reported as a
+/// smaller gap in the response.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FillGapRequest {
+    /// Timeline the gap is in
+    pub timeline_type: TimelineType,
+    /// ID of the post just above the gap
+    pub newer_id: String,
+    /// ID of the post just below the gap
+    pub older_id: String,
+    /// Maximum number of posts to load
+    pub limit: Option<u32>,
+}
+
+/// Request to start or stop streaming a timeline
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StreamRequest {
+    /// Type of timeline to stream
+    pub timeline_type: TimelineType,
 }
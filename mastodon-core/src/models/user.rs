@@ -17,12 +17,13 @@
 //! User model representing a Mastodon account
 
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use super::CustomEmoji;
 
 /// A Mastodon user/account
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct User {
     /// Unique identifier
     pub id: String,
@@ -123,7 +124,7 @@ pub struct User {
 }
 
 /// A custom field on a user's profile
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ProfileField {
     pub name: String,
     pub value: String,
@@ -131,7 +132,7 @@ pub struct ProfileField {
 }
 
 /// Relationship between the current user and another account
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Relationship {
     pub id: String,
     pub following: bool,
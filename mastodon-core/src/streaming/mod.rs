@@ -20,13 +20,16 @@
 //! real-time timeline updates.
 
 use anyhow::Result;
-use megalodon::{streaming::Message, SNS};
-use tokio::sync::{broadcast, mpsc};
+use megalodon::streaming::Message;
+use tokio::sync::{mpsc, watch};
 use tracing::{debug, info, warn};
 
 use crate::log_stream;
-use crate::models::{events, IpcMessage, Post, TimelineType};
-use crate::api::convert_status;
+use crate::models::{
+    events, IpcMessage, NotificationEvent, Post, PostDeletedEvent, PostEvent,
+    ServerSoftware, StreamConnectedEvent, StreamDisconnectedEvent, TimelineType,
+};
+use crate::api::{convert_notification, convert_status, sns_for};
 
 /// Event from the streaming connection
 #[derive(Debug, Clone)]
@@ -45,27 +48,36 @@ pub enum StreamEvent {
 
 /// Streaming connection manager
 pub struct StreamManager {
+    /// Stored account the stream belongs to, if known
+    account_id: Option<String>,
     /// Access token
     access_token: String,
     /// Instance URL
     instance_url: String,
-    /// Shutdown signal sender
-    shutdown_tx: broadcast::Sender<()>,
+    /// Software the instance runs
+    software: ServerSoftware,
+    /// Shutdown signal; set to `true` once the streams should stop
+    shutdown_tx: watch::Sender<bool>,
 }
 
 impl StreamManager {
     /// Create a new stream manager
-    pub fn new(instance_url: &str, access_token: &str) -> Self {
-        let (shutdown_tx, _) = broadcast::channel(1);
+    pub fn new(account_id: Option<&str>, instance_url: &str, access_token: &str, software: ServerSoftware) -> Self {
+        let (shutdown_tx, _) = watch::channel(false);
 
         Self {
+            account_id: account_id.map(str::to_string),
             access_token: access_token.to_string(),
             instance_url: instance_url.to_string(),
+            software,
             shutdown_tx,
         }
     }
 
     /// Start streaming for a timeline
+    ///
+    /// Runs until [`StreamManager::stop_all`] is called or the server closes
+    /// the stream.
     pub async fn start_stream(
         &self,
         timeline_type: TimelineType,
@@ -75,7 +87,7 @@ impl StreamManager {
         info!("Starting stream for timeline: {}", timeline_name);
 
         let client = megalodon::generator(
-            SNS::Mastodon,
+            sns_for(self.software),
             self.instance_url.clone(),
             Some(self.access_token.clone()),
             None,
@@ -101,142 +113,128 @@ impl StreamManager {
         let _ = event_tx
             .send(IpcMessage::event(
                 events::STREAM_CONNECTED,
-                serde_json::json!({ "timeline": timeline_name }),
+                serde_json::to_value(StreamConnectedEvent {
+                    account_id: self.account_id.clone(),
+                    timeline: timeline_name.clone(),
+                })?,
             ))
             .await;
 
         // Use the listen method from megalodon's Streaming trait
         let event_tx_clone = event_tx.clone();
         let timeline_name_clone = timeline_name.clone();
+        let account_id = self.account_id.clone();
 
         // Spawn listening task
-        let listen_handle = tokio::spawn(async move {
+        let mut listen_handle = tokio::spawn(async move {
             stream.listen(Box::new(move |message| {
                 let event_tx = event_tx_clone.clone();
                 let timeline_name = timeline_name_clone.clone();
+                let account_id = account_id.clone();
 
                 Box::pin(async move {
-                    match message {
-                        Message::Update(status) => {
-                            let post = convert_status(&status);
-                            let _ = event_tx
-                                .send(IpcMessage::event(
-                                    events::NEW_POST,
-                                    serde_json::json!({
-                                        "timeline": timeline_name,
-                                        "post": post
-                                    }),
-                                ))
-                                .await;
-                        }
-                        Message::Delete(id) => {
-                            let _ = event_tx
-                                .send(IpcMessage::event(
-                                    events::POST_DELETED,
-                                    serde_json::json!({
-                                        "timeline": timeline_name,
-                                        "post_id": id
-                                    }),
-                                ))
-                                .await;
-                        }
-                        Message::StatusUpdate(status) => {
-                            let post = convert_status(&status);
-                            let _ = event_tx
-                                .send(IpcMessage::event(
-                                    events::POST_UPDATED,
-                                    serde_json::json!({
-                                        "timeline": timeline_name,
-                                        "post": post
-                                    }),
-                                ))
-                                .await;
-                        }
-                        _ => {
-                            debug!("Unhandled stream message type");
-                        }
+                    if let Err(e) = forward_message(message, account_id, &timeline_name, &event_tx).await {
+                        debug!("Dropping stream message, client is gone: {}", e);
                     }
                 })
             })).await;
         });
 
-        // Wait for shutdown signal
-        let _ = shutdown_rx.recv().await;
-        info!("Shutdown signal received, stopping stream");
-        listen_handle.abort();
+        // Wait for a shutdown signal or for the server to close the stream
+        let reason = tokio::select! {
+            _ = shutdown_rx.wait_for(|stop| *stop) => {
+                info!("Shutdown signal received, stopping stream");
+                listen_handle.abort();
+                "stopped"
+            }
+            _ = &mut listen_handle => "closed by server",
+        };
+
+        log_stream!(disconnected, &timeline_name, reason);
+        let _ = event_tx
+            .send(IpcMessage::event(
+                events::STREAM_DISCONNECTED,
+                serde_json::to_value(StreamDisconnectedEvent {
+                    account_id: self.account_id.clone(),
+                    timeline: timeline_name.clone(),
+                    reason: reason.to_string(),
+                })?,
+            ))
+            .await;
 
         Ok(())
     }
 
-    /// Handle a streaming message
-    async fn handle_message(
-        &self,
-        message: Message,
-        timeline_type: &TimelineType,
-        event_tx: &mpsc::Sender<IpcMessage>,
-    ) -> Result<()> {
-        let timeline_name = timeline_type.display_name();
+    /// Stop all streaming connections
+    pub fn stop_all(&self) {
+        self.shutdown_tx.send_replace(true);
+    }
+}
 
-        match message {
-            Message::Update(status) => {
-                log_stream!(message, &timeline_name, "update");
-                let post = convert_status(&status);
-                event_tx
-                    .send(IpcMessage::event(
-                        events::NEW_POST,
-                        serde_json::json!({
-                            "timeline": timeline_name,
-                            "post": post
-                        }),
-                    ))
-                    .await?;
-            }
-            Message::Notification(notification) => {
-                log_stream!(message, &timeline_name, "notification");
+/// Forward a streaming message to the client as an IPC event
+async fn forward_message(
+    message: Message,
+    account_id: Option<String>,
+    timeline_name: &str,
+    event_tx: &mpsc::Sender<IpcMessage>,
+) -> Result<()> {
+    match message {
+        Message::Update(status) => {
+            log_stream!(message, timeline_name, "update");
+            let post = convert_status(&status);
+            event_tx
+                .send(IpcMessage::event(
+                    events::NEW_POST,
+                    serde_json::to_value(PostEvent {
+                        account_id,
+                        timeline: timeline_name.to_string(),
+                        post,
+                    })?,
+                ))
+                .await?;
+        }
+        Message::Notification(notification) => {
+            log_stream!(message, timeline_name, "notification");
+            if let Some(notification) = convert_notification(&notification) {
                 event_tx
                     .send(IpcMessage::event(
                         events::NEW_NOTIFICATION,
-                        serde_json::json!({
-                            "notification": notification
-                        }),
+                        serde_json::to_value(NotificationEvent { account_id, notification })?,
                     ))
                     .await?;
             }
-            Message::Delete(id) => {
-                log_stream!(message, &timeline_name, "delete");
-                event_tx
-                    .send(IpcMessage::event(
-                        events::POST_DELETED,
-                        serde_json::json!({
-                            "timeline": timeline_name,
-                            "post_id": id
-                        }),
-                    ))
-                    .await?;
-            }
-            Message::StatusUpdate(status) => {
-                log_stream!(message, &timeline_name, "status_update");
-                let post = convert_status(&status);
-                event_tx
-                    .send(IpcMessage::event(
-                        events::POST_UPDATED,
-                        serde_json::json!({
-                            "timeline": timeline_name,
-                            "post": post
-                        }),
-                    ))
-                    .await?;
-            }
-            _ => {
-                debug!("Unhandled stream message type");
-            }
         }
-
-        Ok(())
+        Message::Delete(id) => {
+            log_stream!(message, timeline_name, "delete");
+            event_tx
+                .send(IpcMessage::event(
+                    events::POST_DELETED,
+                    serde_json::to_value(PostDeletedEvent {
+                        account_id,
+                        timeline: timeline_name.to_string(),
+                        post_id: id,
+                    })?,
+                ))
+                .await?;
+        }
+        Message::StatusUpdate(status) => {
+            log_stream!(message, timeline_name, "status_update");
+            let post = convert_status(&status);
+            event_tx
+                .send(IpcMessage::event(
+                    events::POST_UPDATED,
+                    serde_json::to_value(PostEvent {
+                        account_id,
+                        timeline: timeline_name.to_string(),
+                        post,
+                    })?,
+                ))
+                .await?;
+        }
+        _ => {
+            debug!("Unhandled stream message type");
+        }
     }
 
-    /// Stop all streaming connections
-    pub fn stop_all(&self) {
-        let _ = self.shutdown_tx.send(());
-    }
+    Ok(())
 }